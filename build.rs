@@ -0,0 +1,56 @@
+//! Generate LAYOUTS, COMBOS & LEADER_KEY_COMBINATIONS (included by src/layouts.rs) from the
+//! keymap file, with the parser & the checks of the keymap tool (tools/keymap).
+use std::{env, fs, path::Path, process::exit};
+
+#[allow(dead_code)]
+#[path = "tools/keymap/src/codes.rs"]
+mod codes;
+#[allow(dead_code)]
+#[path = "tools/keymap/src/rust.rs"]
+mod rust;
+#[allow(dead_code)]
+#[path = "tools/keymap/src/text.rs"]
+mod text;
+
+const KEYMAP: &str = "chew.keymap";
+
+fn main() {
+    for path in [
+        KEYMAP,
+        "tools/keymap/src",
+        "src/software/keys.rs",
+        "src/options.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+
+    match tables() {
+        Ok((tables, bytes)) => {
+            let out = Path::new(&env::var("OUT_DIR").unwrap()).to_path_buf();
+            fs::write(out.join("layouts.rs"), tables).unwrap();
+            fs::write(out.join("keymap.bin"), bytes).unwrap();
+        }
+        Err(e) => {
+            // The warning is shown by cargo above the error, e.g. chew.keymap: line 12: unknown key 'Foo'
+            println!("cargo:warning={}", e);
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// The Rust tables & the binary keymap as uploaded by the tool (decoded by the host tests of
+/// src/software/keymap.rs, so the tool & the firmware agree on the format).
+fn tables() -> Result<(String, Vec<u8>), String> {
+    let source = fs::read_to_string(KEYMAP).map_err(|e| format!("{}: {}", KEYMAP, e))?;
+    let codes = codes::Codes::new().map_err(|e| e.to_string())?;
+
+    text::parse(&source, codes.nb_keys)
+        .and_then(|keymap| {
+            Ok((
+                rust::tables(&codes, &keymap, KEYMAP)?,
+                codes.encode(&keymap)?,
+            ))
+        })
+        .map_err(|e| format!("{}: {}", KEYMAP, e))
+}
@@ -1,6 +1,14 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 16K - 32K - 16K - 96K
+    /* VIA macros storage (see FLASH_VIA_* in options.rs) */
+    VIA : ORIGIN = 0x101D8000, LENGTH = 16K
+    /* Runtime keymap storage (see FLASH_KEYMAP_* in options.rs) */
+    KEYMAP : ORIGIN = 0x101DC000, LENGTH = 32K
+    /* Settings storage (see FLASH_SETTINGS_* in options.rs) */
+    SETTINGS : ORIGIN = 0x101E4000, LENGTH = 16K
+    /* Dynamic macros storage (see FLASH_DYNMAC_* in options.rs) */
+    DYNMAC : ORIGIN = 0x101E8000, LENGTH = 96K
     RAM   : ORIGIN = 0x20000000, LENGTH = 256K
 }
 
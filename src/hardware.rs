@@ -1,4 +1,5 @@
 pub mod buzzer;
+pub mod flash;
 pub mod gpios;
 pub mod led;
 pub mod matrix;
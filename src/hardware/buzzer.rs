@@ -21,6 +21,10 @@ const B4_FLAT: u16 = (XOSC_CRYSTAL_FREQ as f32 / 466.0) as u16;
 const B4: u16 = (XOSC_CRYSTAL_FREQ as f32 / 494.0) as u16;
 const TIME: u32 = 100;
 
+const PITCHES: [u16; 12] = [
+    C4, C4_SHARP, D4, E4_FLAT, E4, F4, F4_SHARP, G4, A4_FLAT, A4, B4_FLAT, B4,
+];
+
 #[allow(dead_code)]
 pub enum Song {
     JingleBells,
@@ -56,6 +60,8 @@ pub enum Song {
     AlertD,
     AlertE,
     AlertF,
+
+    Midi(u8),
 }
 
 pub enum Side {
@@ -64,6 +70,11 @@ pub enum Side {
     Both,
 }
 
+/// Pwm top of a midi note (60 is C4), the lower octaves are played in the fourth one.
+fn pitch(note: u8) -> u16 {
+    PITCHES[note as usize % 12] >> (note / 12).saturating_sub(5)
+}
+
 pub struct Note {
     frequency: u16,
     ticks_length: u32,
@@ -357,6 +368,10 @@ impl<I: SliceId> Buzzer<I> {
                     new_sheet_music .push_back((Note::new(0            , TIME / 5 ), Side::Right)).ok();
                     new_sheet_music .push_back((Note::new(F4           , TIME / 2 ), Side::Both )).ok();
                 }
+
+                Song::Midi(note) => {
+                    new_sheet_music .push_back((Note::new(pitch(note)  , TIME     ), Side::Both )).ok();
+                }
             }
 
             self.add(new_sheet_music, &side, reverse);
@@ -0,0 +1,63 @@
+#[cfg(target_arch = "arm")]
+use rp2040_flash::flash as bootrom;
+
+/// The bootrom only exists on the board, the host tests use RamFlash instead.
+#[cfg(not(target_arch = "arm"))]
+mod bootrom {
+    pub unsafe fn flash_range_erase(_address: u32, _count: u32, _use_boot2: bool) {
+        unreachable!()
+    }
+    pub unsafe fn flash_range_program(_address: u32, _data: &[u8], _use_boot2: bool) {
+        unreachable!()
+    }
+}
+
+use crate::software::storage::{Flash, PAGE_SIZE, SECTOR_SIZE};
+
+// Flash is mapped here by the XIP
+const XIP_BASE: usize = 0x1000_0000;
+
+/// Region of the RP2040 flash which is reserved in memory.x.
+/// Erasing and programming are done by the bootrom functions with interrupts disabled,
+/// the XIP is off meanwhile so the keyboard freezes for a few milliseconds per sector.
+pub struct Rp2040Flash {
+    start: usize,
+    size: usize,
+}
+
+impl Rp2040Flash {
+    /// Start is the offset from the beginning of the flash (not the XIP address).
+    pub fn new(start: u32, size: usize) -> Self {
+        Rp2040Flash {
+            start: start as usize,
+            size,
+        }
+    }
+}
+
+impl Flash for Rp2040Flash {
+    fn capacity(&self) -> usize {
+        self.size
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        let address = (XIP_BASE + self.start + offset) as *const u8;
+
+        // Safety: the region is reserved and mapped by the XIP
+        unsafe { core::ptr::copy_nonoverlapping(address, buffer.as_mut_ptr(), buffer.len()) };
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        // Safety: interrupts are disabled and the core 1 is not used
+        cortex_m::interrupt::free(|_| unsafe {
+            bootrom::flash_range_erase((self.start + offset) as u32, SECTOR_SIZE as u32, true);
+        });
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]) {
+        // Safety: interrupts are disabled and the core 1 is not used
+        cortex_m::interrupt::free(|_| unsafe {
+            bootrom::flash_range_program((self.start + offset) as u32, data, true);
+        });
+    }
+}
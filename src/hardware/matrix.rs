@@ -47,4 +47,8 @@ impl Matrix {
     pub fn is_active(&self, index: usize) -> bool {
         self.current.contains(&index)
     }
+
+    pub fn is_released(&self) -> bool {
+        self.current.is_empty()
+    }
 }
@@ -1,139 +1,17 @@
-use crate::software::keys::KC::HomeRow as HOME;
-use crate::{
-    options::NB_KEYS,
-    software::keys::KC,
-    software::keys::KC::{Alt, Ctl, Gui, Sft},
-};
-
-#[rustfmt::skip]
-pub const LAYOUTS: [[KC; NB_KEYS]; 6] = [[
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-         KC::Q       ,       KC::C       ,       KC::O       ,       KC::P       ,       KC::V       ,                KC::J       ,       KC::M       ,       KC::D       ,       KC::Y       ,       KC::W       ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-   HOME(&Alt,&KC::A) , HOME(&Gui,&KC::S) , HOME(&Ctl,&KC::E) , HOME(&Sft,&KC::N) ,       KC::F       ,                KC::L       , HOME(&Sft,&KC::R) , HOME(&Ctl,&KC::T) , HOME(&Gui,&KC::I) , HOME(&Alt,&KC::U) ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-         KC::Z       ,       KC::X       ,    KC::EAcute     ,       KC::B       ,                                                        KC::H       ,       KC::G       ,       KC::K       ,   KC::LayDead(4)  ,
-//-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-                                             KC::Layout(3)   ,     KC::Space     ,      KC::Esc      ,              KC::Enter     ,    KC::Layout(1)  ,    KC::Layout(2)
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-],
-[
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-      KC::Tilde      ,   KC::LowerThan   ,     KC::Equal     ,  KC::GreaterThan  ,    KC::Exclaim    ,              KC::Pound     ,      KC::Num4     ,      KC::Num5     ,      KC::Num6     ,      KC::Hash     ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-    KC::Backslash    ,     KC::Plus      ,    KC::Asterix    ,     KC::Slash     ,    KC::Question   ,              KC::Euro      ,      KC::Num1     ,      KC::Num2     ,      KC::Num3     ,      KC::Num0     ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-    KC::Diameter     ,   KC::Circumflex  ,   KC::Percentage  ,     KC::Dollar    ,                                                       KC::Num7     ,      KC::Num8     ,      KC::Num9     ,      KC::Degre    ,
-//-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-
-                                               KC::Minus     ,      KC::Dot      ,     KC::Comma     ,               KC::None     ,      KC::None     ,      KC::None
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-],
-[
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-        KC::At       ,  KC::LeftBracket  ,   KC::Ampersand   , KC::RightBracket  ,     KC::None      ,            KC::LeaderKey   ,      KC::Home     ,     KC::PageUp    ,    KC::PageDown   ,      KC::End      ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-    KC::DoubleQuote  ,  KC::LeftParent   ,    KC::Quote      ,  KC::RightParent  ,     KC::Grave     ,             KC::CapLock    ,      KC::Left     ,      KC::Down     ,       KC::Up      ,     KC::Right     ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-       KC::None      ,  KC::LeftCurly    ,    KC::Pipe       ,  KC::RightCurly   ,                                                       KC::Del      ,      KC::Tab      ,      KC::STab     ,   KC::BackSpace   ,
-//-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-                                            KC::Underscore   ,     KC::Colon     ,   KC::SemiColon   ,               KC::None     ,      KC::None     ,      KC::None
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-],
-[
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-   KC::MouseWheelLeft,  KC::MouseWheelUp , KC::MouseWheelDown,KC::MouseWheelRight,  KC::DynMacRecord ,              KC::None      ,      KC::None     ,      KC::None     ,      KC::None     ,      KC::None     ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-     KC::MouseLeft   ,   KC::MouseUp     ,   KC::MouseDown   ,  KC::MouseRight   ,    KC::DynMacGo   ,              KC::None      ,  KC::MouseSpeed1  ,  KC::MouseSpeed2  ,  KC::MouseSpeed3  ,  KC::MouseSpeed4  ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-        KC::Del      ,     KC::None      ,      KC::None     ,   KC::BackSpace   ,                                                       KC::None     ,      KC::None     ,      KC::None     ,      KC::None     ,
-//-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-                                                KC::None     ,      KC::None     ,     KC::None      ,           KC::MouseBtLeft  , KC::MouseBtMiddle ,  KC::MouseBtRight
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-],
-[
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-      KC::ACircum    ,   KC::CCedilla    ,    KC::ECircum    ,    KC::OCircum    ,     KC::None      ,              KC::None      ,      KC::None     ,     KC::YCircum   ,    KC::ICircum    ,     KC::UCircum   ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-       KC::AGrave    ,      KC::OE       ,    KC::EGrave     ,    KC::OGrave     ,   KC::GuillemetL  ,            KC::GuillemetD  ,     KC::EAcute    ,     KC::YGrave    ,    KC::IGrave     ,     KC::UGrave    ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-       KC::ADiaer    ,      KC::AE       ,    KC::EDiaer     ,    KC::ODiaer     ,                                                       KC::None     ,     KC::YDiaer    ,    KC::IDiaer     ,     KC::UDiaer    ,
-//-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-                                               KC::None      ,     KC::None      ,     KC::Esc       ,              KC::None      ,      KC::None     ,      KC::None
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-],
-[
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-        KC::None     ,      KC::F16      ,      KC::F15      ,      KC::F14      ,     KC::None      ,              KC::None      ,       KC::F4      ,      KC::F5       ,      KC::F6       ,     KC::None      ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-        KC::F20      ,      KC::F13      ,      KC::F12      ,      KC::F11      ,     KC::None      ,              KC::None      ,       KC::F1      ,      KC::F2       ,      KC::F3       ,     KC::F10       ,
-//-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-        KC::None     ,      KC::F17      ,      KC::F18      ,      KC::F17      ,                                                        KC::F7      ,      KC::F8       ,      KC::F9       ,     KC::None      ,
-//-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-                                               KC::None      ,     KC::None      ,     KC::None      ,               KC::None     ,      KC::None     ,      KC::None
-//                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-]
-// [
-// //-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-//         KC::None     ,     KC::None      ,     KC::None      ,     KC::None      ,     KC::None      ,              KC::None      ,      KC::None     ,     KC::None      ,     KC::None      ,     KC::None      ,
-// //-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-//         KC::None     ,     KC::None      ,     KC::None      ,     KC::None      ,     KC::None      ,              KC::None      ,      KC::None     ,     KC::None      ,     KC::None      ,     KC::None      ,
-// //-------------------|-------------------|-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|-------------------|-------------------|
-//         KC::None     ,     KC::None      ,     KC::None      ,     KC::None      ,                                                       KC::None     ,     KC::None      ,     KC::None      ,     KC::None      ,
-// //-------------------|-------------------|-------------------|-------------------|                                                |-------------------|-------------------|-------------------|-------------------|
-// //                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-//                                                KC::None      ,     KC::None      ,     KC::None      ,               KC::None     ,      KC::None     ,      KC::None
-// //                                       |-------------------|-------------------|-------------------|        |-------------------|-------------------|-------------------|
-// ]
-];
+use crate::{options::NB_KEYS, software::keys::KC};
 
 // --------------------------------------------------------------------------------------
-// LEADER KEY ---------------------------------------------------------------------------
-#[rustfmt::skip]
-pub const LEADER_KEY_COMBINATIONS: [([KC; 3], KC); 23] = [
-    ([KC::F, KC::L, KC::None], KC::MacroGit),
-    ([KC::M, KC::A, KC::None], KC::MacroMail),
-    ([KC::M, KC::E, KC::None], KC::MacroMailEnd),
-    ([KC::M, KC::S, KC::None], KC::MacroMailShort),
-    ([KC::H, KC::T, KC::None], KC::MacroHTTPS),
-    ([KC::D, KC::O, KC::T],    KC::MacroDotfiles),
-    ([KC::N, KC::I, KC::X],    KC::MacroNixOS),
-
-    ([KC::D, KC::I, KC::A], KC::Diameter),
-    ([KC::D, KC::E, KC::G], KC::Degre),
-
-    ([KC::C, KC::O, KC::P], KC::Copyright),
-    ([KC::R, KC::E, KC::G], KC::Registered),
-    ([KC::E, KC::X, KC::Num1], KC::ExpOne),
-    ([KC::E, KC::X, KC::Num2], KC::ExpTwo),
-    ([KC::E, KC::X, KC::Num3], KC::ExpThree),
-    ([KC::B, KC::E, KC::T], KC::Beta),
-
-    ([KC::P, KC::I, KC::L], KC::Pilcrow),
-    ([KC::M, KC::U, KC::L], KC::Multi),
-    ([KC::D, KC::I, KC::V], KC::Div),
-
-    ([KC::Num1, KC::Num4, KC::None], KC::Quarter),
-    ([KC::Num1, KC::Num2, KC::None], KC::Half),
-    ([KC::Num3, KC::Num4, KC::None], KC::ThreeQuarter),
-
-    ([KC::Y, KC::E, KC::N], KC::Yen),
-
-    ([KC::B, KC::U, KC::Z], KC::ToggleBuzzer),
-];
+// LAYOUTS, COMBOS & LEADER KEY ---------------------------------------------------------
+// Generated by build.rs from chew.keymap (at the root of the repository), a wrong key, key
+// count or layout number stops the build with the line of the keymap file.
+include!(concat!(env!("OUT_DIR"), "/layouts.rs"));
 
 // --------------------------------------------------------------------------------------
-// COMBOS -------------------------------------------------------------------------------
+// MOUSE GRID ---------------------------------------------------------------------------
+// Cells of the grid row by row (regular keys, homerows are matched with their regular key)
 #[rustfmt::skip]
-pub const COMBOS: [([KC; 2], KC); 3] = [
-    ([KC::Layout(1), KC::Layout(2)], KC::Layout(5)), // FN layout
-    // ([KC::N,         KC::R],         KC::CapLock),
-    ([KC::Layout(1), KC::T],         KC::Tion),
-    ([KC::Space,     KC::Q],         KC::Qu),
+pub const MOUSE_GRID: [KC; 9] = [
+    KC::M, KC::D, KC::Y,
+    KC::R, KC::T, KC::I,
+    KC::H, KC::G, KC::K,
 ];
@@ -1,5 +1,5 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 mod hardware;
 mod layouts;
@@ -11,10 +11,21 @@ use hardware::{
     gpios::GpiosMono,
     led::{Led, LedColor},
 };
-use options::{SERIAL_ON, TIMER_MONO_LOOP, TIMER_USB_LOOP};
+use options::{
+    BOOT_6KRO_KEYS, MIDI_BUZZER_ECHO, TIMER_MONO_LOOP, TIMER_SERIAL_LOOP, TIMER_USB_LOOP,
+};
 use software::{
     chew::Chew,
+    hid_consumer::{Consumer, ConsumerConfig, ConsumerReport},
+    hid_gamepad::{Gamepad, GamepadConfig, GamepadReport},
+    hid_keyboard::{ChewKeyboard, ChewKeyboardConfig, KEYBOARD_6KRO},
+    hid_mouse::{SmoothMouse, SmoothMouseConfig},
+    hid_system::{SystemControl, SystemControlConfig},
+    hid_via::{ViaRaw, ViaRawConfig},
     keys::{BuffCase, Buffer},
+    midi_usb::MidiClass,
+    serial_usb::{serial_write_raw, SerialOutput},
+    shell::{echo, Command, Shell},
     status::{Status, Statuses},
 };
 use usbd_serial::SerialPort;
@@ -23,11 +34,12 @@ use waveshare_rp2040_zero::{self as bsp, hal::pwm::Slices};
 
 use bsp::hal::{
     clocks::{init_clocks_and_plls, Clock},
-    entry, pac,
+    pac,
     pio::PIOExt,
+    rom_data,
     timer::Timer,
     usb,
-    watchdog::Watchdog,
+    watchdog::{ScratchRegister, Watchdog},
     Sio,
 };
 use cortex_m::prelude::*;
@@ -40,11 +52,12 @@ use ws2812_pio::Ws2812;
 #[allow(clippy::wildcard_imports)]
 use usb_device::class_prelude::*;
 use usb_device::prelude::*;
-use usbd_human_interface_device::device::keyboard::NKROBootKeyboard;
-use usbd_human_interface_device::device::mouse::{WheelMouse, WheelMouseReport};
+use usbd_human_interface_device::device::mouse::{
+    AbsoluteWheelMouse, AbsoluteWheelMouseConfig, WheelMouseReport,
+};
 use usbd_human_interface_device::prelude::*;
 
-#[entry]
+#[cfg_attr(not(test), bsp::hal::entry)]
 fn main() -> ! {
     let mut pac = pac::Peripherals::take().unwrap();
 
@@ -75,32 +88,6 @@ fn main() -> ! {
 
     let (mut pio, sm0, _, _, _) = pac.PIO0.split(&mut pac.RESETS);
 
-    // USB --
-    let usb_bus = UsbBusAllocator::new(usb::UsbBus::new(
-        pac.USBCTRL_REGS,
-        pac.USBCTRL_DPRAM,
-        clocks.usb_clock,
-        true,
-        &mut pac.RESETS,
-    ));
-
-    let mut serial = SerialPort::new(&usb_bus);
-    let mut rusty_chew = UsbHidClassBuilder::new()
-        .add_device(
-            usbd_human_interface_device::device::keyboard::NKROBootKeyboardConfig::default(),
-        )
-        .add_device(usbd_human_interface_device::device::mouse::WheelMouseConfig::default())
-        .build(&usb_bus);
-
-    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x1338, 0x1985))
-        .strings(&[StringDescriptors::default()
-            .manufacturer("florent@linguenheld.fr")
-            .product("Rusty Chew Mono")
-            .serial_number("01")])
-        .unwrap()
-        .supports_remote_wakeup(true)
-        .build();
-
     // GPIO --
     let mut gpios = GpiosMono {
         rows: [
@@ -124,6 +111,45 @@ fn main() -> ! {
         ],
     };
 
+    // Keyboard protocol --
+    // 6KRO boot keyboard if a BOOT_6KRO_KEYS is held or after a KC::ToggleNkro restart
+    let boot_keyboard = watchdog.read_scratch(ScratchRegister::Scratch0) == KEYBOARD_6KRO
+        || gpios
+            .get_active_indexes(&mut delay)
+            .iter()
+            .any(|index| BOOT_6KRO_KEYS.contains(&(*index as usize)));
+
+    // USB --
+    let usb_bus = UsbBusAllocator::new(usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+
+    let mut serial_port = SerialPort::new(&usb_bus);
+    let mut rusty_chew = UsbHidClassBuilder::new()
+        .add_device(ChewKeyboardConfig::new(boot_keyboard))
+        .add_device(SmoothMouseConfig::default())
+        .add_device(AbsoluteWheelMouseConfig::default())
+        .add_device(ConsumerConfig::default())
+        .add_device(SystemControlConfig::default())
+        .add_device(GamepadConfig::default())
+        .add_device(ViaRawConfig::default())
+        .build(&usb_bus);
+
+    let mut midi = MidiClass::new(&usb_bus);
+
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x1338, 0x1985))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("florent@linguenheld.fr")
+            .product("Rusty Chew Mono")
+            .serial_number("01")])
+        .unwrap()
+        .supports_remote_wakeup(true)
+        .build();
+
     // Buzzer (Check doc to see the assignment pin/pwm/channel)
     let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
     let mut pwm = pwm_slices.pwm6;
@@ -150,12 +176,20 @@ fn main() -> ! {
     let mut mono_count_down = timer.count_down();
     mono_count_down.start(TIMER_MONO_LOOP.millis());
 
+    let mut usb_loop = TIMER_USB_LOOP;
     let mut usb_count_down = timer.count_down();
-    usb_count_down.start(TIMER_USB_LOOP.millis());
+    usb_count_down.start(usb_loop.millis());
+
+    let mut serial_count_down = timer.count_down();
+    serial_count_down.start(TIMER_SERIAL_LOOP.millis());
 
     // --
     let mut ticks: u32 = 0;
+    let mut serial = SerialOutput::new();
+    let mut steno = SerialOutput::new(); // Chords for Plover, the port is theirs meanwhile
+    let mut shell = Shell::new();
     let mut chew = Chew::new(ticks);
+    chew.set_boot_keyboard(boot_keyboard);
     let mut statuses = Statuses::new();
 
     let mut key_buffer = Buffer::new();
@@ -165,24 +199,74 @@ fn main() -> ! {
     let mut mouse_report = WheelMouseReport::default();
     let mut last_mouse_buttons = 0;
 
-    loop {
-        if SERIAL_ON && !usb_dev.poll(&mut [&mut serial]) {
-            led.on(LedColor::Red);
-            continue;
-        }
+    let mut consumer_report;
+    let mut last_consumer_report = ConsumerReport::default();
 
+    let mut system_report;
+    let mut last_system_report = 0;
+
+    let mut pad_report;
+    let mut last_pad_report = GamepadReport::default();
+
+    loop {
         if mono_count_down.wait().is_ok() {
             // serial_write_value(&mut serial, "max duty: ", max_duty, " <-");
 
             let active_indexes = gpios.get_active_indexes(&mut delay);
             chew.update_matrix(active_indexes, ticks);
-            (key_buffer, mouse_report, statuses) =
+            (key_buffer, mouse_report, consumer_report, statuses) =
                 chew.run(key_buffer, mouse_report, statuses, ticks);
 
+            // USB loop changed by the settings --
+            if chew.settings().usb_loop != usb_loop {
+                usb_loop = chew.settings().usb_loop;
+                usb_count_down.start(usb_loop.millis());
+            }
+
+            // Restart with the other keyboard protocol --
+            if chew.keyboard_switch() {
+                let protocol = if boot_keyboard { 0 } else { KEYBOARD_6KRO };
+                watchdog.write_scratch(ScratchRegister::Scratch0, protocol);
+                watchdog.start(1_000.micros());
+                loop {
+                    cortex_m::asm::nop();
+                }
+            }
+
+            if let Some(line) = chew.dynmac_list_line() {
+                serial_write_raw(&mut serial, line.as_bytes());
+            }
+            if let Some(packet) = chew.steno_packet() {
+                serial_write_raw(&mut steno, &packet);
+            }
+
+            // Suspended host --
+            // Nothing is sent, a freshly pressed key wakes it up (if the host allows it)
+            // but it isn't printed
+            system_report = chew.system_report();
+            pad_report = chew.pad_report();
+            if usb_dev.state() == UsbDeviceState::Suspend {
+                if chew.wakeup() && usb_dev.remote_wakeup_enabled() {
+                    usb_dev.bus().remote_wakeup();
+                }
+
+                key_buffer.keys.clear();
+                mouse_report = WheelMouseReport::default();
+                consumer_report = last_consumer_report;
+                system_report = last_system_report;
+                pad_report = last_pad_report;
+            }
+
             if statuses.layout_fr == Status::On {
                 led.on(LedColor::Aqua);
             } else if statuses.layout_fn == Status::On {
                 led.on(LedColor::Fushia);
+            } else if statuses.layout_pad == Status::On {
+                led.on(LedColor::Green);
+            } else if statuses.layout_midi == Status::On {
+                led.on(LedColor::Gray);
+            } else if statuses.layout_steno == Status::On {
+                led.on(LedColor::Yellow);
             } else if statuses.leader_key == Status::On {
                 led.on(LedColor::Blue);
             } else if statuses.caplock == Status::On {
@@ -193,6 +277,20 @@ fn main() -> ! {
                 led.blink(LedColor::Red, 600, ticks);
             } else if statuses.dynmac_rec_waitkey == Status::On {
                 led.blink(LedColor::Purple, 800, ticks);
+            } else if statuses.dynmac_append_waitkey == Status::On {
+                led.blink(LedColor::Maroon, 800, ticks);
+            } else if statuses.dynmac_delete_waitkey == Status::On {
+                led.blink(LedColor::Yellow, 800, ticks);
+            } else if statuses.dynmac_playing == Status::On {
+                led.blink(LedColor::Lime, 400, ticks);
+            } else if statuses.mouse_grid == Status::On {
+                led.on(LedColor::Navy);
+            } else if statuses.mouse_lock == Status::On {
+                led.on(LedColor::Teal);
+            } else if statuses.num_lock == Status::On {
+                led.on(LedColor::Lime);
+            } else if statuses.scroll_lock == Status::On {
+                led.on(LedColor::Purple);
             } else {
                 led.off();
             }
@@ -205,6 +303,18 @@ fn main() -> ! {
                 buzzer.add_song(Song::NotifC, Side::Both, false);
             } else if statuses.layout_fn == Status::SwitchOff {
                 buzzer.add_song(Song::NotifC, Side::Both, true);
+            } else if statuses.layout_pad == Status::SwitchOn {
+                buzzer.add_song(Song::TwoFA, Side::Both, false);
+            } else if statuses.layout_pad == Status::SwitchOff {
+                buzzer.add_song(Song::TwoFA, Side::Both, true);
+            } else if statuses.layout_midi == Status::SwitchOn {
+                buzzer.add_song(Song::TwoGB, Side::Both, false);
+            } else if statuses.layout_midi == Status::SwitchOff {
+                buzzer.add_song(Song::TwoGB, Side::Both, true);
+            } else if statuses.layout_steno == Status::SwitchOn {
+                buzzer.add_song(Song::TwoAC, Side::Both, false);
+            } else if statuses.layout_steno == Status::SwitchOff {
+                buzzer.add_song(Song::TwoAC, Side::Both, true);
             } else if statuses.leader_key == Status::SwitchOn {
                 buzzer.add_song(Song::NotifA, Side::Both, false);
             } else if statuses.caplock == Status::SwitchOn {
@@ -217,12 +327,26 @@ fn main() -> ! {
                 buzzer.add_song(Song::AlertD, Side::Both, false);
             } else if statuses.dynmac_rec_waitkey == Status::SwitchOn {
                 buzzer.add_song(Song::AlertE, Side::Both, false);
+            } else if statuses.dynmac_append_waitkey == Status::SwitchOn {
+                buzzer.add_song(Song::AlertF, Side::Both, false);
+            } else if statuses.dynmac_delete_waitkey == Status::SwitchOn {
+                buzzer.add_song(Song::ErrorE, Side::Both, false);
+            } else if statuses.mouse_grid == Status::SwitchOn {
+                buzzer.add_song(Song::NotifG, Side::Both, false);
+            } else if statuses.mouse_lock == Status::SwitchOn {
+                buzzer.add_song(Song::TwoCE, Side::Both, false);
+            } else if statuses.mouse_lock == Status::SwitchOff {
+                buzzer.add_song(Song::TwoCE, Side::Both, true);
             } else if statuses.buzzer_activation == Status::SwitchOn {
                 buzzer.set_active(true);
                 buzzer.add_song(Song::StartupA, Side::Both, false);
             } else if statuses.buzzer_activation == Status::SwitchOff {
                 buzzer.add_song(Song::StartupA, Side::Both, true);
                 buzzer.set_active(false);
+            } else if statuses.setting == Status::SwitchOn {
+                buzzer.add_song(Song::SuccessA, Side::Both, false);
+            } else if statuses.setting_limit == Status::SwitchOn {
+                buzzer.add_song(Song::ErrorF, Side::Both, false);
             }
 
             // Mouse report directly done here ------------------------------------------
@@ -233,7 +357,7 @@ fn main() -> ! {
                 || mouse_report.vertical_wheel != 0
                 || mouse_report.horizontal_wheel != 0
             {
-                let mouse = rusty_chew.device::<WheelMouse<'_, _>, _>();
+                let mouse = rusty_chew.device::<SmoothMouse<'_, _>, _>();
                 match mouse.write_report(&mouse_report) {
                     Err(UsbHidError::WouldBlock) => {
                         led.on(LedColor::Red);
@@ -248,27 +372,77 @@ fn main() -> ! {
                 };
             }
 
+            // Consumer report only when it changes --
+            if consumer_report != last_consumer_report {
+                let consumer = rusty_chew.device::<Consumer<'_, _>, _>();
+                match consumer.write_report(&consumer_report) {
+                    Err(UsbHidError::WouldBlock) => {}
+                    Ok(_) => last_consumer_report = consumer_report,
+                    Err(e) => {
+                        core::panic!("Failed to write consumer report: {:?}", e)
+                    }
+                };
+            }
+
+            // System control --
+            if system_report != last_system_report {
+                let system = rusty_chew.device::<SystemControl<'_, _>, _>();
+                match system.write_report(system_report) {
+                    Err(UsbHidError::WouldBlock) => {}
+                    Ok(_) => last_system_report = system_report,
+                    Err(e) => {
+                        core::panic!("Failed to write system control report: {:?}", e)
+                    }
+                };
+            }
+
+            // Gamepad --
+            if pad_report != last_pad_report {
+                let pad = rusty_chew.device::<Gamepad<'_, _>, _>();
+                match pad.write_report(&pad_report) {
+                    Err(UsbHidError::WouldBlock) => {}
+                    Ok(_) => last_pad_report = pad_report,
+                    Err(e) => {
+                        core::panic!("Failed to write gamepad report: {:?}", e)
+                    }
+                };
+            }
+
+            // MIDI, lost if the host doesn't read it --
+            let midi_events = chew.midi_events();
+            if !midi_events.is_empty() {
+                if MIDI_BUZZER_ECHO {
+                    for event in midi_events.iter().filter(|e| e.on) {
+                        buzzer.add_song(Song::Midi(event.note), Side::Both, false);
+                    }
+                }
+                midi.send(&midi_events).ok();
+            }
+
+            // Absolute pointer (grid) --
+            if let Some(report) = chew.absolute_report() {
+                let pointer = rusty_chew.device::<AbsoluteWheelMouse<'_, _>, _>();
+                match pointer.write_report(&report) {
+                    Err(UsbHidError::WouldBlock) => {}
+                    Ok(_) => chew.absolute_report_sent(),
+                    Err(e) => {
+                        core::panic!("Failed to write absolute pointer report: {:?}", e)
+                    }
+                };
+            }
+
             buzzer.sing(ticks);
         }
 
         // USB --------------------------------------------------------------------------
-        if !SERIAL_ON && usb_count_down.wait().is_ok() && key_buffer_tempo <= ticks {
+        if usb_count_down.wait().is_ok() && key_buffer_tempo <= ticks {
             if let Some(popped_key) = key_buffer.keys.pop_front() {
                 if popped_key != last_printed_key {
-                    let keyboard = rusty_chew.device::<NKROBootKeyboard<'_, _>, _>();
+                    let keyboard = rusty_chew.device::<ChewKeyboard<'_, _>, _>();
                     match keyboard.write_report(popped_key.key_code.clone()) {
                         Err(UsbHidError::WouldBlock) => {
-                            // Wake up --
-                            if usb_dev.state() == UsbDeviceState::Suspend {
-                                usb_dev.bus().remote_wakeup();
-                                key_buffer.keys.clear();
-                            } else {
-                                led.on(LedColor::Red);
-                                key_buffer.keys.push_front(popped_key).ok();
-                            }
-                        }
-                        Err(UsbHidError::Duplicate) => {
-                            led.on(LedColor::Blue);
+                            led.on(LedColor::Red);
+                            key_buffer.keys.push_front(popped_key).ok();
                         }
                         Ok(_) => {
                             key_buffer_tempo = ticks.wrapping_add(popped_key.tempo);
@@ -292,20 +466,62 @@ fn main() -> ! {
             };
         }
 
-        // if !usb_dev.poll(&mut [&mut _serial]) {
-        //     continue;
-        // }
+        // Serial, only when no key is waiting (the text waits while the steno layout is on) --
+        let steno_on = statuses.layout_steno.is_on();
+        steno.flush(&mut serial_port);
+        if !steno_on && serial_count_down.wait().is_ok() && key_buffer.keys.is_empty() {
+            serial.flush(&mut serial_port);
+        }
 
-        if !SERIAL_ON && usb_dev.poll(&mut [&mut rusty_chew]) {
-            match rusty_chew
-                .device::<NKROBootKeyboard<'_, _>, _>()
-                .read_report()
-            {
+        if usb_dev.poll(&mut [&mut rusty_chew, &mut midi, &mut serial_port]) {
+            match rusty_chew.device::<ChewKeyboard<'_, _>, _>().read_report() {
                 Err(UsbError::WouldBlock) => {}
                 Err(e) => {
                     core::panic!("Failed to read keyboard report: {:?}", e)
                 }
-                Ok(_leds) => {}
+                Ok(leds) => chew.host_leds(leds),
+            }
+
+            // Smooth scrolling if the host has set the wheel resolution multiplier
+            chew.smooth_scroll(
+                rusty_chew
+                    .device::<SmoothMouse<'_, _>, _>()
+                    .read_multiplier(),
+            );
+
+            // VIA configuration, each packet is answered at once
+            if let Ok(mut packet) = rusty_chew.device::<ViaRaw<'_, _>, _>().read_report() {
+                if chew.via(&mut packet, ticks) {
+                    rom_data::reset_to_usb_boot(0, 0);
+                }
+                rusty_chew
+                    .device::<ViaRaw<'_, _>, _>()
+                    .write_report(&packet)
+                    .ok();
+            }
+        }
+
+        // Serial shell, with or without the feature serial --
+        let mut received = [0; 16];
+        if let Ok(length) = serial_port.read(&mut received) {
+            for byte in received[..length].iter() {
+                if !steno_on {
+                    serial_write_raw(&mut serial, echo(byte));
+                }
+
+                match shell.input(*byte) {
+                    Some(Ok(Command::Reset)) => {
+                        watchdog.start(1_000.micros());
+                        loop {
+                            cortex_m::asm::nop();
+                        }
+                    }
+                    Some(Ok(command)) => {
+                        serial_write_raw(&mut serial, chew.command(command).as_bytes())
+                    }
+                    Some(Err(e)) => serial_write_raw(&mut serial, e.to_serial().as_bytes()),
+                    None => {}
+                }
             }
         }
     }
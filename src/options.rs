@@ -1,7 +1,19 @@
 use cfg_if::cfg_if;
 
 pub const HOLD_TIME: u32 = 150; // From free to held (ms)
+pub const HOLD_TIME_MIN: u32 = 50; // Limits of the runtime value
+pub const HOLD_TIME_MAX: u32 = 1000;
 pub const COMBO_TIME: u32 = 20;
+pub const COMBO_TIME_MIN: u32 = 5;
+pub const COMBO_TIME_MAX: u32 = 100;
+
+// Runtime settings (KC::SettingUp/SettingDown), saved in flash once the keyboard is idle
+pub const SETTINGS_SAVE_DELAY: u32 = 3000; // ms after the last change
+pub const SETTING_TIME_STEP: u32 = 10; // Hold time & dead key tempo (ms)
+pub const SETTING_COMBO_STEP: u32 = 5; // ms
+pub const SETTING_SPEED_STEP: u32 = 10; // Mouse & scroll speeds (% of the MOUSE/SCROLL_SPEED_*)
+pub const SETTING_SPEED_MIN: u32 = 20;
+pub const SETTING_SPEED_MAX: u32 = 400;
 
 pub const BUZZER_ON: bool = true;
 pub const BUZZER_STARTUP_ACTIVATION: bool = true;
@@ -11,13 +23,69 @@ pub const BUFFER_LENGTH: usize = 100;
 pub const BUFFER_CASE_LENGTH: usize = 10;
 
 pub const NB_KEYS: usize = 34;
+
+// Dynamic macros
+pub const DYNMAC_MAX_MACROS: usize = 32;
+pub const DYNMAC_MAX_STEPS: usize = 4096; // Shared by all the macros (8 bytes each)
+pub const DYNMAC_MAX_COUNT: u16 = 99; // Repetitions of a dynamic macro
+
+// Runtime keymap (uploaded on the serial port, layouts.rs is used until then)
+pub const KEYMAP_MAX_LAYOUTS: usize = 16;
+pub const KEYMAP_MAX_COMBOS: usize = 16;
+pub const KEYMAP_MAX_LEADER: usize = 64; // Leader key combinations
+pub const KEYMAP_MAX_SIZE: usize = 6 * 1024; // Bytes of the binary keymap
+
+// VIA (raw HID), the 34 keys are placed in a 4 x 10 matrix (see via.rs)
+pub const VIA_ROWS: usize = 4;
+pub const VIA_COLS: usize = 10;
+pub const VIA_MACRO_COUNT: usize = 16;
+pub const VIA_MACRO_BUFFER: usize = 1024; // Bytes shared by all the macros
+
+// Flash regions (offsets from the flash beginning, they have to match memory.x)
+pub const FLASH_VIA_OFFSET: u32 = 0x1D8000;
+pub const FLASH_VIA_SIZE: usize = 16 * 1024;
+pub const FLASH_KEYMAP_OFFSET: u32 = 0x1DC000;
+pub const FLASH_KEYMAP_SIZE: usize = 32 * 1024;
+pub const FLASH_SETTINGS_OFFSET: u32 = 0x1E4000;
+pub const FLASH_SETTINGS_SIZE: usize = 16 * 1024;
+pub const FLASH_DYNMAC_OFFSET: u32 = 0x1E8000;
+pub const FLASH_DYNMAC_SIZE: usize = 96 * 1024;
+
 pub const TEMPO_DEAD_KEY: u32 = 50;
+pub const TEMPO_DEAD_KEY_MAX: u32 = 200;
+
+// Keyboard protocol
+// Hold one of these keys while plugging to get a 6KRO boot keyboard (e.g. for a BIOS or a KVM),
+// KC::ToggleNkro switches at runtime (the keyboard restarts to be enumerated again)
+pub const BOOT_6KRO_KEYS: [usize; 2] = [0, 9]; // Top outer keys (the split uses the master's)
+
+// Host lock LEDs
+// CapLock holds shift (digits & symbols are shifted too), true to toggle the host's Caps Lock
+// and follow its LED instead (only the letters are then shifted)
+pub const CAPLOCK_HOST: bool = false;
+pub const NUM_LOCK_LAYOUT: Option<usize> = None; // Default layout while Num Lock is on (e.g. Some(1))
+
+// Macros
+// Milliseconds added after each printed step (the usb loop stays the minimum)
+pub const TEMPO_MACRO: u32 = 0; // Global, adjusted with KC::MacroTempoUp/Down
+pub const TEMPO_MACRO_STEP: u32 = 10;
+pub const TEMPO_MACRO_MAX: u32 = 200;
+pub const TEMPO_MACRO_LONG: u32 = 20; // Per macro, for long strings
 
 // Milliseconds
 pub const TIMER_MONO_LOOP: u32 = 5;
 pub const TIMER_USB_LOOP: u32 = 15;
+pub const TIMER_USB_LOOP_MIN: u32 = 1;
+pub const TIMER_USB_LOOP_MAX: u32 = 50;
 pub const TIMER_UART_LOOP: u32 = 1;
 pub const TIMER_SPLIT_LOOP: u32 = 5; // New slave index sending
+pub const TIMER_SERIAL_LOOP: u32 = 10;
+
+// Serial output (feature serial), sent along with the keyboard without delaying it
+pub const SERIAL_BUFFER_LENGTH: usize = 1024;
+pub const SERIAL_CHUNK: usize = 32; // Bytes per TIMER_SERIAL_LOOP
+pub const SHELL_LINE_LENGTH: usize = 160; // Enough for a keymap upload line
+pub const KEYMAP_CHUNK: usize = 64; // Bytes per keymap upload line (in hex)
 
 pub const UART_SPEED: u32 = 921_600;
 // pub const UART_SPEED: u32 = 460_800;
@@ -33,13 +101,54 @@ pub const MOUSE_SPEED_1: (i8, u32) = (1, 15);
 pub const MOUSE_SPEED_2: (i8, u32) = (2, 10);
 pub const MOUSE_SPEED_3: (i8, u32) = (5, 10);
 pub const MOUSE_SPEED_4: (i8, u32) = (20, 10);
-pub const MOUSE_SPEED_DEFAULT: (i8, u32) = (10, 10);
+pub const MOUSE_SPEED_DEFAULT: (i8, u32) = (10, 10); // Used if the acceleration is off
+
+// Acceleration, used while no MouseSpeed key is held (these ones give a constant speed)
+pub const MOUSE_ACCEL: bool = true;
+pub const MOUSE_ACCEL_DELAY: u32 = 60; // Between the first move and the next ones (ms)
+pub const MOUSE_ACCEL_INTERVAL: u32 = 10; // Between two moves (ms)
+pub const MOUSE_ACCEL_INITIAL: u32 = 8; // 1/16 px per move (e.g. 8 is half a pixel)
+pub const MOUSE_ACCEL_MAX: u32 = 320;
+pub const MOUSE_ACCEL_TIME_TO_MAX: u32 = 1000; // ms
+pub const MOUSE_ACCEL_CURVE: u32 = 2; // 1 linear, 2 quadratic, 3 cubic...
+
+// Keep moving once the direction keys are released, slowing down until the end
+pub const MOUSE_INERTIA: bool = false;
+pub const MOUSE_INERTIA_TIME: u32 = 300; // ms
+
+// Buttons locked with KC::MouseBtLock are released after this idle time (0 to keep them)
+pub const MOUSE_LOCK_TIMEOUT: u32 = 10_000; // ms
 
 pub const SCROLL_SPEED_1: (i8, u32) = (1, 120);
 pub const SCROLL_SPEED_2: (i8, u32) = (1, 80);
 pub const SCROLL_SPEED_3: (i8, u32) = (5, 10);
 pub const SCROLL_SPEED_4: (i8, u32) = (15, 10);
-pub const SCROLL_SPEED_DEFAULT: (i8, u32) = (1, 20);
+pub const SCROLL_SPEED_DEFAULT: (i8, u32) = (1, 20); // Used if the acceleration is off
+
+// Scroll acceleration, in 1/16 detent so it's smooth if the host allows it (see hid_mouse.rs)
+pub const SCROLL_ACCEL: bool = true;
+pub const SCROLL_ACCEL_INTERVAL: u32 = 20; // Between two scrolls (ms)
+pub const SCROLL_ACCEL_INITIAL: u32 = 4; // 1/16 detent per scroll
+pub const SCROLL_ACCEL_MAX: u32 = 48;
+pub const SCROLL_ACCEL_TIME_TO_MAX: u32 = 1500; // ms
+
+// MIDI layout, each key plays the base note + its matrix index
+pub const MIDI_BASE_NOTE: u8 = 60; // C4
+pub const MIDI_VELOCITY: u8 = 100;
+pub const MIDI_BUZZER_ECHO: bool = true; // Also play the notes on the buzzer (if active)
+
+// Steno chords are written on the serial port (for Plover), GeminiPR or TX Bolt
+pub const STENO_GEMINI: bool = true;
+
+// VIA can restart the keyboard in its bootloader (BOOTLOADER_JUMP), any program which writes
+// on the raw HID interface could do it as well, so it needs the feature via_bootloader.
+cfg_if! {
+    if #[cfg(feature = "via_bootloader")] {
+        pub const VIA_BOOTLOADER: bool = true;
+    } else {
+        pub const VIA_BOOTLOADER: bool = false;
+    }
+}
 
 cfg_if! {
     if #[cfg(feature = "serial")] {
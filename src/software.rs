@@ -1,8 +1,22 @@
 pub mod chew;
 pub mod dynamicmacro;
+pub mod hid_consumer;
+pub mod hid_gamepad;
+pub mod hid_keyboard;
+pub mod hid_mouse;
+pub mod hid_system;
+pub mod hid_via;
+pub mod keymap;
 pub mod keys;
 pub mod macros;
+pub mod midi_usb;
 pub mod modifiers;
 pub mod mouse;
+pub mod rollover;
 pub mod serial_usb;
+pub mod settings;
+pub mod shell;
 pub mod status;
+pub mod steno;
+pub mod storage;
+pub mod via;
@@ -1,17 +1,35 @@
-use heapless::{Deque, Vec};
-use usbd_human_interface_device::device::mouse::WheelMouseReport;
+use core::fmt::Write;
+use heapless::{Deque, String, Vec};
+use usbd_human_interface_device::device::keyboard::KeyboardLedsReport;
+use usbd_human_interface_device::device::mouse::{AbsoluteWheelMouseReport, WheelMouseReport};
+use usbd_human_interface_device::page::Keyboard;
 
 use super::{
     dynamicmacro::DynMac,
-    keys::{Buffer, KC},
+    hid_consumer::ConsumerReport,
+    hid_gamepad::GamepadReport,
+    hid_keyboard::BOOT_KEYS,
+    hid_system::{SYSTEM_POWER, SYSTEM_SLEEP, SYSTEM_WAKE},
+    keymap::Keymap,
+    keys::{Buffer, Pad, KC},
+    midi_usb::MidiEvent,
     modifiers::Modifiers,
     mouse::Mouse,
+    rollover::HeldKeys,
+    settings::Settings,
+    shell::Command,
     status::Statuses,
+    steno::{gemini, tx_bolt, StenoChord},
+    via::{Via, ViaEvent, VIA_REPORT_LENGTH},
 };
 use crate::{
-    hardware::matrix::Matrix,
-    layouts::{COMBOS, LAYOUTS, LEADER_KEY_COMBINATIONS},
-    options::{BUZZER_STARTUP_ACTIVATION, COMBO_TIME, HOLD_TIME, NB_KEYS},
+    hardware::{flash::Rp2040Flash, matrix::Matrix},
+    layouts::MOUSE_GRID,
+    options::{
+        CAPLOCK_HOST, FLASH_DYNMAC_OFFSET, FLASH_DYNMAC_SIZE, FLASH_SETTINGS_OFFSET,
+        FLASH_SETTINGS_SIZE, FLASH_VIA_OFFSET, FLASH_VIA_SIZE, MIDI_BASE_NOTE, NB_KEYS,
+        NUM_LOCK_LAYOUT, STENO_GEMINI, TEMPO_MACRO_MAX, TEMPO_MACRO_STEP,
+    },
 };
 
 // Remove pub --
@@ -46,16 +64,28 @@ struct Leader {
 /// This is the core of this keyboard,
 /// The Run function proceeds all the keyboard hacks to fill the key buffer according to the LAYOUT.
 pub struct Chew {
+    keymap: Keymap,
+    via: Via<Rp2040Flash>,
     layout: Layout,
     leader: Leader,
-    dynmac: DynMac,
+    dynmac: DynMac<Rp2040Flash>,
     mouse: Mouse,
 
     matrix: Matrix,
     mods: Modifiers,
     homerow: Deque<Key, 5>,
+    held: HeldKeys,
 
-    buzzer_activation: bool,
+    settings: Settings<Rp2040Flash>,
+    setting_feedback: Option<bool>,
+    host_leds: KeyboardLedsReport,
+    toggle_nkro: bool,
+
+    midi_notes: Vec<usize, 16>,
+    midi_events: Vec<MidiEvent, 16>,
+
+    steno: StenoChord,
+    steno_packet: Vec<u8, 6>,
 
     pre_pressed_keys: Vec<Key, NB_KEYS>,
     pressed_keys: Vec<Key, NB_KEYS>,
@@ -66,11 +96,20 @@ pub struct Chew {
 
 impl Chew {
     pub fn new(ticks: u32) -> Self {
+        let keymap = Keymap::new();
+        let settings = Settings::new(Rp2040Flash::new(FLASH_SETTINGS_OFFSET, FLASH_SETTINGS_SIZE));
+        let default = match settings.default_layout < keymap.layouts.len() {
+            true => settings.default_layout,
+            false => 0,
+        };
+
         Chew {
+            keymap,
+            via: Via::new(Rp2040Flash::new(FLASH_VIA_OFFSET, FLASH_VIA_SIZE)),
             layout: Layout {
-                number: 0,
+                number: default,
                 index: 0,
-                default: 0,
+                default,
                 dead: false,
                 dead_done: false,
             },
@@ -78,14 +117,24 @@ impl Chew {
                 active: false,
                 buffer: Vec::new(),
             },
-            dynmac: DynMac::new(),
+            dynmac: DynMac::new(Rp2040Flash::new(FLASH_DYNMAC_OFFSET, FLASH_DYNMAC_SIZE)),
             mouse: Mouse::new(),
 
             matrix: Matrix::new(),
             mods: Modifiers::new(),
             homerow: Deque::new(),
+            held: HeldKeys::new(),
+
+            settings,
+            setting_feedback: None,
+            host_leds: KeyboardLedsReport::default(),
+            toggle_nkro: false,
+
+            midi_notes: Vec::new(),
+            midi_events: Vec::new(),
 
-            buzzer_activation: BUZZER_STARTUP_ACTIVATION,
+            steno: StenoChord::new(),
+            steno_packet: Vec::new(),
 
             pre_pressed_keys: Vec::new(),
             pressed_keys: Vec::new(),
@@ -95,6 +144,210 @@ impl Chew {
         }
     }
 
+    /// Answer a VIA packet in place (see via.rs), returns true to jump to the bootloader
+    /// (only with the feature via_bootloader).
+    pub fn via(&mut self, packet: &mut [u8; VIA_REPORT_LENGTH], ticks: u32) -> bool {
+        match self.via.command(packet, &mut self.keymap.layouts, ticks) {
+            ViaEvent::KeymapChanged => self.keymap.edited(ticks),
+            ViaEvent::KeymapReset => {
+                self.keymap.reset().ok();
+                if self.layout.default >= self.keymap.layouts.len() {
+                    self.layout.default = 0;
+                }
+                self.layout.number = self.layout.default;
+            }
+            ViaEvent::Bootloader => return true,
+            ViaEvent::None => {}
+        }
+        false
+    }
+
+    /// One line of the dynamic macros list, to write on the serial port.
+    pub fn dynmac_list_line(&mut self) -> Option<String<60>> {
+        self.dynmac.list_line()
+    }
+
+    /// Next report of the absolute pointer, remove it with absolute_report_sent().
+    pub fn absolute_report(&self) -> Option<AbsoluteWheelMouseReport> {
+        self.mouse.grid.report()
+    }
+
+    pub fn absolute_report_sent(&mut self) {
+        self.mouse.grid.report_sent();
+    }
+
+    /// System control bits of the held keys (see hid_system.rs).
+    pub fn system_report(&self) -> u8 {
+        self.pressed_keys.iter().fold(0, |report, k| match k.code {
+            KC::SystemPower => report | SYSTEM_POWER,
+            KC::SystemSleep => report | SYSTEM_SLEEP,
+            KC::SystemWake => report | SYSTEM_WAKE,
+            _ => report,
+        })
+    }
+
+    /// Gamepad report of the held pad keys.
+    pub fn pad_report(&self) -> GamepadReport {
+        let mut report = GamepadReport::default();
+        let (mut up, mut down, mut left, mut right) = (false, false, false, false);
+
+        for pad in self.pressed_keys.iter().filter_map(|k| match k.code {
+            KC::Pad(pad) => Some(pad),
+            _ => None,
+        }) {
+            match pad {
+                Pad::Up => up = true,
+                Pad::Down => down = true,
+                Pad::Left => left = true,
+                Pad::Right => right = true,
+                button => report.buttons |= 1 << button as u16,
+            }
+        }
+
+        report.hat = GamepadReport::hat_from(up, down, left, right);
+        report
+    }
+
+    /// Take the note on/off events of the midi layout.
+    pub fn midi_events(&mut self) -> Vec<MidiEvent, 16> {
+        core::mem::take(&mut self.midi_events)
+    }
+
+    /// Take the last steno chord, encoded to be written on the serial port.
+    pub fn steno_packet(&mut self) -> Option<Vec<u8, 6>> {
+        match self.steno_packet.is_empty() {
+            true => None,
+            false => Some(core::mem::take(&mut self.steno_packet)),
+        }
+    }
+
+    /// A key has just been pressed, used to wake up a suspended host.
+    pub fn wakeup(&self) -> bool {
+        !self.matrix.freshly_pressed().is_empty()
+    }
+
+    /// Lock LEDs sent by the host, the caplock follows the host's Caps Lock (CAPLOCK_HOST).
+    pub fn host_leds(&mut self, leds: KeyboardLedsReport) {
+        if CAPLOCK_HOST {
+            self.mods.caplock = leds.caps_lock;
+        }
+        self.host_leds = leds;
+    }
+
+    pub fn settings(&self) -> &Settings<Rp2040Flash> {
+        &self.settings
+    }
+
+    /// 6KRO boot keyboard, the report can't have more than 6 keys.
+    pub fn set_boot_keyboard(&mut self, boot: bool) {
+        self.held
+            .set_limit(if boot { BOOT_KEYS } else { usize::MAX });
+    }
+
+    /// KC::ToggleNkro has been pressed, the keyboard has to restart with the other protocol.
+    pub fn keyboard_switch(&mut self) -> bool {
+        core::mem::take(&mut self.toggle_nkro)
+    }
+
+    /// Run a command of the serial shell and give its answer (reset is done by the main loop).
+    pub fn command(&mut self, command: Command) -> String<200> {
+        let mut answer = String::new();
+
+        match command {
+            Command::Help => {
+                answer
+                    .push_str(
+                        "status | layers | get hold_time | set hold_time <ms> | \
+                         buzzer on|off | macros list | keymap default | reset\r\n",
+                    )
+                    .ok();
+            }
+            Command::Status => {
+                write!(
+                    &mut answer,
+                    "layer: {} (default {}) | mods:",
+                    self.layout.number, self.layout.default
+                )
+                .ok();
+                for (modifier, _) in self
+                    .mods
+                    .active_kc()
+                    .iter()
+                    .filter(|(_, index)| *index != usize::MAX)
+                {
+                    write!(&mut answer, " {:?}", modifier).ok();
+                }
+                write!(
+                    &mut answer,
+                    "{} | leader: {} | dynmac: {}\r\n",
+                    if self.mods.caplock { " caplock" } else { "" },
+                    if self.leader.active { "on" } else { "off" },
+                    self.dynmac.state_name()
+                )
+                .ok();
+            }
+            Command::Layers => {
+                write!(
+                    &mut answer,
+                    "layers: {} | active: {} | default: {}\r\n",
+                    self.keymap.layouts.len(),
+                    self.layout.number,
+                    self.layout.default
+                )
+                .ok();
+            }
+            Command::GetHoldTime => {
+                write!(&mut answer, "hold_time: {} ms\r\n", self.settings.hold_time).ok();
+            }
+            Command::SetHoldTime(ms) => {
+                self.settings.hold_time = ms;
+                write!(&mut answer, "hold_time: {} ms\r\n", self.settings.hold_time).ok();
+            }
+            Command::Buzzer(on) => {
+                self.settings.buzzer = on;
+                write!(&mut answer, "buzzer: {}\r\n", if on { "on" } else { "off" }).ok();
+            }
+            Command::MacrosList => self.dynmac.list(),
+            Command::KeymapBegin(length) => {
+                match self.keymap.begin(length) {
+                    Ok(()) => write!(&mut answer, "keymap: 0/{}\r\n", length).ok(),
+                    Err(e) => answer.push_str(&e.to_serial()).ok(),
+                };
+            }
+            Command::KeymapData(bytes) => {
+                match self.keymap.data(&bytes) {
+                    Ok(received) => write!(&mut answer, "keymap: {}\r\n", received).ok(),
+                    Err(e) => answer.push_str(&e.to_serial()).ok(),
+                };
+            }
+            Command::KeymapEnd(crc) => {
+                match self.keymap.end(crc) {
+                    Ok(()) => write!(
+                        &mut answer,
+                        "keymap: saved ({} layers)\r\n",
+                        self.keymap.layouts.len()
+                    )
+                    .ok(),
+                    Err(e) => answer.push_str(&e.to_serial()).ok(),
+                };
+            }
+            Command::KeymapDefault => {
+                match self.keymap.reset() {
+                    Ok(()) => answer.push_str("keymap: layouts.rs\r\n").ok(),
+                    Err(e) => answer.push_str(&e.to_serial()).ok(),
+                };
+            }
+            Command::Reset => {}
+        }
+
+        answer
+    }
+
+    /// The host has enabled (or not) the resolution multiplier of the mouse wheels.
+    pub fn smooth_scroll(&mut self, smooth: bool) {
+        self.mouse.smooth = smooth;
+    }
+
     pub fn update_matrix(&mut self, active_indexes: Vec<u8, 16>, ticks: u32) {
         self.matrix.update(active_indexes);
 
@@ -132,7 +385,7 @@ impl Chew {
         for key in self
             .pre_pressed_keys
             .iter_mut()
-            .filter(|k| k.ticks > COMBO_TIME)
+            .filter(|k| k.ticks > self.settings.combo_time)
         {
             self.pressed_keys.push(*key).ok();
             key.code = KC::Done;
@@ -150,18 +403,33 @@ impl Chew {
         mut mouse_report: WheelMouseReport,
         mut statuses: Statuses,
         ticks: u32,
-    ) -> (Buffer, WheelMouseReport, Statuses) {
+    ) -> (Buffer, WheelMouseReport, ConsumerReport, Statuses) {
+        let mouse_before = mouse_report;
+        key_buffer.dead_tempo = self.settings.dead_key_tempo;
+        self.mouse.speed = (self.settings.mouse_speed, self.settings.scroll_speed);
+
         // Set new keys with the current layout -----------------------------------------
         for key in self
             .pre_pressed_keys
             .iter_mut()
             .filter(|k| k.code == KC::None)
         {
-            key.code = LAYOUTS[self.layout.number][key.index];
+            key.code = self.keymap.key(self.layout.number, key.index);
+        }
+
+        // Gamepad ----------------------------------------------------------------------
+        // Pad, midi & steno keys skip the combo time and are pressed at once (no homerows there)
+        for key in self
+            .pre_pressed_keys
+            .iter_mut()
+            .filter(|k| matches!(k.code, KC::Pad(_) | KC::Midi | KC::Steno(_)))
+        {
+            self.pressed_keys.push(*key).ok();
+            key.code = KC::Done;
         }
 
         // Combos -----------------------------------------------------------------------
-        for (combo, new_key) in COMBOS.iter() {
+        for (combo, new_key) in self.keymap.combos.iter() {
             // Are these keys currently pressed ?
             if let Some(first) = self.pre_pressed_keys.iter().position(|k| match k.code {
                 KC::HomeRow(_, c) => *c == combo[0],
@@ -179,7 +447,7 @@ impl Chew {
                         .push(Key {
                             index: self.pre_pressed_keys[first].index,
                             code: *new_key,
-                            ticks: COMBO_TIME,
+                            ticks: self.settings.combo_time,
                         })
                         .ok();
                 }
@@ -189,7 +457,10 @@ impl Chew {
         // Layout -----------------------------------------------------------------------
         if !(self.matrix.is_active(self.layout.index) || self.layout.dead && !self.layout.dead_done)
         {
-            self.layout.number = self.layout.default;
+            self.layout.number = match NUM_LOCK_LAYOUT {
+                Some(number) if self.host_leds.num_lock && self.layout.default == 0 => number,
+                _ => self.layout.default,
+            };
             self.layout.dead = false;
         }
 
@@ -248,13 +519,17 @@ impl Chew {
                             *self.leader.buffer.get(2).unwrap_or(&KC::None),
                         ];
 
-                        if let Some((_, to_print)) = LEADER_KEY_COMBINATIONS
+                        if let Some((_, to_print)) = self
+                            .keymap
+                            .leader
                             .iter()
                             .find(|(comb, _)| *comb == temp_buffer)
                         {
                             success = Some(to_print);
                             self.leader.active = false;
-                        } else if !LEADER_KEY_COMBINATIONS
+                        } else if !self
+                            .keymap
+                            .leader
                             .iter()
                             .any(|(comb, _)| comb.starts_with(&self.leader.buffer))
                         {
@@ -273,14 +548,51 @@ impl Chew {
                     .push(Key {
                         index: usize::MAX,
                         code: *t,
-                        ticks: COMBO_TIME,
+                        ticks: self.settings.combo_time,
                     })
                     .ok();
             }
         }
 
+        // Mouse grid -------------------------------------------------------------------
+        // Once activated, keys select the cells until a click, leave it with ESC
+        if let Some(grid) = self
+            .pressed_keys
+            .iter_mut()
+            .find(|k| k.code == KC::MouseGrid)
+        {
+            self.mouse.grid.start();
+            grid.code = KC::Done;
+        } else if self.mouse.grid.is_active() {
+            for key in self
+                .pressed_keys
+                .iter_mut()
+                .filter(|k| k.code > KC::DoneButKeep)
+            {
+                let code = match key.code {
+                    KC::HomeRow(_, k) => *k,
+                    k => k,
+                };
+
+                match code {
+                    KC::Esc => self.mouse.grid.stop(),
+                    KC::BackSpace => self.mouse.grid.back(),
+                    KC::Enter | KC::Space | KC::MouseBtLeft => self.mouse.grid.click(0x1),
+                    KC::MouseBtRight => self.mouse.grid.click(0x2),
+                    KC::MouseBtMiddle => self.mouse.grid.click(0x4),
+                    k => {
+                        if let Some(cell) = MOUSE_GRID.iter().position(|c| *c == k) {
+                            self.mouse.grid.select(cell);
+                        }
+                    }
+                }
+
+                key.code = KC::Done;
+            }
+        }
+
         // Dynamic macros ---------------------------------------------------------------
-        key_buffer = self.dynmac.run(&mut self.pressed_keys, key_buffer);
+        self.dynmac.run(&mut self.pressed_keys, ticks);
 
         // Modifiers --------------------------------------------------------------------
         // Regulars --
@@ -290,9 +602,14 @@ impl Chew {
             .for_each(|k| self.mods.set(k.code, k.index));
 
         // Caplock --
+        // Set at once, the host's LED report confirms it (CAPLOCK_HOST)
         if let Some(caplock) = self.pressed_keys.iter_mut().find(|k| k.code == KC::CapLock) {
             self.mods.set(KC::CapLock, 0);
             caplock.code = KC::Done;
+
+            if CAPLOCK_HOST {
+                key_buffer = key_buffer.tap(Keyboard::CapsLock);
+            }
         }
 
         // Homerows --
@@ -311,11 +628,11 @@ impl Chew {
         if let Some(key) = self.homerow.front() {
             // First hold --
             // Set all homerows as held and print the regular keys
-            if key.ticks >= HOLD_TIME {
+            if key.ticks >= self.settings.hold_time {
                 'hr: while let Some(mut popped_key) = self.homerow.pop_front() {
                     match popped_key.code {
                         KC::HomeRow(modifier, regular) => {
-                            if popped_key.ticks >= HOLD_TIME {
+                            if popped_key.ticks >= self.settings.hold_time {
                                 self.mods.set(*modifier, popped_key.index);
                                 popped_key.code = *modifier;
 
@@ -326,7 +643,8 @@ impl Chew {
                             } else if !self.matrix.is_active(popped_key.index) {
                                 key_buffer = regular.usb_code(key_buffer, &self.mods);
                                 self.last_key = Some(popped_key.index);
-                                self.dynmac.record(*regular, &self.mods);
+                                self.dynmac
+                                    .record(*regular, &self.mods, popped_key.index, ticks);
                             } else {
                                 // Specific case with two homerow pressed consecutively
                                 // If the second is in an 'in-between' state, stop here to wait.
@@ -336,9 +654,27 @@ impl Chew {
                         }
                         _ => {
                             // Non Homerow key
+                            let from = key_buffer.keys.len();
                             key_buffer = popped_key.code.usb_code(key_buffer, &self.mods);
-                            self.last_key = Some(popped_key.index);
-                            self.dynmac.record(popped_key.code, &self.mods);
+                            self.dynmac.record(
+                                popped_key.code,
+                                &self.mods,
+                                popped_key.index,
+                                ticks,
+                            );
+
+                            let held;
+                            (key_buffer, held) = self.held.add(
+                                key_buffer,
+                                from,
+                                popped_key.index,
+                                &self.mods,
+                                popped_key.code < KC::ACircum
+                                    && self.matrix.is_active(popped_key.index),
+                            );
+                            if !held {
+                                self.last_key = Some(popped_key.index);
+                            }
                         }
                     }
                 }
@@ -346,9 +682,22 @@ impl Chew {
             // Print all of them with homerow pressed status
             } else if !self.matrix.is_active(key.index) {
                 while let Some(popped_key) = self.homerow.pop_front() {
+                    let from = key_buffer.keys.len();
                     key_buffer = popped_key.code.usb_code(key_buffer, &self.mods);
-                    self.last_key = Some(popped_key.index);
-                    self.dynmac.record(popped_key.code, &self.mods);
+                    self.dynmac
+                        .record(popped_key.code, &self.mods, popped_key.index, ticks);
+
+                    let held;
+                    (key_buffer, held) = self.held.add(
+                        key_buffer,
+                        from,
+                        popped_key.index,
+                        &self.mods,
+                        popped_key.code < KC::ACircum && self.matrix.is_active(popped_key.index),
+                    );
+                    if !held {
+                        self.last_key = Some(popped_key.index);
+                    }
                 }
             }
         }
@@ -359,10 +708,54 @@ impl Chew {
             .iter_mut()
             .find(|k| k.code == KC::ToggleBuzzer)
         {
-            self.buzzer_activation = !self.buzzer_activation;
+            self.settings.buzzer = !self.settings.buzzer;
             toggle_buzzer.code = KC::Done;
         }
 
+        // Settings ---------------------------------------------------------------------
+        for key in self.pressed_keys.iter_mut() {
+            if let KC::SettingUp(setting) | KC::SettingDown(setting) = key.code {
+                let up = matches!(key.code, KC::SettingUp(_));
+                self.setting_feedback = Some(self.settings.adjust(setting, up));
+                key.code = KC::Done;
+            }
+        }
+
+        // Keyboard protocol ------------------------------------------------------------
+        if let Some(toggle_nkro) = self
+            .pressed_keys
+            .iter_mut()
+            .find(|k| k.code == KC::ToggleNkro)
+        {
+            self.toggle_nkro = true;
+            toggle_nkro.code = KC::Done;
+        }
+
+        // Macro tempo ------------------------------------------------------------------
+        for key in self.pressed_keys.iter_mut() {
+            match key.code {
+                KC::MacroTempoUp => {
+                    key_buffer.macro_tempo =
+                        (key_buffer.macro_tempo + TEMPO_MACRO_STEP).min(TEMPO_MACRO_MAX);
+                    key.code = KC::Done;
+                }
+                KC::MacroTempoDown => {
+                    key_buffer.macro_tempo =
+                        key_buffer.macro_tempo.saturating_sub(TEMPO_MACRO_STEP);
+                    key.code = KC::Done;
+                }
+                _ => {}
+            }
+        }
+
+        // VIA macros -------------------------------------------------------------------
+        for key in self.pressed_keys.iter_mut() {
+            if let KC::ViaMacro(number) = key.code {
+                key_buffer = self.via.play(number, key_buffer);
+                key.code = KC::Done;
+            }
+        }
+
         // Regular keys -----------------------------------------------------------------
         for key in self
             .pressed_keys
@@ -376,6 +769,9 @@ impl Chew {
                     //    (except if the layout's key is held)
                     if k == KC::Esc && self.mods.caplock {
                         self.mods.caplock = false;
+                        if CAPLOCK_HOST {
+                            key_buffer = key_buffer.tap(Keyboard::CapsLock);
+                        }
                     } else if !(k == KC::Esc
                         && self.layout.dead
                         && !self.matrix.is_active(self.layout.index))
@@ -383,16 +779,26 @@ impl Chew {
                         if !self.homerow.is_empty() {
                             self.homerow.push_back(*key).ok();
                         } else {
+                            let from = key_buffer.keys.len();
                             key_buffer = k.usb_code(key_buffer, &self.mods);
-                            self.dynmac.record(k, &self.mods);
+                            self.dynmac.record(k, &self.mods, key.index, ticks);
+
+                            // Simple keys stay in the report while they are held down.
+                            // No held with macros (They already add the NoEventIndicated)
+                            let held;
+                            (key_buffer, held) = self.held.add(
+                                key_buffer,
+                                from,
+                                key.index,
+                                &self.mods,
+                                k < KC::ACircum && self.matrix.is_active(key.index),
+                            );
+                            if !held && k < KC::ACircum {
+                                self.last_key = Some(key.index);
+                            }
                         }
                     }
 
-                    // No held with macros (They already add the NoEventIndicated)
-                    if k < KC::ACircum {
-                        self.last_key = Some(key.index);
-                    }
-
                     key.code = KC::Done;
                     self.layout.dead = false;
                 }
@@ -401,21 +807,90 @@ impl Chew {
                 k if (k >= KC::MouseBtLeft && k <= KC::MouseBtRight) => {
                     self.mouse.active_button(&mut mouse_report, key);
                 }
+                KC::MouseBtLock(button) => {
+                    self.mouse.lock_button(*button, ticks);
+                    key.code = KC::Done;
+                }
                 k if (k >= KC::MouseSpeed1 && k <= KC::MouseSpeed4) => {
                     self.mouse.speed(key);
                     key.code = KC::DoneButKeep;
                 }
                 k if (k >= KC::MouseLeft && k <= KC::MouseRight) => {
-                    self.mouse.movement(&mut mouse_report, k, ticks);
+                    self.mouse.movement(k, ticks);
                 }
                 k if (k >= KC::MouseWheelLeft && k <= KC::MouseWheelRight) => {
-                    self.mouse.scroll(&mut mouse_report, k, ticks);
+                    self.mouse.scroll(k, ticks);
                 }
 
                 _ => {}
             }
         }
 
+        // Media ------------------------------------------------------------------------
+        // Held keys are sent with their own report (4 at once)
+        let mut consumer_report = ConsumerReport::default();
+        for (code, media) in
+            consumer_report
+                .codes
+                .iter_mut()
+                .zip(self.pressed_keys.iter().filter_map(|k| match k.code {
+                    KC::Media(media) => Some(media),
+                    _ => None,
+                }))
+        {
+            *code = media as u16;
+        }
+
+        // MIDI -------------------------------------------------------------------------
+        // Note on/off as soon as a key is pressed/released
+        let notes: Vec<usize, 16> = self
+            .pressed_keys
+            .iter()
+            .filter(|k| k.code == KC::Midi)
+            .map(|k| k.index)
+            .collect();
+
+        for (index, on) in notes
+            .iter()
+            .filter(|i| !self.midi_notes.contains(i))
+            .map(|i| (i, true))
+            .chain(
+                self.midi_notes
+                    .iter()
+                    .filter(|i| !notes.contains(i))
+                    .map(|i| (i, false)),
+            )
+        {
+            self.midi_events
+                .push(MidiEvent {
+                    note: MIDI_BASE_NOTE.saturating_add(*index as u8),
+                    on,
+                })
+                .ok();
+        }
+        self.midi_notes = notes;
+
+        // Steno ------------------------------------------------------------------------
+        // Keys are collected until all of them are released, then the chord is sent
+        for key in self.pressed_keys.iter() {
+            if let KC::Steno(steno) = key.code {
+                self.steno.add(steno);
+            }
+        }
+
+        if let Some(chord) = self.steno.take(self.matrix.is_released()) {
+            self.steno_packet = match STENO_GEMINI {
+                true => Vec::from_slice(&gemini(chord)).unwrap_or_default(),
+                false => Vec::from_slice(&tx_bolt(chord)).unwrap_or_default(),
+            };
+        }
+
+        // Held keys (rollover) --------------------------------------------------------
+        let released = self.held.release(&self.matrix);
+        if released || self.held.mods_changed(&self.mods) {
+            key_buffer = self.held.report(key_buffer, &self.mods);
+        }
+
         // Repetition -------------------------------------------------------------------
         if self.last_key.is_some_and(|index| {
             !(self.matrix.is_active(index) || (index == usize::MAX && self.mods.caplock))
@@ -423,14 +898,16 @@ impl Chew {
             // The potential still active mod(s) will set the value again.
             self.last_key = None;
 
-            // End --
-            if self.mods.active().is_empty() {
+            // End (the held keys stay) --
+            if !self.held.is_empty() {
+                key_buffer = self.held.report(key_buffer, &self.mods);
+            } else if self.mods.active().is_empty() {
                 key_buffer = KC::None.usb_code(key_buffer, &self.mods);
             }
         }
 
         // Add the active mods (useful for the real mouse) --
-        if self.last_key.is_none() && !self.mods.active().is_empty() {
+        if self.last_key.is_none() && self.held.is_empty() && !self.mods.active().is_empty() {
             for (key, index) in self.mods.active_kc().iter() {
                 key_buffer = key.usb_code(key_buffer, &self.mods);
                 self.last_key = Some(*index);
@@ -438,18 +915,48 @@ impl Chew {
         }
 
         // --
-        self.mouse.release(&self.matrix, &mut mouse_report);
+        self.mouse.release(&self.matrix, &mut mouse_report, ticks);
+        self.dynmac.record_release(&self.matrix, ticks);
+
+        // Dynamic macros playback & record of what is not a key -------------------------
+        key_buffer = self.dynmac.play(key_buffer, &mut mouse_report, ticks);
+        self.dynmac
+            .record_state(&self.mods, &mouse_before, &mouse_report, ticks);
 
         // --
         statuses.up("FR", self.layout.number == 4);
         statuses.up("FN", self.layout.number == 5);
 
+        // Gamepad, midi & steno layouts are found by their keys, so they can be moved
+        let layout = self.layout.number;
+        let pad = self.keymap.holds(layout, |k| matches!(k, KC::Pad(_)));
+        let midi = self.keymap.holds(layout, |k| *k == KC::Midi);
+        let steno = self.keymap.holds(layout, |k| matches!(k, KC::Steno(_)));
+        statuses.up("PAD", pad);
+        statuses.up("MIDI", midi);
+        statuses.up("STENO", steno);
+
         statuses.up("LEADER", self.leader.active);
         statuses.up("CAPLOCK", self.mods.caplock);
-        statuses.up("BUZZER", self.buzzer_activation);
+        statuses.up("NUMLOCK", self.host_leds.num_lock);
+        statuses.up("SCROLLLOCK", self.host_leds.scroll_lock);
+        statuses.up("BUZZER", self.settings.buzzer);
+        statuses.up("SETTING", self.setting_feedback == Some(true));
+        statuses.up("SETTING_LIMIT", self.setting_feedback == Some(false));
+        self.setting_feedback = None;
+        statuses.up("MOUSE_LOCK", self.mouse.is_locked());
+        statuses.up("MOUSE_GRID", self.mouse.grid.is_active());
 
         statuses = self.dynmac.up_statuses(statuses);
 
-        (key_buffer, mouse_report, statuses)
+        // Persistent settings, keymap, VIA & dynamic macros, written while nothing happens --
+        let idle = self.pressed_keys.is_empty() && key_buffer.keys.is_empty();
+        self.settings.default_layout = self.layout.default;
+        self.settings.save(idle, ticks);
+        self.keymap.save(idle, ticks);
+        self.via.save(idle, ticks);
+        self.dynmac.save(idle, ticks);
+
+        (key_buffer, mouse_report, consumer_report, statuses)
     }
 }
@@ -1,75 +1,396 @@
-use heapless::{FnvIndexMap, Vec};
+use core::{fmt::Write, iter::once};
+use heapless::{String, Vec};
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
 
 use super::{
     chew::Key,
     keys::{Buffer, KC},
     modifiers::Modifiers,
     status::Statuses,
+    storage::{Flash, RecordStore},
 };
-use crate::options::NB_KEYS;
+use crate::{
+    hardware::matrix::Matrix,
+    options::{
+        DYNMAC_MAX_COUNT, DYNMAC_MAX_MACROS, DYNMAC_MAX_STEPS, NB_KEYS, SETTINGS_SAVE_DELAY,
+    },
+};
+
+// Increase the version each time the format below changes (old records are then ignored).
+const STORAGE_VERSION: u8 = 3;
+const STORAGE_KIND: u8 = 1;
 
 #[derive(PartialEq, Eq)]
 enum State {
     Inactive,
     RecordWaitKey,
+    AppendWaitKey,
     RecordInProgress,
     GoWaitKey,
+    DeleteWaitKey,
+}
+
+/// What has been done, keys are saved with KC::to_u16 and mods as a bitmask
+/// to keep a step in 8 bytes.
+#[derive(Clone, Copy)]
+enum Action {
+    Key(u16, u8, u16), // Held for n ms
+    Mods(u8),
+    MouseButtons(u8),
+    MouseMove(i8, i8, i8, i8, u8), // x, y, vertical wheel, horizontal wheel, repeated n times
+}
+
+/// An action and the time elapsed since the previous one (ms).
+#[derive(Clone, Copy)]
+struct Step {
+    action: Action,
+    delay: u16,
+}
+
+impl Step {
+    /// 8 bytes per step
+    ///
+    ///  Tag         Action values (5 bytes)                                 Delay
+    ///  0000 0000 - 0000 0000 0000 0000 0000 0000 0000 0000 0000 0000 - 0000 0000 0000 0000
+    ///
+    /// A repeated mouse move waits its delay before each repetition.
+    fn to_bytes(self) -> [u8; 8] {
+        let (tag, values) = match self.action {
+            Action::Key(k, mods, hold) => {
+                let [k_low, k_high] = k.to_le_bytes();
+                let [h_low, h_high] = hold.to_le_bytes();
+                (0, [k_low, k_high, mods, h_low, h_high])
+            }
+            Action::Mods(mods) => (1, [mods, 0, 0, 0, 0]),
+            Action::MouseButtons(buttons) => (2, [buttons, 0, 0, 0, 0]),
+            Action::MouseMove(x, y, v, h, n) => (3, [x as u8, y as u8, v as u8, h as u8, n]),
+        };
+
+        let [d_low, d_high] = self.delay.to_le_bytes();
+        [
+            tag, values[0], values[1], values[2], values[3], values[4], d_low, d_high,
+        ]
+    }
+
+    fn from_bytes(b: [u8; 8]) -> Option<Step> {
+        Some(Step {
+            action: match b[0] {
+                0 => Action::Key(
+                    u16::from_le_bytes([b[1], b[2]]),
+                    b[3],
+                    u16::from_le_bytes([b[4], b[5]]),
+                ),
+                1 => Action::Mods(b[1]),
+                2 => Action::MouseButtons(b[1]),
+                3 => Action::MouseMove(b[1] as i8, b[2] as i8, b[3] as i8, b[4] as i8, b[5]),
+                _ => return None,
+            },
+            delay: u16::from_le_bytes([b[6], b[7]]),
+        })
+    }
+}
+
+/// Steps of a macro in the arena.
+struct Register {
+    key: KC,
+    start: usize,
+    length: usize,
+}
+
+/// Macro being played, steps are launched one by one when their delay is over.
+struct Playback {
+    key: KC,
+    index: usize,
+    last_ticks: u32,
+    compressed: bool,
+    buttons: u8,
+    remaining: u16,
+    repeated: u8, // Of the current mouse move
 }
 
 /// Allows the user to record some printed keys
 /// To record:
 ///    1 - Press KC::DynMacRecord
 ///    2 - Press a key which will be used to create an entry in the macro dictionary
-///    3 - Now all pressed key are recorded with their modifiers
+///    3 - Now all pressed keys are recorded with their modifiers, their timing and how long
+///        they are held. Modifiers held alone, mouse buttons and moves are recorded too
+///        (a move which goes on at the same pace is one step).
 ///    4 - Press KC::DynMacRecord to end up the record
 ///
-/// To launch a record, press KC::DynMacGo and the key which was used to record the macro
+/// To launch a record, press KC::DynMacGo (original speed) or KC::DynMacGoFast (compressed,
+/// without the delays & the holds) and the key which was used to record the macro.
+/// Digits typed just before KC::DynMacGo give the number of repetitions, like vim's 12@a
+/// (e.g. 1 2 Go A plays A 12 times, the digits are typed as usual).
+///
+/// Management:
+///    - KC::DynMacAppend then a key continues the record of this macro
+///    - KC::DynMacDelete then a key removes this macro
+///    - KC::DynMacList writes the registered keys on the serial port
+///
+/// Macros are saved in flash once the keyboard is idle after a record or a deletion, and
+/// reloaded at startup.
+///
+/// All the steps share one arena (DYNMAC_MAX_STEPS) so a few long macros or a lot of short
+/// ones can be recorded. The macro being recorded is always at the end of the arena.
+///
+/// The flash is a parameter so the macros can be checked without the board (see RamFlash).
 ///
 /// Do not work:
-///     - Mouse
 ///     - Nested dynamic macros (useful ?)
-///     - Holding keys
-pub struct DynMac {
+pub struct DynMac<F: Flash> {
     state: State,
-    key_record: KC,
-    macros: FnvIndexMap<KC, Vec<(KC, Modifiers), 128>, 32>, // Is it too much ?
+    registers: Vec<Register, DYNMAC_MAX_MACROS>,
+    steps: Vec<Step, DYNMAC_MAX_STEPS>,
+    store: RecordStore<F>,
+
+    compressed: bool,
+    count: u16,
+    playback: Option<Playback>,
+    listing: Option<usize>,
+
+    last_ticks: u32,
+    last_mods: u8,
+    last_buttons: u8,
+    holding: Vec<(usize, usize, u32), NB_KEYS>, // Step, matrix index & press ticks
+    changed_at: Option<u32>,
 }
 
-impl DynMac {
-    pub fn new() -> Self {
-        DynMac {
+impl<F: Flash> DynMac<F> {
+    pub fn new(flash: F) -> Self {
+        let mut dynmac = DynMac {
             state: State::Inactive,
-            key_record: KC::None,
-            macros: FnvIndexMap::new(),
+            registers: Vec::new(),
+            steps: Vec::new(),
+            store: RecordStore::new(flash, STORAGE_VERSION, STORAGE_KIND),
+
+            compressed: false,
+            count: 0,
+            playback: None,
+            listing: None,
+
+            last_ticks: 0,
+            last_mods: 0,
+            last_buttons: 0,
+            holding: Vec::new(),
+            changed_at: None,
+        };
+        dynmac.load();
+        dynmac
+    }
+
+    /// Format
+    ///
+    ///  Nb macros   Key         Nb steps    Steps (8 bytes each)
+    ///  0000 0000 - 0000 0000 - 0000 0000 - ...
+    ///              0000 0000   0000 0000
+    fn to_bytes<'a>(
+        registers: &'a [Register],
+        steps: &'a [Step],
+    ) -> impl Iterator<Item = u8> + Clone + 'a {
+        once(registers.len() as u8).chain(registers.iter().flat_map(|register| {
+            register
+                .key
+                .to_u16()
+                .to_le_bytes()
+                .into_iter()
+                .chain((register.length as u16).to_le_bytes())
+                .chain(
+                    steps[register.start..register.start + register.length]
+                        .iter()
+                        .flat_map(|s| s.to_bytes()),
+                )
+        }))
+    }
+
+    /// Fill the arena with the last record (a truncated one is ignored).
+    fn load(&mut self) {
+        let Some(mut bytes) = self.store.load() else {
+            return;
+        };
+
+        for _ in 0..bytes.next().unwrap_or(0) {
+            let mut header = [0; 4];
+            let mut steps = [0; 8];
+            let mut fill = |buffer: &mut [u8]| {
+                buffer.iter_mut().all(|b| match bytes.next() {
+                    Some(value) => {
+                        *b = value;
+                        true
+                    }
+                    None => false,
+                })
+            };
+
+            if !fill(&mut header) {
+                self.registers.clear();
+                self.steps.clear();
+                return;
+            }
+
+            let mut register = Register {
+                key: KC::from_u16(u16::from_le_bytes([header[0], header[1]])),
+                start: self.steps.len(),
+                length: 0,
+            };
+            for _ in 0..u16::from_le_bytes([header[2], header[3]]) {
+                if !fill(&mut steps) {
+                    self.registers.clear();
+                    self.steps.clear();
+                    return;
+                }
+
+                if let Some(step) = Step::from_bytes(steps) {
+                    if self.steps.push(step).is_ok() {
+                        register.length += 1;
+                    }
+                }
+            }
+            self.registers.push(register).ok();
+        }
+    }
+
+    /// The whole arena is written once the keyboard has been idle for SETTINGS_SAVE_DELAY
+    /// and no macro is recorded or played (a write freezes the keyboard for a while).
+    pub fn save(&mut self, idle: bool, ticks: u32) {
+        if let Some(at) = self.changed_at {
+            if idle
+                && self.state == State::Inactive
+                && self.playback.is_none()
+                && ticks.wrapping_sub(at) >= SETTINGS_SAVE_DELAY
+            {
+                // A failed write is tried again after the next change
+                self.store
+                    .save(Self::to_bytes(&self.registers, &self.steps))
+                    .ok();
+                self.changed_at = None;
+            }
         }
     }
 
+    fn position(&self, key: KC) -> Option<usize> {
+        self.registers.iter().position(|r| r.key == key)
+    }
+
+    fn macro_steps<'a>(registers: &[Register], steps: &'a [Step], key: KC) -> Option<&'a [Step]> {
+        registers
+            .iter()
+            .find(|r| r.key == key)
+            .map(|r| &steps[r.start..r.start + r.length])
+    }
+
+    /// Take the register out and move its steps to the end of the arena.
+    /// The others are shifted to keep the arena without holes.
+    fn take(&mut self, position: usize) -> Register {
+        let mut register = self.registers.remove(position);
+
+        self.steps[register.start..].rotate_left(register.length);
+        for other in self.registers.iter_mut() {
+            if other.start > register.start {
+                other.start -= register.length;
+            }
+        }
+
+        register.start = self.steps.len() - register.length;
+        register
+    }
+
+    /// Remove a macro and its steps.
+    fn remove(&mut self, key: KC) -> bool {
+        match self.position(key) {
+            Some(position) => {
+                let register = self.take(position);
+                self.steps.truncate(register.start);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Put the macro to record at the end, with its previous steps for an append.
+    fn open(&mut self, key: KC, append: bool) {
+        let register = match self.position(key) {
+            Some(position) if append => self.take(position),
+            _ => {
+                self.remove(key);
+                Register {
+                    key,
+                    start: self.steps.len(),
+                    length: 0,
+                }
+            }
+        };
+
+        if self.registers.push(register).is_err() {
+            self.state = State::Inactive;
+        }
+    }
+
+    /// Find the register key (the one used to name a macro), homerows give their regular key.
+    fn register(pressed_keys: &mut Vec<Key, NB_KEYS>) -> Option<KC> {
+        pressed_keys
+            .iter_mut()
+            .find(|k| k.code > KC::DoneButKeep && k.code < KC::MouseBtLeft)
+            .map(|key| {
+                let code = match key.code {
+                    KC::HomeRow(_, regular) => *regular,
+                    code => code,
+                };
+                key.code = KC::Done;
+                code
+            })
+    }
+
     /// Manage the state according to pressed_keys
-    pub fn run(&mut self, pressed_keys: &mut Vec<Key, NB_KEYS>, mut key_buffer: Buffer) -> Buffer {
+    pub fn run(&mut self, pressed_keys: &mut Vec<Key, NB_KEYS>, ticks: u32) {
         match self.state {
             State::Inactive => {
-                // Start a new record --
-                if let Some(key) = pressed_keys.iter_mut().find(|k| k.code == KC::DynMacRecord) {
-                    self.state = State::RecordWaitKey;
-                    key.code = KC::Done;
+                // Count --
+                // Any other key than a digit or a modifier starts it again
+                for key in pressed_keys
+                    .iter()
+                    .filter(|k| k.code > KC::DoneButKeep && k.code < KC::MouseBtLeft)
+                {
+                    if key.code >= KC::Num0 && key.code <= KC::Num9 {
+                        let digit = key.code.to_u16() - KC::Num0.to_u16();
+                        self.count = (self.count * 10 + digit).min(DYNMAC_MAX_COUNT);
+                    } else if !(key.code >= KC::Alt && key.code <= KC::Sft) {
+                        self.count = 0;
+                    }
+                }
+
+                for key in pressed_keys.iter_mut() {
+                    match key.code {
+                        // Start a new record --
+                        KC::DynMacRecord => self.state = State::RecordWaitKey,
+                        KC::DynMacAppend => self.state = State::AppendWaitKey,
+
+                        // Go --
+                        KC::DynMacGo | KC::DynMacGoFast => {
+                            self.state = State::GoWaitKey;
+                            self.compressed = key.code == KC::DynMacGoFast;
+                        }
+
+                        // Management --
+                        KC::DynMacDelete => self.state = State::DeleteWaitKey,
+                        KC::DynMacList => self.listing = Some(0),
+                        _ => continue,
+                    }
 
-                // Go --
-                } else if let Some(key) = pressed_keys.iter_mut().find(|k| k.code == KC::DynMacGo) {
-                    self.state = State::GoWaitKey;
                     key.code = KC::Done;
+                    break;
                 }
             }
 
             // Record active, now select the key to save it (erase if it already exists)
-            State::RecordWaitKey => {
-                if let Some(key) = pressed_keys
-                    .iter_mut()
-                    .find(|k| k.code > KC::DoneButKeep && k.code < KC::MouseBtLeft)
-                {
-                    self.macros.insert(key.code, Vec::new()).ok();
-                    self.key_record = key.code;
+            // or to continue it with append
+            State::RecordWaitKey | State::AppendWaitKey => {
+                if let Some(code) = Self::register(pressed_keys) {
+                    let append = self.state == State::AppendWaitKey;
                     self.state = State::RecordInProgress;
-                    key.code = KC::Done;
+                    self.open(code, append);
+
+                    self.last_ticks = ticks;
+                    self.holding.clear();
                 }
             }
 
@@ -78,44 +399,302 @@ impl DynMac {
                 if let Some(key) = pressed_keys.iter_mut().find(|k| k.code == KC::DynMacRecord) {
                     self.state = State::Inactive;
                     key.code = KC::Done;
+                    self.release(ticks, |_| false);
+                    self.changed_at = Some(ticks);
                 }
             }
 
             // Go active, now select the macro
             State::GoWaitKey => {
-                if let Some(key) = pressed_keys
-                    .iter_mut()
-                    .find(|k| k.code > KC::DoneButKeep && k.code < KC::MouseBtLeft)
-                {
-                    // Then launch it by filling the key buffer --
-                    if let Some(list) = self.macros.get(&key.code) {
-                        let mut previous = KC::None;
-                        for (k, m) in list.iter() {
-                            if previous == *k {
-                                // Add a break to allow twice same key
-                                key_buffer = KC::None.usb_code(key_buffer, &Modifiers::new());
+                if let Some(code) = Self::register(pressed_keys) {
+                    if self.position(code).is_some() {
+                        self.playback = Some(Playback {
+                            key: code,
+                            index: 0,
+                            last_ticks: ticks,
+                            compressed: self.compressed,
+                            buttons: 0,
+                            remaining: self.count.max(1),
+                            repeated: 0,
+                        });
+                    }
+                    self.count = 0;
+
+                    self.state = State::Inactive;
+                }
+            }
+
+            State::DeleteWaitKey => {
+                if let Some(code) = Self::register(pressed_keys) {
+                    if self.remove(code) {
+                        self.changed_at = Some(ticks);
+                    }
+                    self.state = State::Inactive;
+                }
+            }
+        }
+    }
+
+    /// Start the list of the registered keys (see list_line).
+    pub fn list(&mut self) {
+        self.listing = Some(0);
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        match (&self.state, self.playback.is_some()) {
+            (State::Inactive, true) => "playing",
+            (State::Inactive, false) => "inactive",
+            (State::RecordWaitKey, _) => "record (waiting for a key)",
+            (State::AppendWaitKey, _) => "append (waiting for a key)",
+            (State::RecordInProgress, _) => "recording",
+            (State::GoWaitKey, _) => "go (waiting for a key)",
+            (State::DeleteWaitKey, _) => "delete (waiting for a key)",
+        }
+    }
+
+    /// Give the list of the registered keys, one line per call (to not overflow the serial).
+    pub fn list_line(&mut self) -> Option<String<60>> {
+        let index = self.listing?;
+        let mut line = String::new();
+
+        if index == 0 {
+            write!(
+                &mut line,
+                "Dynamic macros: {} ({}/{} steps)\r\n",
+                self.registers.len(),
+                self.steps.len(),
+                DYNMAC_MAX_STEPS
+            )
+            .ok();
+        } else if let Some(register) = self.registers.get(index - 1) {
+            write!(
+                &mut line,
+                "  {:?} - {} steps\r\n",
+                register.key, register.length
+            )
+            .ok();
+        } else {
+            self.listing = None;
+            return None;
+        }
+
+        self.listing = Some(index + 1);
+        Some(line)
+    }
+
+    /// Launch the steps whose delay is over.
+    /// Keys are added in the buffer with their hold time as tempo, mouse steps wait for the
+    /// buffer to be empty to keep the order.
+    pub fn play(
+        &mut self,
+        mut key_buffer: Buffer,
+        mouse_report: &mut WheelMouseReport,
+        ticks: u32,
+    ) -> Buffer {
+        let mut over = false;
+
+        if let Some(ref mut playback) = self.playback {
+            let Some(steps) = Self::macro_steps(&self.registers, &self.steps, playback.key) else {
+                self.playback = None;
+                return key_buffer;
+            };
+
+            while let Some(step) = steps.get(playback.index) {
+                let delay = if playback.compressed {
+                    0
+                } else {
+                    step.delay as u32
+                };
+                if ticks.wrapping_sub(playback.last_ticks) < delay {
+                    break;
+                }
+
+                match step.action {
+                    Action::Key(k, mods, hold) => {
+                        let start = key_buffer.keys.len();
+                        key_buffer =
+                            KC::from_u16(k).usb_code(key_buffer, &Modifiers::from_bits(mods));
+
+                        let tempo = key_buffer.macro_tempo;
+                        key_buffer.slow_down(start, tempo);
+                        if let Some(last) = key_buffer.keys.back_mut() {
+                            if !playback.compressed {
+                                last.tempo = last.tempo.max(hold as u32);
                             }
-                            key_buffer = k.usb_code(key_buffer, m);
-                            previous = *k;
                         }
+
                         key_buffer = KC::None.usb_code(key_buffer, &Modifiers::new());
                     }
+                    Action::Mods(mods) => {
+                        key_buffer = KC::None.usb_code(key_buffer, &Modifiers::from_bits(mods));
+                    }
+                    Action::MouseButtons(_) | Action::MouseMove(..)
+                        if !key_buffer.keys.is_empty() =>
+                    {
+                        break;
+                    }
+                    Action::MouseButtons(buttons) => {
+                        playback.buttons = buttons;
+                    }
+                    Action::MouseMove(x, y, v, h, n) => {
+                        mouse_report.x = i8::saturating_add(mouse_report.x, x);
+                        mouse_report.y = i8::saturating_add(mouse_report.y, y);
+                        mouse_report.vertical_wheel =
+                            i8::saturating_add(mouse_report.vertical_wheel, v);
+                        mouse_report.horizontal_wheel =
+                            i8::saturating_add(mouse_report.horizontal_wheel, h);
 
-                    self.state = State::Inactive;
-                    key.code = KC::Done;
+                        // Same step again after its delay
+                        playback.repeated += 1;
+                        if playback.repeated < n {
+                            playback.last_ticks = ticks;
+                            continue;
+                        }
+                        playback.repeated = 0;
+                    }
                 }
+
+                playback.last_ticks = ticks;
+                playback.index += 1;
+            }
+
+            // Buttons have to be set on each loop to be maintained
+            mouse_report.buttons |= playback.buttons;
+
+            if playback.index >= steps.len() {
+                playback.remaining -= 1;
+                playback.index = 0;
+                over = playback.remaining == 0;
             }
         }
 
+        if over {
+            self.playback = None;
+        }
+
         key_buffer
     }
 
-    /// Save the key into the current macro list
-    pub fn record(&mut self, key_to_record: KC, mods_to_record: &Modifiers) {
+    /// Add a step to the macro being recorded (the last one of the arena).
+    fn push(&mut self, action: Action, ticks: u32) {
         if self.state == State::RecordInProgress {
-            if let Some(entry) = self.macros.get_mut(&self.key_record) {
-                entry.push((key_to_record, mods_to_record.clone())).ok();
+            if let Some(register) = self.registers.last_mut() {
+                let delay = ticks.wrapping_sub(self.last_ticks).min(u16::MAX as u32) as u16;
+                if self.steps.push(Step { action, delay }).is_ok() {
+                    register.length += 1;
+                    self.last_ticks = ticks;
+                }
+            }
+        }
+    }
+
+    /// Save the key into the current macro list, index is its position in the matrix.
+    pub fn record(
+        &mut self,
+        key_to_record: KC,
+        mods_to_record: &Modifiers,
+        index: usize,
+        ticks: u32,
+    ) {
+        if self.state != State::RecordInProgress {
+            return;
+        }
+
+        let key_to_record = match key_to_record {
+            KC::HomeRow(_, regular) => *regular,
+            k => k,
+        };
+
+        // Mods are already in the key step
+        self.last_mods = mods_to_record.to_bits();
+        let length = self.steps.len();
+        self.push(
+            Action::Key(key_to_record.to_u16(), self.last_mods, 0),
+            ticks,
+        );
+
+        if self.steps.len() > length {
+            self.holding.retain(|(_, i, _)| *i != index);
+            self.holding.push((length, index, ticks)).ok();
+        }
+    }
+
+    /// Save how long the recorded keys were held once they are released.
+    pub fn record_release(&mut self, matrix: &Matrix, ticks: u32) {
+        self.release(ticks, |index| matrix.is_active(index));
+    }
+
+    fn release<H: Fn(usize) -> bool>(&mut self, ticks: u32, held: H) {
+        let steps = &mut self.steps;
+        self.holding.retain(|(step, index, start)| {
+            if held(*index) {
+                return true;
+            }
+
+            if let Some(Step {
+                action: Action::Key(_, _, ref mut hold),
+                ..
+            }) = steps.get_mut(*step)
+            {
+                *hold = ticks.wrapping_sub(*start).min(u16::MAX as u32) as u16;
+            }
+            false
+        });
+    }
+
+    /// Save the mods held alone and the mouse evolutions of this chew loop.
+    /// Before is the mouse report at the beginning of the loop.
+    pub fn record_state(
+        &mut self,
+        mods: &Modifiers,
+        before: &WheelMouseReport,
+        after: &WheelMouseReport,
+        ticks: u32,
+    ) {
+        if self.state != State::RecordInProgress {
+            return;
+        }
+
+        let bits = mods.to_bits();
+        if bits != self.last_mods {
+            self.last_mods = bits;
+            self.push(Action::Mods(bits), ticks);
+        }
+
+        if after.buttons != self.last_buttons {
+            self.last_buttons = after.buttons;
+            self.push(Action::MouseButtons(after.buttons), ticks);
+        }
+
+        let movement = (
+            after.x.wrapping_sub(before.x),
+            after.y.wrapping_sub(before.y),
+            after.vertical_wheel.wrapping_sub(before.vertical_wheel),
+            after.horizontal_wheel.wrapping_sub(before.horizontal_wheel),
+        );
+        if movement != (0, 0, 0, 0) {
+            // The same move at the same pace as the previous step only increases its repetitions
+            let delay = ticks.wrapping_sub(self.last_ticks);
+            let recording = self.registers.last().is_some_and(|r| r.length > 0);
+            if let Some(Step {
+                action: Action::MouseMove(x, y, v, h, ref mut n),
+                delay: previous,
+            }) = self.steps.last_mut().filter(|_| recording)
+            {
+                if (*x, *y, *v, *h) == movement
+                    && *n < u8::MAX
+                    && delay.abs_diff(*previous as u32) <= 1
+                {
+                    *n += 1;
+                    self.last_ticks = ticks;
+                    return;
+                }
             }
+
+            self.push(
+                Action::MouseMove(movement.0, movement.1, movement.2, movement.3, 1),
+                ticks,
+            );
         }
     }
 
@@ -123,6 +702,219 @@ impl DynMac {
         statuses.up("DN_REC_WAIT", self.state == State::RecordWaitKey);
         statuses.up("DN_REC_PROG", self.state == State::RecordInProgress);
         statuses.up("DN_GO_WAIT", self.state == State::GoWaitKey);
+        statuses.up("DN_APP_WAIT", self.state == State::AppendWaitKey);
+        statuses.up("DN_DEL_WAIT", self.state == State::DeleteWaitKey);
+        statuses.up("DN_PLAY", self.playback.is_some());
         statuses
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::FLASH_DYNMAC_SIZE, software::storage::RamFlash};
+
+    fn dynmac() -> DynMac<RamFlash> {
+        DynMac::new(RamFlash::new(FLASH_DYNMAC_SIZE))
+    }
+
+    fn key(k: KC, delay: u16) -> Step {
+        Step {
+            action: Action::Key(k.to_u16(), 0, 0),
+            delay,
+        }
+    }
+
+    /// Register a macro at the end of the arena, as a record would.
+    fn add(dynmac: &mut DynMac<RamFlash>, k: KC, steps: &[Step]) {
+        let start = dynmac.steps.len();
+        dynmac.steps.extend_from_slice(steps).unwrap();
+        dynmac
+            .registers
+            .push(Register {
+                key: k,
+                start,
+                length: steps.len(),
+            })
+            .ok()
+            .unwrap();
+    }
+
+    fn bytes(dynmac: &DynMac<RamFlash>, k: KC) -> std::vec::Vec<[u8; 8]> {
+        DynMac::<RamFlash>::macro_steps(&dynmac.registers, &dynmac.steps, k)
+            .unwrap()
+            .iter()
+            .map(|s| s.to_bytes())
+            .collect()
+    }
+
+    fn press(codes: &[KC]) -> Vec<Key, NB_KEYS> {
+        codes
+            .iter()
+            .enumerate()
+            .map(|(index, &code)| Key {
+                index,
+                code,
+                ticks: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn step_bytes_round_trip() {
+        let steps = [
+            Step {
+                action: Action::Key(KC::A.to_u16(), 0b0000_0101, 450),
+                delay: 12,
+            },
+            Step {
+                action: Action::Mods(0b1000_0001),
+                delay: u16::MAX,
+            },
+            Step {
+                action: Action::MouseButtons(0b0000_0011),
+                delay: 0,
+            },
+            Step {
+                action: Action::MouseMove(-5, 127, -128, 1, 42),
+                delay: 300,
+            },
+        ];
+
+        for step in steps {
+            let bytes = step.to_bytes();
+            let read = Step::from_bytes(bytes).expect("known tag");
+            assert_eq!(read.to_bytes(), bytes);
+            assert_eq!(read.delay, step.delay);
+        }
+        assert!(Step::from_bytes([4, 0, 0, 0, 0, 0, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn arena_stays_compact() {
+        let mut dynmac = dynmac();
+        add(&mut dynmac, KC::A, &[key(KC::Num1, 1), key(KC::Num2, 2)]);
+        add(&mut dynmac, KC::B, &[key(KC::Num3, 3)]);
+        add(&mut dynmac, KC::C, &[key(KC::Num4, 4), key(KC::Num5, 5)]);
+        let a = bytes(&dynmac, KC::A);
+        let c = bytes(&dynmac, KC::C);
+
+        assert!(dynmac.remove(KC::B));
+        assert!(!dynmac.remove(KC::B));
+        assert_eq!(dynmac.steps.len(), 4);
+        assert_eq!(bytes(&dynmac, KC::A), a);
+        assert_eq!(bytes(&dynmac, KC::C), c);
+
+        // Append moves A to the end, C is shifted
+        dynmac.open(KC::A, true);
+        dynmac.state = State::RecordInProgress;
+        dynmac.push(Action::Mods(1), 0);
+        assert_eq!(dynmac.registers.last().map(|r| r.key), Some(KC::A));
+        assert_eq!(dynmac.steps.len(), 5);
+        assert_eq!(bytes(&dynmac, KC::A)[..2], a);
+        assert_eq!(bytes(&dynmac, KC::C), c);
+
+        // Record again from scratch
+        dynmac.open(KC::C, false);
+        assert_eq!(dynmac.steps.len(), 3);
+        assert!(bytes(&dynmac, KC::C).is_empty());
+        assert_eq!(bytes(&dynmac, KC::A)[..2], a);
+    }
+
+    #[test]
+    fn saved_macros_are_loaded() {
+        let mut dynmac = dynmac();
+        add(&mut dynmac, KC::A, &[key(KC::Num1, 1), key(KC::Num2, 2)]);
+        add(
+            &mut dynmac,
+            KC::B,
+            &[Step {
+                action: Action::MouseMove(1, -1, 0, 0, 3),
+                delay: 8,
+            }],
+        );
+
+        // Written only once the keyboard has been idle long enough
+        dynmac.changed_at = Some(0);
+        dynmac.save(false, SETTINGS_SAVE_DELAY);
+        dynmac.save(true, SETTINGS_SAVE_DELAY - 1);
+        assert!(dynmac.changed_at.is_some());
+        dynmac.save(true, SETTINGS_SAVE_DELAY);
+        assert!(dynmac.changed_at.is_none());
+
+        let (a, b) = (bytes(&dynmac, KC::A), bytes(&dynmac, KC::B));
+
+        let loaded = DynMac::new(dynmac.store.into_flash());
+        assert_eq!(loaded.registers.len(), 2);
+        assert_eq!(bytes(&loaded, KC::A), a);
+        assert_eq!(bytes(&loaded, KC::B), b);
+    }
+
+    #[test]
+    fn count_typed_before_go() {
+        let mut dynmac = dynmac();
+        for code in [KC::Num1, KC::Sft, KC::Num2] {
+            dynmac.run(&mut press(&[code]), 0);
+        }
+        assert_eq!(dynmac.count, 12);
+
+        // Capped
+        for _ in 0..3 {
+            dynmac.run(&mut press(&[KC::Num9]), 0);
+        }
+        assert_eq!(dynmac.count, DYNMAC_MAX_COUNT);
+
+        // Any other key starts it again
+        dynmac.run(&mut press(&[KC::A]), 0);
+        assert_eq!(dynmac.count, 0);
+        dynmac.run(&mut press(&[KC::Num3]), 0);
+        assert_eq!(dynmac.count, 3);
+
+        // Used by the next launch
+        add(&mut dynmac, KC::B, &[]);
+        dynmac.run(&mut press(&[KC::DynMacGo]), 0);
+        dynmac.run(&mut press(&[KC::B]), 0);
+        assert_eq!(dynmac.playback.as_ref().map(|p| p.remaining), Some(3));
+        assert_eq!(dynmac.count, 0);
+    }
+
+    #[test]
+    fn compressed_skips_delays() {
+        for (go, compressed) in [(KC::DynMacGo, false), (KC::DynMacGoFast, true)] {
+            let mut dynmac = dynmac();
+            add(&mut dynmac, KC::B, &[key(KC::A, 0), key(KC::C, 100)]);
+            dynmac.run(&mut press(&[go]), 0);
+            dynmac.run(&mut press(&[KC::B]), 10);
+
+            let mut report = WheelMouseReport::default();
+            let buffer = dynmac.play(Buffer::new(), &mut report, 10);
+            assert!(!buffer.keys.is_empty());
+            if compressed {
+                assert!(dynmac.playback.is_none());
+                continue;
+            }
+
+            let buffer = dynmac.play(buffer, &mut report, 109);
+            assert_eq!(dynmac.playback.as_ref().map(|p| p.index), Some(1));
+            dynmac.play(buffer, &mut report, 110);
+            assert!(dynmac.playback.is_none());
+        }
+    }
+
+    #[test]
+    fn delay_across_tick_wrap() {
+        let mut dynmac = dynmac();
+        add(&mut dynmac, KC::B, &[key(KC::A, 100)]);
+        let start = u32::MAX - 9;
+        dynmac.run(&mut press(&[KC::DynMacGo]), start);
+        dynmac.run(&mut press(&[KC::B]), start);
+
+        let mut report = WheelMouseReport::default();
+        for elapsed in [5, 99] {
+            let buffer = dynmac.play(Buffer::new(), &mut report, start.wrapping_add(elapsed));
+            assert!(buffer.keys.is_empty());
+        }
+        let buffer = dynmac.play(Buffer::new(), &mut report, start.wrapping_add(100));
+        assert!(!buffer.keys.is_empty());
+    }
+}
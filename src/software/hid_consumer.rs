@@ -0,0 +1,96 @@
+use fugit::ExtU32;
+use usb_device::bus::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_human_interface_device::device::consumer::MULTIPLE_CODE_REPORT_DESCRIPTOR;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+/// Up to four consumer usages held at once (0 is unassigned).
+/// Codes are raw u16 because some of them (e.g. the brightness) are not in the crate's page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConsumerReport {
+    pub codes: [u16; 4],
+}
+
+impl ConsumerReport {
+    /// The codes one after the other, little-endian.
+    fn to_bytes(self) -> [u8; 8] {
+        let mut data = [0; 8];
+        for (bytes, code) in data.chunks_mut(2).zip(self.codes.iter()) {
+            bytes.copy_from_slice(&code.to_le_bytes());
+        }
+        data
+    }
+}
+
+/// Consumer control (media, volume, brightness...) with the multiple code descriptor.
+pub struct Consumer<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+}
+
+impl<B: UsbBus> Consumer<'_, B> {
+    pub fn write_report(&mut self, report: &ConsumerReport) -> Result<(), UsbHidError> {
+        self.interface
+            .write_report(&report.to_bytes())
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+}
+
+pub struct ConsumerConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for ConsumerConfig<'_> {
+    fn default() -> Self {
+        ConsumerConfig {
+            interface: InterfaceBuilder::with_static_descriptor(MULTIPLE_CODE_REPORT_DESCRIPTOR)
+                .unwrap()
+                .description("Consumer Control")
+                .in_endpoint(50.millis())
+                .unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for ConsumerConfig<'a> {
+    type Allocated = Consumer<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        Consumer {
+            interface: self.interface.allocate(usb_alloc),
+        }
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for Consumer<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {}
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_packed_little_endian() {
+        let report = ConsumerReport {
+            codes: [0x00E9, 0x0223, 0, 0xABCD],
+        };
+        assert_eq!(
+            report.to_bytes(),
+            [0xE9, 0x00, 0x23, 0x02, 0x00, 0x00, 0xCD, 0xAB]
+        );
+        assert_eq!(ConsumerReport::default().to_bytes(), [0; 8]);
+    }
+}
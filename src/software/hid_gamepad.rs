@@ -0,0 +1,162 @@
+use fugit::ExtU32;
+use usb_device::bus::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+pub const HAT_CENTERED: u8 = 8;
+
+/// Gamepad with 16 buttons and a hat switch (8 directions, null state when centered).
+#[rustfmt::skip]
+pub const GAMEPAD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,        // Usage Page (Generic Desktop),
+    0x09, 0x05,        // Usage (Game Pad),
+    0xA1, 0x01,        // Collection (Application),
+    0x05, 0x09,        //   Usage Page (Buttons),
+    0x19, 0x01,        //   Usage Minimum (1),
+    0x29, 0x10,        //   Usage Maximum (16),
+    0x15, 0x00,        //   Logical Minimum (0),
+    0x25, 0x01,        //   Logical Maximum (1),
+    0x75, 0x01,        //   Report Size (1),
+    0x95, 0x10,        //   Report Count (16),
+    0x81, 0x02,        //   Input (Data, Variable, Absolute),
+
+    0x05, 0x01,        //   Usage Page (Generic Desktop),
+    0x09, 0x39,        //   Usage (Hat Switch),
+    0x15, 0x00,        //   Logical Minimum (0),
+    0x25, 0x07,        //   Logical Maximum (7),
+    0x35, 0x00,        //   Physical Minimum (0),
+    0x46, 0x3B, 0x01,  //   Physical Maximum (315),
+    0x65, 0x14,        //   Unit (Degrees),
+    0x75, 0x04,        //   Report Size (4),
+    0x95, 0x01,        //   Report Count (1),
+    0x81, 0x42,        //   Input (Data, Variable, Absolute, Null State),
+    0x65, 0x00,        //   Unit (None),
+    0x75, 0x04,        //   Report Size (4),
+    0x95, 0x01,        //   Report Count (1),
+    0x81, 0x03,        //   Input (Constant),
+    0xC0,              // End Collection
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GamepadReport {
+    pub buttons: u16,
+    pub hat: u8,
+}
+
+impl Default for GamepadReport {
+    fn default() -> Self {
+        GamepadReport {
+            buttons: 0,
+            hat: HAT_CENTERED,
+        }
+    }
+}
+
+impl GamepadReport {
+    /// Hat value from the held directions, the opposite ones cancel each other.
+    pub fn hat_from(up: bool, down: bool, left: bool, right: bool) -> u8 {
+        match (up as i8 - down as i8, right as i8 - left as i8) {
+            (1, 0) => 0,
+            (1, 1) => 1,
+            (0, 1) => 2,
+            (-1, 1) => 3,
+            (-1, 0) => 4,
+            (-1, -1) => 5,
+            (0, -1) => 6,
+            (1, -1) => 7,
+            _ => HAT_CENTERED,
+        }
+    }
+}
+
+pub struct Gamepad<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+}
+
+impl<B: UsbBus> Gamepad<'_, B> {
+    pub fn write_report(&mut self, report: &GamepadReport) -> Result<(), UsbHidError> {
+        let buttons = report.buttons.to_le_bytes();
+        self.interface
+            .write_report(&[buttons[0], buttons[1], report.hat & 0x0F])
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+}
+
+pub struct GamepadConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for GamepadConfig<'_> {
+    fn default() -> Self {
+        GamepadConfig {
+            interface: InterfaceBuilder::with_static_descriptor(GAMEPAD_REPORT_DESCRIPTOR)
+                .unwrap()
+                .description("Gamepad")
+                .in_endpoint(5.millis())
+                .unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for GamepadConfig<'a> {
+    type Allocated = Gamepad<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        Gamepad {
+            interface: self.interface.allocate(usb_alloc),
+        }
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for Gamepad<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {}
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Up, down, left, right
+    fn hat(held: [bool; 4]) -> u8 {
+        GamepadReport::hat_from(held[0], held[1], held[2], held[3])
+    }
+
+    #[test]
+    fn hat_directions_clockwise_from_up() {
+        let (u, d, l, r) = (true, true, true, true);
+        let f = false;
+        assert_eq!(hat([u, f, f, f]), 0);
+        assert_eq!(hat([u, f, f, r]), 1);
+        assert_eq!(hat([f, f, f, r]), 2);
+        assert_eq!(hat([f, d, f, r]), 3);
+        assert_eq!(hat([f, d, f, f]), 4);
+        assert_eq!(hat([f, d, l, f]), 5);
+        assert_eq!(hat([f, f, l, f]), 6);
+        assert_eq!(hat([u, f, l, f]), 7);
+    }
+
+    #[test]
+    fn hat_centered_and_opposites() {
+        assert_eq!(hat([false; 4]), HAT_CENTERED);
+        assert_eq!(hat([true; 4]), HAT_CENTERED);
+        assert_eq!(hat([true, true, false, false]), HAT_CENTERED);
+        assert_eq!(hat([false, false, true, true]), HAT_CENTERED);
+
+        // The other axis still counts
+        assert_eq!(hat([true, true, false, true]), 2);
+        assert_eq!(hat([true, false, true, true]), 0);
+    }
+}
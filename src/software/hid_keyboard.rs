@@ -0,0 +1,130 @@
+use fugit::ExtU32;
+use usb_device::bus::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_human_interface_device::device::keyboard::{
+    KeyboardLedsReport, BOOT_KEYBOARD_REPORT_DESCRIPTOR, NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR,
+};
+use usbd_human_interface_device::page::Keyboard;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+pub const BOOT_KEYS: usize = 6;
+
+// Kept in a watchdog scratch register to restart as a 6KRO keyboard ("6KRO")
+pub const KEYBOARD_6KRO: u32 = 0x364B_524F;
+
+const BOOT_REPORT_LENGTH: usize = 8;
+const NKRO_REPORT_LENGTH: usize = 25;
+
+/// Keyboard chosen at startup (the host can't see another interface without a new enumeration):
+///     - NKRO: boot compatible report followed by a bitmap of all the keys
+///     - 6KRO: classic boot keyboard for the BIOS/KVM which only speak the boot protocol
+pub struct ChewKeyboard<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes32, OutBytes8, ReportSingle>,
+    boot: bool,
+}
+
+impl<B: UsbBus> ChewKeyboard<'_, B> {
+    /// Keys over the boot limit are dropped, the report builder has to queue them.
+    pub fn write_report<K: IntoIterator<Item = Keyboard>>(
+        &mut self,
+        keys: K,
+    ) -> Result<(), UsbHidError> {
+        let mut report = [0; NKRO_REPORT_LENGTH];
+        let mut boot_keys = 0;
+
+        for key in keys {
+            let code = u8::from(key);
+            match code {
+                0x00 => {}                                      // NoEventIndicated
+                0xE0..=0xE7 => report[0] |= 1 << (code - 0xE0), // Modifiers
+                _ => {
+                    if boot_keys < BOOT_KEYS {
+                        report[2 + boot_keys] = code;
+                        boot_keys += 1;
+                    }
+                    if (code as usize) < (NKRO_REPORT_LENGTH - BOOT_REPORT_LENGTH) * 8 {
+                        report[BOOT_REPORT_LENGTH + code as usize / 8] |= 1 << (code % 8);
+                    }
+                }
+            }
+        }
+
+        let length = match self.boot {
+            true => BOOT_REPORT_LENGTH,
+            false => NKRO_REPORT_LENGTH,
+        };
+
+        self.interface
+            .write_report(&report[..length])
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+
+    pub fn read_report(&mut self) -> usb_device::Result<KeyboardLedsReport> {
+        let data = &mut [0];
+        match self.interface.read_report(data) {
+            Err(e) => Err(e),
+            Ok(_) => Ok(KeyboardLedsReport {
+                num_lock: data[0] & 0x01 != 0,
+                caps_lock: data[0] & 0x02 != 0,
+                scroll_lock: data[0] & 0x04 != 0,
+                compose: data[0] & 0x08 != 0,
+                kana: data[0] & 0x10 != 0,
+            }),
+        }
+    }
+}
+
+pub struct ChewKeyboardConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes32, OutBytes8, ReportSingle>,
+    boot: bool,
+}
+
+impl ChewKeyboardConfig<'_> {
+    pub fn new(boot: bool) -> Self {
+        let (descriptor, description) = match boot {
+            true => (BOOT_KEYBOARD_REPORT_DESCRIPTOR, "Keyboard"),
+            false => (NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR, "NKRO Keyboard"),
+        };
+
+        ChewKeyboardConfig {
+            interface: InterfaceBuilder::with_static_descriptor(descriptor)
+                .unwrap()
+                .boot_device(InterfaceProtocol::Keyboard)
+                .description(description)
+                .in_endpoint(10.millis())
+                .unwrap()
+                .with_out_endpoint(100.millis())
+                .unwrap()
+                .build(),
+            boot,
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for ChewKeyboardConfig<'a> {
+    type Allocated = ChewKeyboard<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        ChewKeyboard {
+            interface: self.interface.allocate(usb_alloc),
+            boot: self.boot,
+        }
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for ChewKeyboard<'a, B> {
+    type I = Interface<'a, B, InBytes32, OutBytes8, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {}
+
+    // No idle handling: the report length depends on the keyboard, so the ManagedIdleInterface
+    // (one packed report type) doesn't fit. Each change is sent, a duplicate report is harmless.
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
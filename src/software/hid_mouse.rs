@@ -0,0 +1,165 @@
+use fugit::ExtU32;
+use usb_device::bus::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+/// Wheel units sent for one detent once the host has enabled the multiplier.
+/// It has to match the physical maximum of the resolution multipliers below.
+pub const SCROLL_MULTIPLIER: i32 = 16;
+
+/// Wheel mouse (same report as WheelMouse) with a Resolution Multiplier on both wheels.
+/// Hosts which support it set the multiplier with a feature report and then divide the wheel
+/// values by SCROLL_MULTIPLIER, the others keep reading whole detents.
+///
+/// Reference: <https://learn.microsoft.com/en-us/windows-hardware/design/component-guidelines/enhanced-wheel-support>
+#[rustfmt::skip]
+pub const SMOOTH_MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,        // Usage Page (Generic Desktop),
+    0x09, 0x02,        // Usage (Mouse),
+    0xA1, 0x01,        // Collection (Application),
+    0x09, 0x01,        //   Usage (Pointer),
+    0xA1, 0x00,        //   Collection (Physical),
+    0x95, 0x08,        //     Report Count (8),
+    0x75, 0x01,        //     Report Size (1),
+    0x05, 0x09,        //     Usage Page (Buttons),
+    0x19, 0x01,        //     Usage Minimum (1),
+    0x29, 0x08,        //     Usage Maximum (8),
+    0x15, 0x00,        //     Logical Minimum (0),
+    0x25, 0x01,        //     Logical Maximum (1),
+    0x81, 0x02,        //     Input (Data, Variable, Absolute),
+
+    0x75, 0x08,        //     Report Size (8),
+    0x95, 0x02,        //     Report Count (2),
+    0x05, 0x01,        //     Usage Page (Generic Desktop),
+    0x09, 0x30,        //     Usage (X),
+    0x09, 0x31,        //     Usage (Y),
+    0x15, 0x81,        //     Logical Minimum (-127),
+    0x25, 0x7F,        //     Logical Maximum (127),
+    0x81, 0x06,        //     Input (Data, Variable, Relative),
+
+    0xA1, 0x02,        //     Collection (Logical),
+    0x09, 0x48,        //       Usage (Resolution Multiplier),
+    0x15, 0x00,        //       Logical Minimum (0),
+    0x25, 0x01,        //       Logical Maximum (1),
+    0x35, 0x01,        //       Physical Minimum (1),
+    0x45, 0x10,        //       Physical Maximum (16),
+    0x75, 0x02,        //       Report Size (2),
+    0x95, 0x01,        //       Report Count (1),
+    0xB1, 0x02,        //       Feature (Data, Variable, Absolute),
+    0x35, 0x00,        //       Physical Minimum (0),
+    0x45, 0x00,        //       Physical Maximum (0),
+    0x09, 0x38,        //       Usage (Wheel),
+    0x15, 0x81,        //       Logical Minimum (-127),
+    0x25, 0x7F,        //       Logical Maximum (127),
+    0x75, 0x08,        //       Report Size (8),
+    0x95, 0x01,        //       Report Count (1),
+    0x81, 0x06,        //       Input (Data, Variable, Relative),
+    0xC0,              //     End Collection,
+
+    0xA1, 0x02,        //     Collection (Logical),
+    0x09, 0x48,        //       Usage (Resolution Multiplier),
+    0x15, 0x00,        //       Logical Minimum (0),
+    0x25, 0x01,        //       Logical Maximum (1),
+    0x35, 0x01,        //       Physical Minimum (1),
+    0x45, 0x10,        //       Physical Maximum (16),
+    0x75, 0x02,        //       Report Size (2),
+    0x95, 0x01,        //       Report Count (1),
+    0xB1, 0x02,        //       Feature (Data, Variable, Absolute),
+    0x35, 0x00,        //       Physical Minimum (0),
+    0x45, 0x00,        //       Physical Maximum (0),
+    0x05, 0x0C,        //       Usage Page (Consumer),
+    0x0A, 0x38, 0x02,  //       Usage (AC Pan),
+    0x15, 0x81,        //       Logical Minimum (-127),
+    0x25, 0x7F,        //       Logical Maximum (127),
+    0x75, 0x08,        //       Report Size (8),
+    0x95, 0x01,        //       Report Count (1),
+    0x81, 0x06,        //       Input (Data, Variable, Relative),
+    0xC0,              //     End Collection,
+
+    0x75, 0x04,        //     Report Size (4),
+    0x95, 0x01,        //     Report Count (1),
+    0xB1, 0x03,        //     Feature (Constant),
+    0xC0,              //   End Collection,
+    0xC0,              // End Collection
+];
+
+pub struct SmoothMouse<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutBytes8, ReportSingle>,
+    smooth: bool,
+}
+
+impl<B: UsbBus> SmoothMouse<'_, B> {
+    pub fn write_report(&mut self, report: &WheelMouseReport) -> Result<(), UsbHidError> {
+        let data = [
+            report.buttons,
+            report.x as u8,
+            report.y as u8,
+            report.vertical_wheel as u8,
+            report.horizontal_wheel as u8,
+        ];
+        self.interface
+            .write_report(&data)
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+
+    /// Read the feature report sent by the host (the out endpoint is not used).
+    /// Smooth scrolling is on once one of the multipliers is set.
+    pub fn read_multiplier(&mut self) -> bool {
+        let mut data = [0; 8];
+        if let Ok(length) = self.interface.read_report(&mut data) {
+            if length > 0 {
+                self.smooth = data[0] & 0x0F != 0;
+            }
+        }
+        self.smooth
+    }
+}
+
+pub struct SmoothMouseConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutBytes8, ReportSingle>,
+}
+
+impl Default for SmoothMouseConfig<'_> {
+    fn default() -> Self {
+        SmoothMouseConfig {
+            interface: InterfaceBuilder::with_static_descriptor(SMOOTH_MOUSE_REPORT_DESCRIPTOR)
+                .unwrap()
+                .boot_device(InterfaceProtocol::Mouse)
+                .description("Smooth Mouse")
+                .in_endpoint(10.millis())
+                .unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for SmoothMouseConfig<'a> {
+    type Allocated = SmoothMouse<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        SmoothMouse {
+            interface: self.interface.allocate(usb_alloc),
+            smooth: false,
+        }
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for SmoothMouse<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutBytes8, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    // The host has to set the multiplier again after a reset
+    fn reset(&mut self) {
+        self.smooth = false;
+    }
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
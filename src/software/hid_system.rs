@@ -0,0 +1,82 @@
+use fugit::ExtU32;
+use usb_device::bus::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+pub const SYSTEM_POWER: u8 = 0x01;
+pub const SYSTEM_SLEEP: u8 = 0x02;
+pub const SYSTEM_WAKE: u8 = 0x04;
+
+/// Generic desktop system control, one bit per usage (power down, sleep, wake up).
+#[rustfmt::skip]
+pub const SYSTEM_CONTROL_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,        // Usage Page (Generic Desktop),
+    0x09, 0x80,        // Usage (System Control),
+    0xA1, 0x01,        // Collection (Application),
+    0x19, 0x81,        //   Usage Minimum (System Power Down),
+    0x29, 0x83,        //   Usage Maximum (System Wake Up),
+    0x15, 0x00,        //   Logical Minimum (0),
+    0x25, 0x01,        //   Logical Maximum (1),
+    0x75, 0x01,        //   Report Size (1),
+    0x95, 0x03,        //   Report Count (3),
+    0x81, 0x02,        //   Input (Data, Variable, Absolute),
+    0x95, 0x05,        //   Report Count (5),
+    0x81, 0x03,        //   Input (Constant),
+    0xC0,              // End Collection
+];
+
+pub struct SystemControl<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes8, OutNone, ReportSingle>,
+}
+
+impl<B: UsbBus> SystemControl<'_, B> {
+    /// Report with the SYSTEM_* bits of the held keys.
+    pub fn write_report(&mut self, report: u8) -> Result<(), UsbHidError> {
+        self.interface
+            .write_report(&[report])
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+}
+
+pub struct SystemControlConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes8, OutNone, ReportSingle>,
+}
+
+impl Default for SystemControlConfig<'_> {
+    fn default() -> Self {
+        SystemControlConfig {
+            interface: InterfaceBuilder::with_static_descriptor(SYSTEM_CONTROL_REPORT_DESCRIPTOR)
+                .unwrap()
+                .description("System Control")
+                .in_endpoint(50.millis())
+                .unwrap()
+                .without_out_endpoint()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for SystemControlConfig<'a> {
+    type Allocated = SystemControl<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        SystemControl {
+            interface: self.interface.allocate(usb_alloc),
+        }
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for SystemControl<'a, B> {
+    type I = Interface<'a, B, InBytes8, OutNone, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {}
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use fugit::ExtU32;
+use usb_device::bus::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_human_interface_device::usb_class::prelude::*;
+
+use super::via::VIA_REPORT_LENGTH;
+
+/// Raw HID interface found by VIA (usage page 0xFF60, usage 0x61), 32 bytes each way.
+#[rustfmt::skip]
+pub const VIA_REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x60, 0xFF,  // Usage Page (Vendor Defined 0xFF60),
+    0x09, 0x61,        // Usage (0x61),
+    0xA1, 0x01,        // Collection (Application),
+    0x09, 0x62,        //   Usage (Data In),
+    0x15, 0x00,        //   Logical Minimum (0),
+    0x26, 0xFF, 0x00,  //   Logical Maximum (255),
+    0x95, 0x20,        //   Report Count (32),
+    0x75, 0x08,        //   Report Size (8),
+    0x81, 0x02,        //   Input (Data, Variable, Absolute),
+    0x09, 0x63,        //   Usage (Data Out),
+    0x15, 0x00,        //   Logical Minimum (0),
+    0x26, 0xFF, 0x00,  //   Logical Maximum (255),
+    0x95, 0x20,        //   Report Count (32),
+    0x75, 0x08,        //   Report Size (8),
+    0x91, 0x02,        //   Output (Data, Variable, Absolute),
+    0xC0,              // End Collection
+];
+
+pub struct ViaRaw<'a, B: UsbBus> {
+    interface: Interface<'a, B, InBytes32, OutBytes32, ReportSingle>,
+}
+
+impl<B: UsbBus> ViaRaw<'_, B> {
+    /// Packet sent by VIA, answered with write_report (see via.rs).
+    pub fn read_report(&mut self) -> usb_device::Result<[u8; VIA_REPORT_LENGTH]> {
+        let mut packet = [0; VIA_REPORT_LENGTH];
+        self.interface.read_report(&mut packet).map(|_| packet)
+    }
+
+    pub fn write_report(&mut self, packet: &[u8; VIA_REPORT_LENGTH]) -> Result<(), UsbHidError> {
+        self.interface
+            .write_report(packet)
+            .map(|_| ())
+            .map_err(UsbHidError::from)
+    }
+}
+
+pub struct ViaRawConfig<'a> {
+    interface: InterfaceConfig<'a, InBytes32, OutBytes32, ReportSingle>,
+}
+
+impl Default for ViaRawConfig<'_> {
+    fn default() -> Self {
+        ViaRawConfig {
+            interface: InterfaceBuilder::with_static_descriptor(VIA_REPORT_DESCRIPTOR)
+                .unwrap()
+                .description("VIA")
+                .in_endpoint(1.millis())
+                .unwrap()
+                .with_out_endpoint(1.millis())
+                .unwrap()
+                .build(),
+        }
+    }
+}
+
+impl<'a, B: UsbBus + 'a> UsbAllocatable<'a, B> for ViaRawConfig<'a> {
+    type Allocated = ViaRaw<'a, B>;
+
+    fn allocate(self, usb_alloc: &'a UsbBusAllocator<B>) -> Self::Allocated {
+        ViaRaw {
+            interface: self.interface.allocate(usb_alloc),
+        }
+    }
+}
+
+impl<'a, B: UsbBus> DeviceClass<'a> for ViaRaw<'a, B> {
+    type I = Interface<'a, B, InBytes32, OutBytes32, ReportSingle>;
+
+    fn interface(&mut self) -> &mut Self::I {
+        &mut self.interface
+    }
+
+    fn reset(&mut self) {}
+
+    fn tick(&mut self) -> Result<(), UsbHidError> {
+        Ok(())
+    }
+}
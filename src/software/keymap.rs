@@ -0,0 +1,471 @@
+use heapless::{String, Vec};
+
+use super::{
+    keys::{Media, Pad, Setting, Steno, KC},
+    storage::{crc32, RecordStore},
+};
+use crate::{
+    hardware::flash::Rp2040Flash,
+    layouts::{COMBOS, LAYOUTS, LEADER_KEY_COMBINATIONS},
+    options::{
+        FLASH_KEYMAP_OFFSET, FLASH_KEYMAP_SIZE, KEYMAP_MAX_COMBOS, KEYMAP_MAX_LAYOUTS,
+        KEYMAP_MAX_LEADER, KEYMAP_MAX_SIZE, NB_KEYS, SETTINGS_SAVE_DELAY, VIA_MACRO_COUNT,
+    },
+};
+
+// Increase the version each time the format below changes (old records are then ignored).
+const STORAGE_VERSION: u8 = 1;
+const STORAGE_KIND: u8 = 3;
+
+// Discriminants of the KC variants with data (see keys.rs)
+const HOME_ROW: u16 = 11000;
+const MOUSE_BT_LOCK: u16 = 30003;
+const MEDIA: u16 = 31000;
+const PAD: u16 = 32000;
+const STENO: u16 = 34000;
+const LAYOUT: u16 = 40000;
+const LAY_SET: u16 = 40001;
+const LAY_DEAD: u16 = 40002;
+const VIA_MACRO: u16 = 52100;
+const SETTING_UP: u16 = 60010;
+const SETTING_DOWN: u16 = 60011;
+
+const _: () = assert!(LAYOUTS.len() <= KEYMAP_MAX_LAYOUTS);
+const _: () = assert!(COMBOS.len() <= KEYMAP_MAX_COMBOS);
+const _: () = assert!(LEADER_KEY_COMBINATIONS.len() <= KEYMAP_MAX_LEADER);
+
+type Layouts = Vec<[KC; NB_KEYS], KEYMAP_MAX_LAYOUTS>;
+type Combos = Vec<([KC; 2], KC), KEYMAP_MAX_COMBOS>;
+type Leader = Vec<([KC; 3], KC), KEYMAP_MAX_LEADER>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    NotStarted,
+    TooLarge,
+    Incomplete,
+    Crc,
+    Truncated,
+    UnknownKey,
+    WrongLayout,
+    WrongSize,
+    Storage,
+}
+
+impl KeymapError {
+    pub fn to_serial(&self) -> String<40> {
+        let mut output = String::new();
+        output
+            .push_str(match self {
+                KeymapError::NotStarted => "-- Error Keymap not started --\r\n",
+                KeymapError::TooLarge => "-- Error Keymap too large --\r\n",
+                KeymapError::Incomplete => "-- Error Keymap incomplete --\r\n",
+                KeymapError::Crc => "-- Error Keymap crc --\r\n",
+                KeymapError::Truncated => "-- Error Keymap truncated --\r\n",
+                KeymapError::UnknownKey => "-- Error Keymap unknown key --\r\n",
+                KeymapError::WrongLayout => "-- Error Keymap wrong layout --\r\n",
+                KeymapError::WrongSize => "-- Error Keymap wrong size --\r\n",
+                KeymapError::Storage => "-- Error Keymap not saved --\r\n",
+            })
+            .ok();
+        output
+    }
+}
+
+/// Bytes received on the serial port, checked once they are all there.
+struct Upload {
+    length: usize,
+    bytes: Vec<u8, KEYMAP_MAX_SIZE>,
+}
+
+/// Layouts, combos & leader key combinations read by Chew.
+/// They come from layouts.rs until a keymap is uploaded on the serial port (see the host tool
+/// in tools/keymap) or edited with VIA, the last one is then loaded from flash at each start.
+/// An empty record brings layouts.rs back.
+pub struct Keymap {
+    pub layouts: Layouts,
+    pub combos: Combos,
+    pub leader: Leader,
+    store: RecordStore<Rp2040Flash>,
+    upload: Option<Upload>,
+    changed_at: Option<u32>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        let store = RecordStore::new(
+            Rp2040Flash::new(FLASH_KEYMAP_OFFSET, FLASH_KEYMAP_SIZE),
+            STORAGE_VERSION,
+            STORAGE_KIND,
+        );
+
+        let (layouts, combos, leader) = match store.load().map(decode) {
+            Some(Ok(tables)) => tables,
+            _ => defaults(),
+        };
+
+        Keymap {
+            layouts,
+            combos,
+            leader,
+            store,
+            upload: None,
+            changed_at: None,
+        }
+    }
+
+    pub fn key(&self, layout: usize, index: usize) -> KC {
+        self.layouts
+            .get(layout)
+            .and_then(|keys| keys.get(index))
+            .copied()
+            .unwrap_or(KC::None)
+    }
+
+    /// Does the layout hold this kind of key (e.g. gamepad keys make a gamepad layout)?
+    /// The layouts can be moved by an upload or VIA so their role doesn't come from their number.
+    pub fn holds<F: Fn(&KC) -> bool>(&self, layout: usize, kind: F) -> bool {
+        self.layouts
+            .get(layout)
+            .is_some_and(|keys| keys.iter().any(kind))
+    }
+
+    /// Start of an upload, the previous one is dropped.
+    pub fn begin(&mut self, length: usize) -> Result<(), KeymapError> {
+        if length > KEYMAP_MAX_SIZE {
+            return Err(KeymapError::TooLarge);
+        }
+
+        self.upload = Some(Upload {
+            length,
+            bytes: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Add a part of the upload, returns the number of bytes received so far.
+    pub fn data(&mut self, bytes: &[u8]) -> Result<usize, KeymapError> {
+        let upload = self.upload.as_mut().ok_or(KeymapError::NotStarted)?;
+
+        if upload.bytes.len() + bytes.len() > upload.length
+            || upload.bytes.extend_from_slice(bytes).is_err()
+        {
+            self.upload = None;
+            return Err(KeymapError::TooLarge);
+        }
+        Ok(upload.bytes.len())
+    }
+
+    /// Check the complete upload, then save & use it.
+    pub fn end(&mut self, crc: u32) -> Result<(), KeymapError> {
+        let upload = self.upload.take().ok_or(KeymapError::NotStarted)?;
+
+        if upload.bytes.len() != upload.length {
+            return Err(KeymapError::Incomplete);
+        }
+        if crc32(upload.bytes.iter().copied()) != crc {
+            return Err(KeymapError::Crc);
+        }
+
+        let (layouts, combos, leader) = decode(upload.bytes.iter().copied())?;
+        self.store
+            .save(upload.bytes.iter().copied())
+            .map_err(|_| KeymapError::Storage)?;
+
+        self.layouts = layouts;
+        self.combos = combos;
+        self.leader = leader;
+        Ok(())
+    }
+
+    /// Back to layouts.rs, the uploaded keymap is forgotten.
+    pub fn reset(&mut self) -> Result<(), KeymapError> {
+        self.upload = None;
+        self.store
+            .save(core::iter::empty())
+            .map_err(|_| KeymapError::Storage)?;
+
+        (self.layouts, self.combos, self.leader) = defaults();
+        self.changed_at = None;
+        Ok(())
+    }
+
+    /// The layouts have been modified in place (VIA), they are saved by save().
+    pub fn edited(&mut self, ticks: u32) {
+        self.changed_at = Some(ticks);
+    }
+
+    /// Edits are written once the keyboard has been idle for SETTINGS_SAVE_DELAY.
+    pub fn save(&mut self, idle: bool, ticks: u32) {
+        if let Some(at) = self.changed_at {
+            if idle && ticks.wrapping_sub(at) >= SETTINGS_SAVE_DELAY {
+                // A failed write is tried again after the next change
+                if let Ok(bytes) = encode(&self.layouts, &self.combos, &self.leader) {
+                    self.store.save(bytes.iter().copied()).ok();
+                }
+                self.changed_at = None;
+            }
+        }
+    }
+}
+
+fn defaults() -> (Layouts, Combos, Leader) {
+    (
+        LAYOUTS.iter().copied().collect(),
+        COMBOS.iter().copied().collect(),
+        LEADER_KEY_COMBINATIONS.iter().copied().collect(),
+    )
+}
+
+/// Format
+///
+///  Nb layouts  Nb keys     Nb combos   Nb leader
+///  0000 0000 - 0000 0000 - 0000 0000 - 0000 0000 - Layouts - Combos - Leader
+///
+///  Layouts: nb keys per layout, row by row as in layouts.rs
+///  Combos: the 2 keys then the new one
+///  Leader: the 3 keys (KC::None for a shorter combination) then the new one
+///
+/// Each key is its KC value (2 bytes, little endian), the variants with data are followed by
+/// their values (2 bytes each):
+///  HomeRow: modifier & regular key        MouseBtLock: button
+///  Media, Pad, Steno & Setting: value     Layout, LaySet & LayDead: layout number
+///  ViaMacro: macro number
+fn decode<I: Iterator<Item = u8>>(mut bytes: I) -> Result<(Layouts, Combos, Leader), KeymapError> {
+    let mut header = [0; 4];
+    for b in header.iter_mut() {
+        *b = bytes.next().ok_or(KeymapError::Truncated)?;
+    }
+    let [nb_layouts, nb_keys, nb_combos, nb_leader] = header.map(|b| b as usize);
+
+    if nb_layouts == 0
+        || nb_layouts > KEYMAP_MAX_LAYOUTS
+        || nb_keys != NB_KEYS
+        || nb_combos > KEYMAP_MAX_COMBOS
+        || nb_leader > KEYMAP_MAX_LEADER
+    {
+        return Err(KeymapError::WrongSize);
+    }
+
+    let mut read = || read_key(&mut bytes, nb_layouts);
+
+    let mut layouts = Layouts::new();
+    for _ in 0..nb_layouts {
+        let mut keys = [KC::None; NB_KEYS];
+        for key in keys.iter_mut() {
+            *key = read()?;
+        }
+        layouts.push(keys).ok();
+    }
+
+    let mut combos = Combos::new();
+    for _ in 0..nb_combos {
+        combos.push(([read()?, read()?], read()?)).ok();
+    }
+
+    let mut leader = Leader::new();
+    for _ in 0..nb_leader {
+        leader.push(([read()?, read()?, read()?], read()?)).ok();
+    }
+
+    match bytes.next() {
+        Some(_) => Err(KeymapError::WrongSize),
+        None => Ok((layouts, combos, leader)),
+    }
+}
+
+fn encode(
+    layouts: &Layouts,
+    combos: &Combos,
+    leader: &Leader,
+) -> Result<Vec<u8, KEYMAP_MAX_SIZE>, KeymapError> {
+    let mut bytes = Vec::new();
+    bytes
+        .extend_from_slice(&[
+            layouts.len() as u8,
+            NB_KEYS as u8,
+            combos.len() as u8,
+            leader.len() as u8,
+        ])
+        .map_err(|_| KeymapError::TooLarge)?;
+
+    let keys = layouts
+        .iter()
+        .flatten()
+        .chain(
+            combos
+                .iter()
+                .flat_map(|(combo, new_key)| combo.iter().chain([new_key])),
+        )
+        .chain(
+            leader
+                .iter()
+                .flat_map(|(combination, new_key)| combination.iter().chain([new_key])),
+        );
+
+    for key in keys {
+        write_key(*key, &mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+fn write_key(key: KC, bytes: &mut Vec<u8, KEYMAP_MAX_SIZE>) -> Result<(), KeymapError> {
+    let (code, data): (u16, &[u16]) = match key {
+        KC::HomeRow(modifier, key) => (HOME_ROW, &[modifier.to_u16(), key.to_u16()]),
+        KC::MouseBtLock(button) => (MOUSE_BT_LOCK, &[button.to_u16()]),
+        KC::Media(media) => (MEDIA, &[media as u16]),
+        KC::Pad(pad) => (PAD, &[pad as u16]),
+        KC::Steno(steno) => (STENO, &[steno as u16]),
+        KC::Layout(number) => (LAYOUT, &[number as u16]),
+        KC::LaySet(number) => (LAY_SET, &[number as u16]),
+        KC::LayDead(number) => (LAY_DEAD, &[number as u16]),
+        KC::ViaMacro(number) => (VIA_MACRO, &[number as u16]),
+        KC::SettingUp(setting) => (SETTING_UP, &[setting as u16]),
+        KC::SettingDown(setting) => (SETTING_DOWN, &[setting as u16]),
+        key => (key.to_u16(), &[]),
+    };
+
+    for value in [code].iter().chain(data) {
+        bytes
+            .extend_from_slice(&value.to_le_bytes())
+            .map_err(|_| KeymapError::TooLarge)?;
+    }
+    Ok(())
+}
+
+fn read_u16<I: Iterator<Item = u8>>(bytes: &mut I) -> Result<u16, KeymapError> {
+    match (bytes.next(), bytes.next()) {
+        (Some(low), Some(high)) => Ok(u16::from_le_bytes([low, high])),
+        _ => Err(KeymapError::Truncated),
+    }
+}
+
+/// A key without data, as a static reference to be held by HomeRow or MouseBtLock.
+fn read_simple<I: Iterator<Item = u8>>(bytes: &mut I) -> Result<&'static KC, KeymapError> {
+    match read_u16(bytes)? {
+        0 => Ok(&KC::None),
+        code => match KC::from_u16_static(code) {
+            KC::None => Err(KeymapError::UnknownKey),
+            key => Ok(key),
+        },
+    }
+}
+
+fn read_key<I: Iterator<Item = u8>>(bytes: &mut I, nb_layouts: usize) -> Result<KC, KeymapError> {
+    Ok(match read_u16(bytes)? {
+        HOME_ROW => KC::HomeRow(read_simple(bytes)?, read_simple(bytes)?),
+        MOUSE_BT_LOCK => KC::MouseBtLock(read_simple(bytes)?),
+        MEDIA => KC::Media(Media::from_u16(read_u16(bytes)?).ok_or(KeymapError::UnknownKey)?),
+        PAD => KC::Pad(Pad::from_u16(read_u16(bytes)?).ok_or(KeymapError::UnknownKey)?),
+        STENO => KC::Steno(Steno::from_u16(read_u16(bytes)?).ok_or(KeymapError::UnknownKey)?),
+        code @ (LAYOUT | LAY_SET | LAY_DEAD) => {
+            let number = read_u16(bytes)? as usize;
+            if number >= nb_layouts {
+                return Err(KeymapError::WrongLayout);
+            }
+
+            match code {
+                LAYOUT => KC::Layout(number),
+                LAY_SET => KC::LaySet(number),
+                _ => KC::LayDead(number),
+            }
+        }
+        VIA_MACRO => match read_u16(bytes)? as usize {
+            number if number < VIA_MACRO_COUNT => KC::ViaMacro(number as u8),
+            _ => return Err(KeymapError::UnknownKey),
+        },
+        code @ (SETTING_UP | SETTING_DOWN) => {
+            let setting = Setting::from_u16(read_u16(bytes)?).ok_or(KeymapError::UnknownKey)?;
+            match code {
+                SETTING_UP => KC::SettingUp(setting),
+                _ => KC::SettingDown(setting),
+            }
+        }
+        0 => KC::None,
+        code => match KC::from_u16(code) {
+            KC::None => return Err(KeymapError::UnknownKey),
+            key => key,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::software::keys::Pad;
+
+    // chew.keymap compiled by the keymap tool (see build.rs)
+    const UPLOADED: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/keymap.bin"));
+
+    #[test]
+    fn uploaded_keymap_is_the_built_one() {
+        let (layouts, combos, leader) = decode(UPLOADED.iter().copied()).unwrap();
+        assert_eq!(layouts.as_slice(), LAYOUTS.as_slice());
+        assert_eq!(combos.as_slice(), COMBOS.as_slice());
+        assert_eq!(leader.as_slice(), LEADER_KEY_COMBINATIONS.as_slice());
+
+        // And VIA saves the same bytes
+        let bytes = encode(&layouts, &combos, &leader).unwrap();
+        assert_eq!(bytes.as_slice(), UPLOADED);
+    }
+
+    #[test]
+    fn keys_with_data() {
+        let mut keys = [KC::None; NB_KEYS];
+        keys[..8].copy_from_slice(&[
+            KC::HomeRow(&KC::Ctl, &KC::Esc),
+            KC::MouseBtLock(&KC::MouseBtRight),
+            KC::Pad(Pad::Bt10),
+            KC::Steno(Steno::Star),
+            KC::ViaMacro(15),
+            KC::SettingUp(Setting::HoldTime),
+            KC::SettingDown(Setting::MouseSpeed),
+            KC::LayDead(1),
+        ]);
+
+        let layouts: Layouts = [keys, [KC::LaySet(0); NB_KEYS]].into_iter().collect();
+        let combos: Combos = [([KC::A, KC::B], KC::Layout(1))].into_iter().collect();
+        let leader: Leader = [([KC::C, KC::None, KC::None], KC::Media(Media::Mute))]
+            .into_iter()
+            .collect();
+
+        let bytes = encode(&layouts, &combos, &leader).unwrap();
+        assert_eq!(decode(bytes.iter().copied()), Ok((layouts, combos, leader)));
+    }
+
+    #[test]
+    fn wrong_keymaps() {
+        let layouts: Layouts = [[KC::Layout(1); NB_KEYS]; 2].into_iter().collect();
+        let bytes = encode(&layouts, &Combos::new(), &Leader::new()).unwrap();
+        assert!(decode(bytes.iter().copied()).is_ok());
+
+        // Layout 1 doesn't exist anymore
+        let mut one = bytes.clone();
+        one[0] = 1;
+        one.truncate(4 + NB_KEYS * 4);
+        assert_eq!(decode(one.iter().copied()), Err(KeymapError::WrongLayout));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(
+            decode(truncated.iter().copied()),
+            Err(KeymapError::Truncated)
+        );
+
+        let mut longer = bytes.clone();
+        longer.push(0).unwrap();
+        assert_eq!(decode(longer.iter().copied()), Err(KeymapError::WrongSize));
+
+        let mut other_board = bytes.clone();
+        other_board[1] = NB_KEYS as u8 + 1;
+        assert_eq!(
+            decode(other_board.iter().copied()),
+            Err(KeymapError::WrongSize)
+        );
+
+        let mut unknown = bytes.clone();
+        unknown[4..6].copy_from_slice(&0xFFFE_u16.to_le_bytes());
+        assert_eq!(
+            decode(unknown.iter().copied()),
+            Err(KeymapError::UnknownKey)
+        );
+    }
+}
@@ -2,7 +2,9 @@
 use heapless::{Deque, Vec};
 use usbd_human_interface_device::page::Keyboard;
 
-use crate::options::{BUFFER_CASE_LENGTH, BUFFER_LENGTH, TEMPO_DEAD_KEY};
+use crate::options::{
+    BUFFER_CASE_LENGTH, BUFFER_LENGTH, TEMPO_DEAD_KEY, TEMPO_MACRO, TEMPO_MACRO_LONG,
+};
 use super::{macros::str_to_usb_code, modifiers::Modifiers};
 
 // --------------------------------------------------------------------------------------
@@ -20,13 +22,28 @@ pub struct BuffCase {
     pub tempo: u32,
 }
 
+// Macro_tempo is the minimal tempo of each macro step, it can be adjusted at runtime.
 pub struct Buffer {
     pub keys: Deque<BuffCase, BUFFER_LENGTH>,
+    pub macro_tempo: u32,
+    pub dead_tempo: u32,
 }
 
 impl Buffer {
     pub fn new() -> Self {
-        Buffer { keys: Deque::new() }
+        Buffer {
+            keys: Deque::new(),
+            macro_tempo: TEMPO_MACRO,
+            dead_tempo: TEMPO_DEAD_KEY,
+        }
+    }
+
+    // Give a minimal tempo to the entries added since 'from' (slow hosts can drop macro keys).
+    pub fn slow_down(&mut self, from: usize, tempo: u32) {
+        self.keys
+            .iter_mut()
+            .skip(from)
+            .for_each(|case| case.tempo = case.tempo.max(tempo));
     }
 
     // Add a new entry in the Deque with active mods (without excluded), the keys and set the tempo.
@@ -49,10 +66,152 @@ impl Buffer {
     fn add_no_mods(self, keys: &[Keyboard], tempo: u32) -> Self {
         self.add(keys, &Modifiers::new(), &[], tempo)
     }
+
+    // Press and release a key without any mod (e.g. the host's Caps Lock).
+    pub fn tap(self, key: Keyboard) -> Self {
+        self.add_no_mods(&[key], 0)
+            .add_no_mods(&[Keyboard::NoEventIndicated], 0)
+    }
 }
 
 // --------------------------------------------------------------------------------------
 
+/// Consumer control usages (sent with the consumer report, see hid_consumer.rs).
+#[rustfmt::skip]
+#[allow(dead_code)]
+#[repr(u16)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Media {
+    BrightnessUp = 0x6F,
+    BrightnessDown = 0x70,
+    Next = 0xB5,
+    Previous = 0xB6,
+    Stop = 0xB7,
+    PlayPause = 0xCD,
+    Mute = 0xE2,
+    VolumeUp = 0xE9,
+    VolumeDown = 0xEA,
+    Calculator = 0x192,
+    Browser = 0x196,
+    Search = 0x221,
+    Home = 0x223,
+}
+
+/// Gamepad buttons (bit index in the report) and hat directions (see hid_gamepad.rs).
+#[rustfmt::skip]
+#[allow(dead_code)]
+#[repr(u16)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Pad {
+    Bt1 = 0,
+    Bt2 = 1,
+    Bt3 = 2,
+    Bt4 = 3,
+    Bt5 = 4,
+    Bt6 = 5,
+    Bt7 = 6,
+    Bt8 = 7,
+    Bt9 = 8,
+    Bt10 = 9,
+    Bt11 = 10,
+    Bt12 = 11,
+    Bt13 = 12,
+    Bt14 = 13,
+    Bt15 = 14,
+    Bt16 = 15,
+    Up = 16,
+    Down = 17,
+    Left = 18,
+    Right = 19,
+}
+
+/// Steno keys in the TX Bolt order, their value is the bit in the chord (see steno.rs).
+#[rustfmt::skip]
+#[allow(dead_code)]
+#[repr(u8)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Steno {
+    SL = 0, TL = 1, KL = 2, PL = 3, WL = 4, HL = 5,
+    RL = 6, A = 7, O = 8, Star = 9, E = 10, U = 11,
+    FR = 12, RR = 13, PR = 14, BR = 15, LR = 16, GR = 17,
+    TR = 18, SR = 19, DR = 20, ZR = 21, Num = 22,
+}
+
+/// Runtime options adjusted with KC::SettingUp/SettingDown (see settings.rs).
+#[rustfmt::skip]
+#[allow(dead_code)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Setting {
+    HoldTime,
+    ComboTime,
+    DeadKeyTempo,
+    MouseSpeed,
+    ScrollSpeed,
+    UsbLoop,
+}
+
+impl Media {
+    pub fn from_u16(value: u16) -> Option<Media> {
+        [
+            Media::BrightnessUp,
+            Media::BrightnessDown,
+            Media::Next,
+            Media::Previous,
+            Media::Stop,
+            Media::PlayPause,
+            Media::Mute,
+            Media::VolumeUp,
+            Media::VolumeDown,
+            Media::Calculator,
+            Media::Browser,
+            Media::Search,
+            Media::Home,
+        ]
+        .into_iter()
+        .find(|m| *m as u16 == value)
+    }
+}
+
+impl Pad {
+    pub fn from_u16(value: u16) -> Option<Pad> {
+        #[rustfmt::skip]
+        const PADS: [Pad; 20] = [
+            Pad::Bt1, Pad::Bt2, Pad::Bt3, Pad::Bt4, Pad::Bt5, Pad::Bt6, Pad::Bt7, Pad::Bt8,
+            Pad::Bt9, Pad::Bt10, Pad::Bt11, Pad::Bt12, Pad::Bt13, Pad::Bt14, Pad::Bt15, Pad::Bt16,
+            Pad::Up, Pad::Down, Pad::Left, Pad::Right,
+        ];
+        PADS.get(value as usize).copied()
+    }
+}
+
+impl Steno {
+    pub fn from_u16(value: u16) -> Option<Steno> {
+        #[rustfmt::skip]
+        const STENO: [Steno; 23] = [
+            Steno::SL, Steno::TL, Steno::KL, Steno::PL, Steno::WL, Steno::HL,
+            Steno::RL, Steno::A, Steno::O, Steno::Star, Steno::E, Steno::U,
+            Steno::FR, Steno::RR, Steno::PR, Steno::BR, Steno::LR, Steno::GR,
+            Steno::TR, Steno::SR, Steno::DR, Steno::ZR, Steno::Num,
+        ];
+        STENO.get(value as usize).copied()
+    }
+}
+
+impl Setting {
+    pub fn from_u16(value: u16) -> Option<Setting> {
+        [
+            Setting::HoldTime,
+            Setting::ComboTime,
+            Setting::DeadKeyTempo,
+            Setting::MouseSpeed,
+            Setting::ScrollSpeed,
+            Setting::UsbLoop,
+        ]
+        .get(value as usize)
+        .copied()
+    }
+}
+
 #[rustfmt::skip]
 #[allow(dead_code)]
 #[repr(u16)]
@@ -247,6 +406,7 @@ pub enum KC {
     MouseBtLeft = 30000,
     MouseBtMiddle = 30001,
     MouseBtRight = 30002,
+    MouseBtLock(&'static KC) = 30003, // Toggle a button (e.g. to drag)
         MouseLeft = 30020,
         MouseDown = 30021,
         MouseUp = 30022,
@@ -259,6 +419,16 @@ pub enum KC {
         MouseSpeed2 = 30041,
         MouseSpeed3 = 30042,
         MouseSpeed4 = 30043,
+    MouseGrid = 30050,
+
+    Media(Media) = 31000,
+    SystemPower = 31010,
+    SystemSleep = 31011,
+    SystemWake = 31012,
+
+    Pad(Pad) = 32000,
+    Midi = 33000,
+    Steno(Steno) = 34000,
 
     Layout(usize) = 40000,
     LaySet(usize) = 40001,
@@ -268,6 +438,10 @@ pub enum KC {
         CapLock = 51001,
         DynMacRecord = 51002,
         DynMacGo = 51003,
+        DynMacGoFast = 51004,
+        DynMacAppend = 51005,
+        DynMacDelete = 51006,
+        DynMacList = 51007,
 
     MacroGit = 52001,
     MacroMail = 52002,
@@ -276,13 +450,20 @@ pub enum KC {
     MacroHTTPS = 52005,
     MacroDotfiles = 52006,
     MacroNixOS = 52007,
+    ViaMacro(u8) = 52100, // Written with VIA (see via.rs)
 
         ToggleBuzzer = 60000,
+        MacroTempoUp = 60001,
+        MacroTempoDown = 60002,
+        ToggleNkro = 60003,
+        SettingUp(Setting) = 60010,
+        SettingDown(Setting) = 60011,
 }
 
 impl KC {
     /// Convert a Chew keycode into an array of Keyboard page.
     pub fn usb_code(&self, buffer: Buffer, mods: &Modifiers) -> Buffer {
+        let dead = buffer.dead_tempo;
         match *self {
             KC::None => buffer.add_simple(&[Keyboard::NoEventIndicated], mods),
 
@@ -429,29 +610,29 @@ impl KC {
             KC::F24 => buffer.add_simple(&[Keyboard::F24], mods),
 
             // --
-            KC::ACircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, TEMPO_DEAD_KEY).add_simple(&[Keyboard::A], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::ADiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    TEMPO_DEAD_KEY).add_simple(&[Keyboard::A], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::AGrave  => buffer.add_no_mods(&DEAD_GRAVE,      TEMPO_DEAD_KEY).add_simple(&[Keyboard::A], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::ACircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, dead).add_simple(&[Keyboard::A], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::ADiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    dead).add_simple(&[Keyboard::A], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::AGrave  => buffer.add_no_mods(&DEAD_GRAVE,      dead).add_simple(&[Keyboard::A], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
 
-            KC::ECircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, TEMPO_DEAD_KEY).add_simple(&[Keyboard::E], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::EDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    TEMPO_DEAD_KEY).add_simple(&[Keyboard::E], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::EGrave  => buffer.add_no_mods(&DEAD_GRAVE,      TEMPO_DEAD_KEY).add_simple(&[Keyboard::E], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::ECircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, dead).add_simple(&[Keyboard::E], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::EDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    dead).add_simple(&[Keyboard::E], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::EGrave  => buffer.add_no_mods(&DEAD_GRAVE,      dead).add_simple(&[Keyboard::E], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
 
-            KC::ICircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, TEMPO_DEAD_KEY).add_simple(&[Keyboard::I], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::IDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    TEMPO_DEAD_KEY).add_simple(&[Keyboard::I], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::IGrave  => buffer.add_no_mods(&DEAD_GRAVE,      TEMPO_DEAD_KEY).add_simple(&[Keyboard::I], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::ICircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, dead).add_simple(&[Keyboard::I], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::IDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    dead).add_simple(&[Keyboard::I], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::IGrave  => buffer.add_no_mods(&DEAD_GRAVE,      dead).add_simple(&[Keyboard::I], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
 
-            KC::OCircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, TEMPO_DEAD_KEY).add_simple(&[Keyboard::O], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::ODiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    TEMPO_DEAD_KEY).add_simple(&[Keyboard::O], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::OGrave  => buffer.add_no_mods(&DEAD_GRAVE,      TEMPO_DEAD_KEY).add_simple(&[Keyboard::O], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::OCircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, dead).add_simple(&[Keyboard::O], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::ODiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    dead).add_simple(&[Keyboard::O], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::OGrave  => buffer.add_no_mods(&DEAD_GRAVE,      dead).add_simple(&[Keyboard::O], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
 
-            KC::UCircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, TEMPO_DEAD_KEY).add_simple(&[Keyboard::U], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::UDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    TEMPO_DEAD_KEY).add_simple(&[Keyboard::U], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::UGrave  => buffer.add_no_mods(&DEAD_GRAVE,      TEMPO_DEAD_KEY).add_simple(&[Keyboard::U], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::UCircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, dead).add_simple(&[Keyboard::U], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::UDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    dead).add_simple(&[Keyboard::U], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::UGrave  => buffer.add_no_mods(&DEAD_GRAVE,      dead).add_simple(&[Keyboard::U], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
 
-            KC::YCircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, TEMPO_DEAD_KEY).add_simple(&[Keyboard::Y], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::YDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    TEMPO_DEAD_KEY).add_simple(&[Keyboard::Y], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
-            KC::YGrave  => buffer.add_no_mods(&DEAD_GRAVE,      TEMPO_DEAD_KEY).add_simple(&[Keyboard::Y], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::YCircum => buffer.add_no_mods(&DEAD_CIRCUMFLEX, dead).add_simple(&[Keyboard::Y], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::YDiaer  => buffer.add_no_mods(&DEAD_DIAERIS,    dead).add_simple(&[Keyboard::Y], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
+            KC::YGrave  => buffer.add_no_mods(&DEAD_GRAVE,      dead).add_simple(&[Keyboard::Y], mods).add_no_mods(&[Keyboard::NoEventIndicated], 0),
 
             KC::Qu   => buffer.add(&[Keyboard::Q, Keyboard::U], mods, &[Keyboard::LeftAlt, Keyboard::RightAlt, Keyboard::LeftGUI, Keyboard::LeftControl], 0).add_no_mods(&[Keyboard::NoEventIndicated], 0),
             KC::Tion => buffer.add(&[Keyboard::T], mods, &[Keyboard::LeftAlt, Keyboard::RightAlt, Keyboard::LeftGUI, Keyboard::LeftControl], 0)
@@ -471,15 +652,330 @@ impl KC {
 
             // --
             // Use ↵ to enter
-            KC::MacroGit       => str_to_usb_code("flinguenheld",                              buffer),
-            KC::MacroMailShort => str_to_usb_code("f@linguenheld.net",                         buffer),
-            KC::MacroMail      => str_to_usb_code("florent@linguenheld.net",                   buffer),
-            KC::MacroMailEnd   => str_to_usb_code("@linguenheld.net",                          buffer),
-            KC::MacroHTTPS     => str_to_usb_code("https://",                                  buffer),
-            KC::MacroDotfiles  => str_to_usb_code("https://github.com/flinguenheld/dotfiles",  buffer),
-            KC::MacroNixOS     => str_to_usb_code("sudo nixos-rebuild switch --flake .#flopc", buffer),
+            // The tempo is the minimal one for this macro (the buffer's macro tempo can be higher)
+            KC::MacroGit       => str_to_usb_code("flinguenheld",                              0,                buffer),
+            KC::MacroMailShort => str_to_usb_code("f@linguenheld.net",                         0,                buffer),
+            KC::MacroMail      => str_to_usb_code("florent@linguenheld.net",                   0,                buffer),
+            KC::MacroMailEnd   => str_to_usb_code("@linguenheld.net",                          0,                buffer),
+            KC::MacroHTTPS     => str_to_usb_code("https://",                                  0,                buffer),
+            KC::MacroDotfiles  => str_to_usb_code("https://github.com/flinguenheld/dotfiles",  TEMPO_MACRO_LONG, buffer),
+            KC::MacroNixOS     => str_to_usb_code("sudo nixos-rebuild switch --flake .#flopc", TEMPO_MACRO_LONG, buffer),
 
             _ => buffer,
         }
     }
+
+    /// Numeric value of a keycode, variants with data give their base value (HomeRow gives its regular key).
+    pub fn to_u16(self) -> u16 {
+        match self {
+            KC::HomeRow(_, k) => k.to_u16(),
+            // Safety: KC is repr(u16) so each variant starts with its discriminant
+            _ => unsafe { *(&self as *const Self as *const u16) },
+        }
+    }
+
+    /// Keycode from its numeric value, only the variants without data are handled.
+    pub fn from_u16(value: u16) -> KC {
+        *KC::from_u16_static(value)
+    }
+
+    /// Same as from_u16, the static reference can be held by HomeRow or MouseBtLock.
+    pub fn from_u16_static(value: u16) -> &'static KC {
+        match value {
+            0     => &KC::None,
+            1     => &KC::Done,
+            2     => &KC::DoneButKeep,
+            10    => &KC::A,
+            11    => &KC::B,
+            12    => &KC::C,
+            13    => &KC::D,
+            14    => &KC::E,
+            15    => &KC::F,
+            16    => &KC::G,
+            17    => &KC::H,
+            18    => &KC::I,
+            19    => &KC::J,
+            20    => &KC::K,
+            21    => &KC::L,
+            22    => &KC::M,
+            23    => &KC::N,
+            24    => &KC::O,
+            25    => &KC::P,
+            26    => &KC::Q,
+            27    => &KC::R,
+            28    => &KC::S,
+            29    => &KC::T,
+            30    => &KC::U,
+            31    => &KC::V,
+            32    => &KC::W,
+            33    => &KC::X,
+            34    => &KC::Y,
+            35    => &KC::Z,
+            100   => &KC::CCedilla,
+            101   => &KC::EAcute,
+            102   => &KC::AE,
+            103   => &KC::OE,
+            500   => &KC::Enter,
+            501   => &KC::Space,
+            502   => &KC::Esc,
+            503   => &KC::Del,
+            504   => &KC::BackSpace,
+            505   => &KC::Tab,
+            506   => &KC::STab,
+            507   => &KC::Home,
+            508   => &KC::End,
+            509   => &KC::PageUp,
+            510   => &KC::PageDown,
+            600   => &KC::Left,
+            601   => &KC::Down,
+            602   => &KC::Up,
+            603   => &KC::Right,
+            800   => &KC::Num0,
+            801   => &KC::Num1,
+            802   => &KC::Num2,
+            803   => &KC::Num3,
+            804   => &KC::Num4,
+            805   => &KC::Num5,
+            806   => &KC::Num6,
+            807   => &KC::Num7,
+            808   => &KC::Num8,
+            809   => &KC::Num9,
+            1000  => &KC::Minus,
+            1001  => &KC::Equal,
+            1002  => &KC::LeftBracket,
+            1003  => &KC::RightBracket,
+            1004  => &KC::Backslash,
+            1005  => &KC::NonusHash,
+            1006  => &KC::SemiColon,
+            1007  => &KC::Quote,
+            1008  => &KC::Grave,
+            1009  => &KC::Comma,
+            1010  => &KC::Dot,
+            1011  => &KC::Slash,
+            1012  => &KC::NonusBackslash,
+            2000  => &KC::Tilde,
+            2001  => &KC::Exclaim,
+            2002  => &KC::At,
+            2003  => &KC::Hash,
+            2004  => &KC::Dollar,
+            2005  => &KC::Percentage,
+            2006  => &KC::Circumflex,
+            2007  => &KC::Ampersand,
+            2008  => &KC::Asterix,
+            2009  => &KC::LeftParent,
+            2010  => &KC::RightParent,
+            2011  => &KC::Underscore,
+            2012  => &KC::Plus,
+            2013  => &KC::LeftCurly,
+            2014  => &KC::RightCurly,
+            2015  => &KC::Pipe,
+            2016  => &KC::Colon,
+            2017  => &KC::DoubleQuote,
+            2018  => &KC::LowerThan,
+            2019  => &KC::GreaterThan,
+            2020  => &KC::Question,
+            3021  => &KC::GuillemetL,
+            3022  => &KC::GuillemetD,
+            3023  => &KC::Diameter,
+            3024  => &KC::Degre,
+            3025  => &KC::Euro,
+            3026  => &KC::Pound,
+            3027  => &KC::Copyright,
+            3028  => &KC::Registered,
+            3029  => &KC::ExpOne,
+            3030  => &KC::ExpTwo,
+            3031  => &KC::ExpThree,
+            3032  => &KC::Pilcrow,
+            3033  => &KC::Multi,
+            3034  => &KC::Div,
+            3035  => &KC::Beta,
+            3036  => &KC::Quarter,
+            3037  => &KC::Half,
+            3038  => &KC::ThreeQuarter,
+            3039  => &KC::Yen,
+            4000  => &KC::F1,
+            4001  => &KC::F2,
+            4002  => &KC::F3,
+            4003  => &KC::F4,
+            4004  => &KC::F5,
+            4005  => &KC::F6,
+            4006  => &KC::F7,
+            4007  => &KC::F8,
+            4008  => &KC::F9,
+            4009  => &KC::F10,
+            4010  => &KC::F11,
+            4011  => &KC::F12,
+            4012  => &KC::F13,
+            4013  => &KC::F14,
+            4014  => &KC::F15,
+            4015  => &KC::F16,
+            4016  => &KC::F17,
+            4017  => &KC::F18,
+            4018  => &KC::F19,
+            4019  => &KC::F20,
+            4020  => &KC::F21,
+            4021  => &KC::F22,
+            4022  => &KC::F23,
+            4023  => &KC::F24,
+            5000  => &KC::ACircum,
+            5001  => &KC::AGrave,
+            5002  => &KC::ADiaer,
+            5003  => &KC::ECircum,
+            5004  => &KC::EGrave,
+            5005  => &KC::EDiaer,
+            5006  => &KC::ICircum,
+            5007  => &KC::IGrave,
+            5008  => &KC::IDiaer,
+            5009  => &KC::OCircum,
+            5010  => &KC::OGrave,
+            5011  => &KC::ODiaer,
+            5012  => &KC::UCircum,
+            5013  => &KC::UGrave,
+            5014  => &KC::UDiaer,
+            5015  => &KC::YCircum,
+            5016  => &KC::YGrave,
+            5017  => &KC::YDiaer,
+            5050  => &KC::Qu,
+            5051  => &KC::Tion,
+            10000 => &KC::Alt,
+            10001 => &KC::Altgr,
+            10002 => &KC::Ctl,
+            10003 => &KC::Gui,
+            10004 => &KC::Sft,
+            20000 => &KC::DeadCircumflex,
+            20001 => &KC::DeadDiaeris,
+            20002 => &KC::DeadGrave,
+            30000 => &KC::MouseBtLeft,
+            30001 => &KC::MouseBtMiddle,
+            30002 => &KC::MouseBtRight,
+            30020 => &KC::MouseLeft,
+            30021 => &KC::MouseDown,
+            30022 => &KC::MouseUp,
+            30023 => &KC::MouseRight,
+            30030 => &KC::MouseWheelLeft,
+            30031 => &KC::MouseWheelDown,
+            30032 => &KC::MouseWheelUp,
+            30033 => &KC::MouseWheelRight,
+            30040 => &KC::MouseSpeed1,
+            30041 => &KC::MouseSpeed2,
+            30042 => &KC::MouseSpeed3,
+            30043 => &KC::MouseSpeed4,
+            30050 => &KC::MouseGrid,
+            31010 => &KC::SystemPower,
+            31011 => &KC::SystemSleep,
+            31012 => &KC::SystemWake,
+            33000 => &KC::Midi,
+            51000 => &KC::LeaderKey,
+            51001 => &KC::CapLock,
+            51002 => &KC::DynMacRecord,
+            51003 => &KC::DynMacGo,
+            51004 => &KC::DynMacGoFast,
+            51005 => &KC::DynMacAppend,
+            51006 => &KC::DynMacDelete,
+            51007 => &KC::DynMacList,
+            52001 => &KC::MacroGit,
+            52002 => &KC::MacroMail,
+            52003 => &KC::MacroMailEnd,
+            52004 => &KC::MacroMailShort,
+            52005 => &KC::MacroHTTPS,
+            52006 => &KC::MacroDotfiles,
+            52007 => &KC::MacroNixOS,
+            60000 => &KC::ToggleBuzzer,
+            60001 => &KC::MacroTempoUp,
+            60002 => &KC::MacroTempoDown,
+            60003 => &KC::ToggleNkro,
+            _     => &KC::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{string::String, vec::Vec};
+
+    /// Values of the variants of `pub enum name` in this file and whether they hold data,
+    /// so a variant missing in the tables above can't go unnoticed.
+    fn variants(name: &str) -> Vec<(u16, bool)> {
+        let source = include_str!("keys.rs");
+        let body = source
+            .split_once(&std::format!("pub enum {} {{", name))
+            .unwrap()
+            .1;
+        let body = body.split("\n}").next().unwrap();
+
+        // Items separated by commas outside the parentheses, without the comments
+        let mut items = Vec::new();
+        let mut item = String::new();
+        let mut depth = 0;
+        for c in body.lines().flat_map(|l| l.split("//").next().unwrap().chars().chain([' '])) {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    items.push(core::mem::take(&mut item));
+                    continue;
+                }
+                _ => {}
+            }
+            item.push(c);
+        }
+        items.push(item);
+
+        let mut next = 0;
+        items
+            .iter()
+            .map(|i| i.trim())
+            .filter(|i| !i.is_empty())
+            .map(|i| {
+                let (head, value) = i.split_once('=').unwrap_or((i, ""));
+                let value = value.trim();
+                let value = match value.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16).unwrap(),
+                    None if value.is_empty() => next,
+                    None => value.parse().unwrap(),
+                };
+                next = value + 1;
+                (value, head.contains('('))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keycodes_round_trip() {
+        let variants = variants("KC");
+        assert!(variants.len() > 100);
+
+        for (value, data) in variants {
+            match data {
+                false => {
+                    assert_eq!(KC::from_u16(value).to_u16(), value, "from_u16({})", value);
+                    assert_eq!(KC::from_u16_static(value).to_u16(), value);
+                }
+                // Their data isn't known from the value alone
+                true => assert_eq!(KC::from_u16(value), KC::None),
+            }
+        }
+    }
+
+    #[test]
+    fn data_round_trip() {
+        // Each value of the enum comes back, the others are refused
+        fn check<T: Copy>(name: &str, from_u16: fn(u16) -> Option<T>, to_u16: fn(T) -> u16) {
+            let values: Vec<u16> = variants(name).iter().map(|(v, _)| *v).collect();
+            assert!(!values.is_empty());
+
+            for value in 0..=*values.iter().max().unwrap() + 1 {
+                match values.contains(&value) {
+                    true => assert_eq!(from_u16(value).map(to_u16), Some(value), "{} {}", name, value),
+                    false => assert!(from_u16(value).is_none(), "{} {}", name, value),
+                }
+            }
+        }
+
+        check("Media", Media::from_u16, |m| m as u16);
+        check("Pad", Pad::from_u16, |p| p as u16);
+        check("Steno", Steno::from_u16, |s| s as u16);
+        check("Setting", Setting::from_u16, |s| s as u16);
+    }
 }
@@ -5,7 +5,11 @@ use super::{
 
 /// Fill the buffer with all letters.
 /// Check the letter list below.
-pub fn str_to_usb_code(text: &str, mut buffer: Buffer) -> Buffer {
+/// Each step waits at least tempo ms or the buffer's macro tempo if it is higher.
+pub fn str_to_usb_code(text: &str, tempo: u32, mut buffer: Buffer) -> Buffer {
+    let tempo = tempo.max(buffer.macro_tempo);
+    let start = buffer.keys.len();
+
     let mut last = ' ';
     for c in text.chars() {
         if c == last {
@@ -16,7 +20,9 @@ pub fn str_to_usb_code(text: &str, mut buffer: Buffer) -> Buffer {
         buffer = char_to_usb_code(c, buffer);
     }
 
-    KC::None.usb_code(buffer, &Modifiers::new())
+    buffer = KC::None.usb_code(buffer, &Modifiers::new());
+    buffer.slow_down(start, tempo);
+    buffer
 }
 
 /// Add in the buffer the KC value of "symbol".
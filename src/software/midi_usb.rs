@@ -0,0 +1,132 @@
+use heapless::Vec;
+use usb_device::class_prelude::*;
+use usb_device::Result;
+
+use crate::options::MIDI_VELOCITY;
+
+const AUDIO_CLASS: u8 = 0x01;
+const AUDIO_CONTROL_SUBCLASS: u8 = 0x01;
+const MIDI_STREAMING_SUBCLASS: u8 = 0x03;
+
+const CS_INTERFACE: u8 = 0x24;
+const CS_ENDPOINT: u8 = 0x25;
+
+const MIDI_IN_JACK: u8 = 0x02;
+const MIDI_OUT_JACK: u8 = 0x03;
+const EMBEDDED: u8 = 0x01;
+const EXTERNAL: u8 = 0x02;
+
+const IN_JACK_ID: u8 = 0x01;
+const OUT_JACK_ID: u8 = 0x02;
+
+const MAX_PACKET_SIZE: u16 = 64;
+
+#[derive(Clone, Copy)]
+pub struct MidiEvent {
+    pub note: u8,
+    pub on: bool,
+}
+
+impl MidiEvent {
+    /// USB MIDI event packet (cable 0, channel 1).
+    fn packet(&self) -> [u8; 4] {
+        match self.on {
+            true => [0x09, 0x90, self.note & 0x7F, MIDI_VELOCITY],
+            false => [0x08, 0x80, self.note & 0x7F, 0],
+        }
+    }
+}
+
+/// Minimal USB MIDI device (Audio Control + MIDI Streaming), it only sends to the host.
+/// One embedded OUT jack fed by an external IN jack, on a bulk IN endpoint.
+pub struct MidiClass<'a, B: UsbBus> {
+    audio_control: InterfaceNumber,
+    midi_streaming: InterfaceNumber,
+    ep_in: EndpointIn<'a, B>,
+}
+
+impl<'a, B: UsbBus> MidiClass<'a, B> {
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        MidiClass {
+            audio_control: alloc.interface(),
+            midi_streaming: alloc.interface(),
+            ep_in: alloc.bulk(MAX_PACKET_SIZE),
+        }
+    }
+
+    /// Send all the events at once (16 max), they are lost if the host doesn't read them.
+    pub fn send(&mut self, events: &[MidiEvent]) -> Result<usize> {
+        let data: Vec<u8, { MAX_PACKET_SIZE as usize }> = events
+            .iter()
+            .take(MAX_PACKET_SIZE as usize / 4)
+            .flat_map(|event| event.packet())
+            .collect();
+
+        self.ep_in.write(&data)
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for MidiClass<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+        writer.iad(
+            self.audio_control,
+            2,
+            AUDIO_CLASS,
+            AUDIO_CONTROL_SUBCLASS,
+            0x00,
+            None,
+        )?;
+
+        // Audio control, header only --
+        writer.interface(
+            self.audio_control,
+            AUDIO_CLASS,
+            AUDIO_CONTROL_SUBCLASS,
+            0x00,
+        )?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                0x01,
+                0x00,
+                0x01,
+                0x09,
+                0x00,
+                0x01,
+                self.midi_streaming.into(),
+            ],
+        )?;
+
+        // MIDI streaming --
+        // Total length: header (7), jacks (6 + 9), endpoint (9 + 5)
+        writer.interface(
+            self.midi_streaming,
+            AUDIO_CLASS,
+            MIDI_STREAMING_SUBCLASS,
+            0x00,
+        )?;
+        writer.write(CS_INTERFACE, &[0x01, 0x00, 0x01, 36, 0x00])?;
+        writer.write(CS_INTERFACE, &[MIDI_IN_JACK, EXTERNAL, IN_JACK_ID, 0x00])?;
+        writer.write(
+            CS_INTERFACE,
+            &[
+                MIDI_OUT_JACK,
+                EMBEDDED,
+                OUT_JACK_ID,
+                0x01,
+                IN_JACK_ID,
+                0x01,
+                0x00,
+            ],
+        )?;
+
+        // Audio endpoints have two more bytes (refresh & synch address)
+        writer.endpoint_ex(&self.ep_in, |data| {
+            data[..2].copy_from_slice(&[0x00, 0x00]);
+            Ok(2)
+        })?;
+        writer.write(CS_ENDPOINT, &[0x01, 0x01, OUT_JACK_ID])?;
+
+        Ok(())
+    }
+}
@@ -2,12 +2,13 @@
 use heapless::Vec;
 use usbd_human_interface_device::page::Keyboard;
 
-use crate::options::NB_KEYS;
+use crate::options::{CAPLOCK_HOST, NB_KEYS};
 
 use super::{chew::Key, keys::KC};
 
 /// Due to layers modifiers have to be manage with their matrix index directly.
 /// This struct keeps the matrix index for each modifier.
+/// Caplock holds shift, unless the host's Caps Lock is used (CAPLOCK_HOST).
 #[derive(Clone)]
 pub struct Modifiers {
     pub alt:     usize,
@@ -49,7 +50,7 @@ impl Modifiers {
         if self.ctrl   != usize::MAX { output.push(Keyboard::LeftControl).ok(); }
         if self.gui    != usize::MAX { output.push(Keyboard::LeftGUI).ok(); }
         if self.shift  != usize::MAX { output.push(Keyboard::LeftShift).ok(); }
-        if self.caplock              { output.push(Keyboard::LeftShift).ok(); }
+        if self.caplock && !CAPLOCK_HOST { output.push(Keyboard::LeftShift).ok(); }
 
         output
     }
@@ -61,11 +62,37 @@ impl Modifiers {
         if self.ctrl    != usize::MAX { output.push((KC::Ctl,   self.ctrl)).ok(); }
         if self.gui     != usize::MAX { output.push((KC::Gui,   self.gui)).ok(); }
         if self.shift   != usize::MAX { output.push((KC::Sft,   self.shift)).ok(); }
-        if self.caplock               { output.push((KC::Sft,   usize::MAX)).ok(); }
+        if self.caplock && !CAPLOCK_HOST { output.push((KC::Sft, usize::MAX)).ok(); }
 
         output
     }    
 
+    /// Active mods as a bitmask (alt, alt_gr, ctrl, gui, shift, caplock) to save them.
+    pub fn to_bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.alt    != usize::MAX { bits |= 0b000001; }
+        if self.alt_gr != usize::MAX { bits |= 0b000010; }
+        if self.ctrl   != usize::MAX { bits |= 0b000100; }
+        if self.gui    != usize::MAX { bits |= 0b001000; }
+        if self.shift  != usize::MAX { bits |= 0b010000; }
+        if self.caplock              { bits |= 0b100000; }
+
+        bits
+    }
+
+    /// Restored mods are not linked to a matrix index (0 is used).
+    pub fn from_bits(bits: u8) -> Modifiers {
+        let mut mods = Modifiers::new();
+        if bits & 0b000001 != 0 { mods.alt    = 0; }
+        if bits & 0b000010 != 0 { mods.alt_gr = 0; }
+        if bits & 0b000100 != 0 { mods.ctrl   = 0; }
+        if bits & 0b001000 != 0 { mods.gui    = 0; }
+        if bits & 0b010000 != 0 { mods.shift  = 0; }
+        mods.caplock = bits & 0b100000 != 0;
+
+        mods
+    }
+
     pub fn update_state(&mut self, pressed_keys: &Vec<Key, NB_KEYS>) {
         self.alt    = pressed_keys.iter().find(|k| k.code == KC::Alt  ).map(|k| k.index).unwrap_or(usize::MAX);
         self.alt_gr = pressed_keys.iter().find(|k| k.code == KC::Altgr).map(|k| k.index).unwrap_or(usize::MAX);
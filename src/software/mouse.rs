@@ -1,42 +1,105 @@
-use heapless::Vec;
-use usbd_human_interface_device::device::mouse::WheelMouseReport;
+use heapless::{Deque, Vec};
+use usbd_human_interface_device::device::mouse::{AbsoluteWheelMouseReport, WheelMouseReport};
 
-use super::{chew::Key, keys::KC};
+use super::{chew::Key, hid_mouse::SCROLL_MULTIPLIER, keys::KC};
 use crate::{
     hardware::matrix::Matrix,
     options::{
-        MOUSE_SPEED_1, MOUSE_SPEED_2, MOUSE_SPEED_3, MOUSE_SPEED_4, MOUSE_SPEED_DEFAULT,
-        SCROLL_SPEED_1, SCROLL_SPEED_2, SCROLL_SPEED_3, SCROLL_SPEED_4, SCROLL_SPEED_DEFAULT,
+        MOUSE_ACCEL, MOUSE_ACCEL_CURVE, MOUSE_ACCEL_DELAY, MOUSE_ACCEL_INITIAL,
+        MOUSE_ACCEL_INTERVAL, MOUSE_ACCEL_MAX, MOUSE_ACCEL_TIME_TO_MAX, MOUSE_INERTIA,
+        MOUSE_INERTIA_TIME, MOUSE_LOCK_TIMEOUT, MOUSE_SPEED_1, MOUSE_SPEED_2, MOUSE_SPEED_3,
+        MOUSE_SPEED_4, MOUSE_SPEED_DEFAULT, SCROLL_ACCEL, SCROLL_ACCEL_INITIAL,
+        SCROLL_ACCEL_INTERVAL, SCROLL_ACCEL_MAX, SCROLL_ACCEL_TIME_TO_MAX, SCROLL_SPEED_1,
+        SCROLL_SPEED_2, SCROLL_SPEED_3, SCROLL_SPEED_4, SCROLL_SPEED_DEFAULT,
     },
 };
 
 // Move i8 each u32 ms
 type Speed = (i8, u32);
 
+// Moves are computed in 1/16 px, the remainders are kept for the next ones
+const SUBPIXELS: i32 = 16;
+
+// 1/sqrt(2) in 1/256, applied on diagonals
+const DIAGONAL: i32 = 181;
+
+/// Speed after holding the direction keys for elapsed ms.
+/// It goes from initial to max in time_to_max following the curve (x^MOUSE_ACCEL_CURVE).
+fn accelerate(elapsed: u32, initial: u32, max: u32, time_to_max: u32) -> i32 {
+    // 0 -> 256 (at once without time)
+    let ratio = match time_to_max {
+        0 => 256,
+        time => elapsed.min(time) * 256 / time,
+    };
+    let curve = (1..MOUSE_ACCEL_CURVE).fold(ratio, |acc, _| acc * ratio / 256);
+
+    (initial + max.saturating_sub(initial) * curve / 256) as i32
+}
+
 /// Allows Chew to emulate the mouse.
 /// Speeds are saved by pressing order and have to be maintained to be effective.
+///
+/// Without speed key, the pointer accelerates while a direction is held (see MOUSE_ACCEL_*).
+/// With inertia, it keeps its last direction & slows down once the keys are released.
+///
+/// Directions held in a chew loop are combined and moved once in release(), diagonals are
+/// normalised. Speeds are in sub-pixels so the slow moves stay smooth.
+///
+/// Buttons can be locked (toggled) to drag without holding them, they are released after
+/// MOUSE_LOCK_TIMEOUT without any mouse activity.
+///
+/// Scrolls work the same way in 1/16 detent. They are sent as is if the host has enabled
+/// the resolution multiplier (smooth), otherwise only the whole detents are sent.
 pub struct Mouse {
     buttons: Vec<(usize, u8), 3>,
+    locked: u8,
+    lock_activity: u32,
     speeds: Vec<(usize, Speed, Speed), 4>,
 
-    move_tempo: u32,
-    move_ok: u32,
+    move_tempo: (u32, u32), // Last move & delay before the next one
+    move_start: Option<u32>,
+    move_last: u32,
+    direction: (i8, i8),
+    remainder: (i32, i32),
+    last_speed: i32,
+    inertia: Option<(u32, i32, (i8, i8))>, // Start, speed, direction
+
+    scroll_tempo: (u32, u32), // Last scroll & delay before the next one
+    scroll_start: Option<u32>,
+    scroll_last: u32,
+    wheels: (i8, i8), // Vertical, horizontal
+    wheels_remainder: (i32, i32),
+    pub smooth: bool,
+    pub speed: (u32, u32), // Mouse & scroll in % (runtime settings)
 
-    scroll_tempo: u32,
-    scroll_ok: u32,
+    pub grid: Grid,
 }
 
 impl Mouse {
     pub fn new() -> Self {
         Mouse {
             buttons: Vec::new(),
+            locked: 0,
+            lock_activity: 0,
             speeds: Vec::new(),
 
-            move_tempo: 0,
-            move_ok: 0,
+            move_tempo: (0, 0),
+            move_start: None,
+            move_last: u32::MAX,
+            direction: (0, 0),
+            remainder: (0, 0),
+            last_speed: 0,
+            inertia: None,
 
-            scroll_tempo: 0,
-            scroll_ok: 0,
+            scroll_tempo: (0, 0),
+            scroll_start: None,
+            scroll_last: u32::MAX,
+            wheels: (0, 0),
+            wheels_remainder: (0, 0),
+            smooth: false,
+            speed: (100, 100),
+
+            grid: Grid::new(),
         }
     }
 
@@ -49,52 +112,170 @@ impl Mouse {
                 _ => (key.index, MOUSE_SPEED_4, SCROLL_SPEED_4),
             })
             .ok();
-        self.scroll_tempo = 0;
     }
 
-    pub fn movement(&mut self, report: &mut WheelMouseReport, key: KC, ticks: u32) {
-        let (speed, tempo) = self.speeds.last().map_or(MOUSE_SPEED_DEFAULT, |s| s.1);
+    /// Save the direction held during this chew loop, the move is done in release().
+    pub fn movement(&mut self, key: KC, ticks: u32) {
+        if self.move_last != ticks {
+            self.move_last = ticks;
+            self.direction = (0, 0);
+        }
+
+        match key {
+            KC::MouseLeft => self.direction.0 -= 1,
+            KC::MouseDown => self.direction.1 += 1,
+            KC::MouseUp => self.direction.1 -= 1,
+            KC::MouseRight => self.direction.0 += 1,
+            _ => {}
+        }
+    }
+
+    /// Add the move to the report, the sub-pixels left are kept for the next one.
+    fn shift(&mut self, report: &mut WheelMouseReport, (x, y): (i8, i8), speed: i32) {
+        let speed = match x != 0 && y != 0 {
+            true => speed * DIAGONAL / 256,
+            false => speed,
+        };
 
-        // Move_ok is saved to allow several moves in the same chew loop.
-        if ticks >= self.move_tempo || self.move_ok == ticks {
-            self.move_tempo = ticks.wrapping_add(tempo);
-            self.move_ok = ticks;
+        self.remainder.0 += x.signum() as i32 * speed;
+        self.remainder.1 += y.signum() as i32 * speed;
 
-            match key {
-                KC::MouseLeft => report.x = i8::saturating_add(report.x, -speed),
-                KC::MouseDown => report.y = i8::saturating_add(report.y, speed),
-                KC::MouseUp => report.y = i8::saturating_add(report.y, -speed),
-                KC::MouseRight => report.x = i8::saturating_add(report.x, speed),
-                _ => {}
+        let moves = (self.remainder.0 / SUBPIXELS, self.remainder.1 / SUBPIXELS);
+        self.remainder.0 -= moves.0 * SUBPIXELS;
+        self.remainder.1 -= moves.1 * SUBPIXELS;
+
+        report.x = i8::saturating_add(report.x, moves.0.clamp(-127, 127) as i8);
+        report.y = i8::saturating_add(report.y, moves.1.clamp(-127, 127) as i8);
+    }
+
+    /// Move according to the directions held in this chew loop.
+    /// Once released, launch the inertia or stop the acceleration.
+    fn moves(&mut self, report: &mut WheelMouseReport, ticks: u32) {
+        if self.move_last == ticks {
+            self.inertia = None;
+
+            let start = *self.move_start.get_or_insert(ticks);
+            let (speed, tempo) = match self.speeds.last() {
+                Some((_, (speed, tempo), _)) => (*speed as i32 * SUBPIXELS, *tempo),
+                None if MOUSE_ACCEL => match ticks.wrapping_sub(start) {
+                    0 => (MOUSE_ACCEL_INITIAL as i32, MOUSE_ACCEL_DELAY),
+                    elapsed => (
+                        accelerate(
+                            elapsed.saturating_sub(MOUSE_ACCEL_DELAY),
+                            MOUSE_ACCEL_INITIAL,
+                            MOUSE_ACCEL_MAX,
+                            MOUSE_ACCEL_TIME_TO_MAX,
+                        ),
+                        MOUSE_ACCEL_INTERVAL,
+                    ),
+                },
+                None => (
+                    MOUSE_SPEED_DEFAULT.0 as i32 * SUBPIXELS,
+                    MOUSE_SPEED_DEFAULT.1,
+                ),
+            };
+            let speed = speed * self.speed.0 as i32 / 100;
+            self.last_speed = speed;
+
+            if ticks.wrapping_sub(self.move_tempo.0) >= self.move_tempo.1 {
+                self.move_tempo = (ticks, tempo);
+                self.shift(report, self.direction, speed);
             }
+            return;
         }
-    }
 
-    pub fn scroll(&mut self, report: &mut WheelMouseReport, key: KC, ticks: u32) {
-        let (speed, tempo) = self.speeds.last().map_or(SCROLL_SPEED_DEFAULT, |s| s.2);
+        if self.move_start.take().is_some() {
+            self.remainder = (0, 0);
+            if MOUSE_INERTIA && self.speeds.is_empty() {
+                self.inertia = Some((ticks, self.last_speed, self.direction));
+            }
+        }
 
-        if ticks >= self.scroll_tempo || self.scroll_ok == ticks {
-            self.scroll_tempo = ticks.wrapping_add(tempo);
-            self.scroll_ok = ticks;
+        if let Some((start, speed, direction)) = self.inertia {
+            let elapsed = ticks.wrapping_sub(start);
+            if elapsed >= MOUSE_INERTIA_TIME {
+                self.inertia = None;
+            } else if ticks.wrapping_sub(self.move_tempo.0) >= self.move_tempo.1 {
+                self.move_tempo = (ticks, MOUSE_ACCEL_INTERVAL);
 
-            match key {
-                KC::MouseWheelLeft => {
-                    report.horizontal_wheel = i8::saturating_add(report.horizontal_wheel, speed)
-                }
-                KC::MouseWheelDown => {
-                    report.vertical_wheel = i8::saturating_add(report.vertical_wheel, -speed)
-                }
-                KC::MouseWheelUp => {
-                    report.vertical_wheel = i8::saturating_add(report.vertical_wheel, speed)
-                }
-                KC::MouseWheelRight => {
-                    report.horizontal_wheel = i8::saturating_add(report.horizontal_wheel, -speed)
-                }
-                _ => {}
+                let left = (MOUSE_INERTIA_TIME - elapsed) as i32;
+                let speed = speed * left / MOUSE_INERTIA_TIME as i32;
+                self.shift(report, direction, speed);
             }
         }
     }
 
+    /// Save the wheel direction held during this chew loop, the scroll is done in release().
+    pub fn scroll(&mut self, key: KC, ticks: u32) {
+        if self.scroll_last != ticks {
+            self.scroll_last = ticks;
+            self.wheels = (0, 0);
+        }
+
+        match key {
+            KC::MouseWheelLeft => self.wheels.1 += 1,
+            KC::MouseWheelDown => self.wheels.0 -= 1,
+            KC::MouseWheelUp => self.wheels.0 += 1,
+            KC::MouseWheelRight => self.wheels.1 -= 1,
+            _ => {}
+        }
+    }
+
+    /// Scroll according to the wheels held in this chew loop.
+    fn scrolls(&mut self, report: &mut WheelMouseReport, ticks: u32) {
+        if self.scroll_last != ticks {
+            self.scroll_start = None;
+            self.wheels_remainder = (0, 0);
+            return;
+        }
+
+        let start = *self.scroll_start.get_or_insert(ticks);
+        let (speed, tempo) = match self.speeds.last() {
+            Some((_, _, (speed, tempo))) => (*speed as i32 * SCROLL_MULTIPLIER, *tempo),
+            None if SCROLL_ACCEL => (
+                accelerate(
+                    ticks.wrapping_sub(start),
+                    SCROLL_ACCEL_INITIAL,
+                    SCROLL_ACCEL_MAX,
+                    SCROLL_ACCEL_TIME_TO_MAX,
+                ),
+                SCROLL_ACCEL_INTERVAL,
+            ),
+            None => (
+                SCROLL_SPEED_DEFAULT.0 as i32 * SCROLL_MULTIPLIER,
+                SCROLL_SPEED_DEFAULT.1,
+            ),
+        };
+
+        let speed = speed * self.speed.1 as i32 / 100;
+
+        // First one is immediate
+        if start != ticks && ticks.wrapping_sub(self.scroll_tempo.0) < self.scroll_tempo.1 {
+            return;
+        }
+        self.scroll_tempo = (ticks, tempo);
+
+        self.wheels_remainder.0 += self.wheels.0.signum() as i32 * speed;
+        self.wheels_remainder.1 += self.wheels.1.signum() as i32 * speed;
+
+        // Without multiplier, the remainder is kept until it reaches a whole detent
+        let unit = match self.smooth {
+            true => 1,
+            false => SCROLL_MULTIPLIER,
+        };
+        let scrolls = (
+            self.wheels_remainder.0 / unit,
+            self.wheels_remainder.1 / unit,
+        );
+        self.wheels_remainder.0 -= scrolls.0 * unit;
+        self.wheels_remainder.1 -= scrolls.1 * unit;
+
+        report.vertical_wheel =
+            i8::saturating_add(report.vertical_wheel, scrolls.0.clamp(-127, 127) as i8);
+        report.horizontal_wheel =
+            i8::saturating_add(report.horizontal_wheel, scrolls.1.clamp(-127, 127) as i8);
+    }
+
     pub fn active_button(&mut self, mouse_report: &mut WheelMouseReport, key: &Key) {
         match key.code {
             KC::MouseBtLeft => {
@@ -112,8 +293,43 @@ impl Mouse {
         }
     }
 
+    /// Lock or unlock a button.
+    pub fn lock_button(&mut self, button: KC, ticks: u32) {
+        self.locked ^= match button {
+            KC::MouseBtLeft => 0x1,
+            KC::MouseBtMiddle => 0x4,
+            KC::MouseBtRight => 0x2,
+            _ => 0,
+        };
+        self.lock_activity = ticks;
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked != 0
+    }
+
+    /// Locked buttons have to be set in each report, unless they are idle for too long.
+    fn maintain_locked(&mut self, mouse_report: &mut WheelMouseReport, ticks: u32) {
+        if self.locked == 0 {
+            return;
+        }
+
+        if self.move_last == ticks || self.scroll_last == ticks || !self.buttons.is_empty() {
+            self.lock_activity = ticks;
+        } else if MOUSE_LOCK_TIMEOUT > 0
+            && ticks.wrapping_sub(self.lock_activity) > MOUSE_LOCK_TIMEOUT
+        {
+            self.locked = 0;
+        }
+
+        mouse_report.buttons |= self.locked;
+    }
+
     /// Button values have to be updated when the button is released
-    pub fn release(&mut self, matrix: &Matrix, mouse_report: &mut WheelMouseReport) {
+    pub fn release(&mut self, matrix: &Matrix, mouse_report: &mut WheelMouseReport, ticks: u32) {
+        self.moves(mouse_report, ticks);
+        self.scrolls(mouse_report, ticks);
+
         self.buttons
             .retain(|(index, bt_value)| match matrix.is_active(*index) {
                 false => {
@@ -123,5 +339,456 @@ impl Mouse {
                 _ => true,
             });
         self.speeds.retain(|s| matrix.is_active(s.0));
+
+        self.maintain_locked(mouse_report, ticks);
+    }
+}
+
+// --------------------------------------------------------------------------------------
+// ---------------------------------------------------------------------------- GRID --
+
+// Absolute coordinates go from 0 to this value on both axes, whatever the screen size
+const ABSOLUTE_MAX: u16 = 32767;
+
+/// Part of the screen in absolute coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Region {
+    fn screen() -> Self {
+        Region {
+            x: 0,
+            y: 0,
+            width: ABSOLUTE_MAX,
+            height: ABSOLUTE_MAX,
+        }
+    }
+
+    pub fn center(&self) -> (u16, u16) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// One of the 3x3 cells, numbered row by row from the top left corner.
+    fn cell(&self, index: usize) -> Region {
+        let (column, row) = ((index % 3) as u16, (index / 3) as u16);
+        Region {
+            x: self.x + self.width / 3 * column,
+            y: self.y + self.height / 3 * row,
+            width: self.width / 3,
+            height: self.height / 3,
+        }
+    }
+}
+
+/// Keyboard driven navigation with the absolute pointer.
+/// Once started, the screen is split into 3x3 cells (see MOUSE_GRID in layouts.rs), selecting a
+/// cell moves the pointer to its center and splits it again, and so on until a click.
+///
+/// Reports are queued, the main loop sends them one by one with absolute_report().
+/// A waiting move is replaced by the next one, the press & the release of a click are queued
+/// together or not at all, so a button can't stay pressed.
+pub struct Grid {
+    regions: Vec<Region, 8>, // Selected ones, the last is the current one
+    reports: Deque<(AbsoluteWheelMouseReport, bool), 8>, // Report, can be replaced (move)
+}
+
+impl Grid {
+    pub fn new() -> Self {
+        Grid {
+            regions: Vec::new(),
+            reports: Deque::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.regions.is_empty()
+    }
+
+    pub fn current(&self) -> Option<Region> {
+        self.regions.last().copied()
+    }
+
+    fn at(&self, buttons: u8) -> Option<AbsoluteWheelMouseReport> {
+        self.current().map(|region| {
+            let (x, y) = region.center();
+            AbsoluteWheelMouseReport {
+                buttons,
+                x,
+                y,
+                wheel: 0,
+            }
+        })
+    }
+
+    /// Move the pointer to the center of the current region.
+    fn point(&mut self) {
+        if let Some(report) = self.at(0) {
+            match self.reports.back_mut() {
+                Some((back, true)) => *back = report,
+                _ => {
+                    // Only lost behind a full queue of clicks
+                    self.reports.push_back((report, true)).ok();
+                }
+            }
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.regions.clear();
+        self.regions.push(Region::screen()).ok();
+        self.point();
+    }
+
+    /// Zoom in the cell, the last region is kept once it's too small to be split or once the
+    /// history is full (so back always goes to the parent region).
+    pub fn select(&mut self, cell: usize) {
+        if let Some(region) = self.current() {
+            let next = region.cell(cell);
+            if next.width > 0 && next.height > 0 {
+                self.regions.push(next).ok();
+            }
+            self.point();
+        }
+    }
+
+    /// Back to the previous region (leave the grid from the whole screen).
+    pub fn back(&mut self) {
+        self.regions.pop();
+        self.point();
+    }
+
+    /// Press & release the buttons at the current position, then leave the grid.
+    pub fn click(&mut self, buttons: u8) {
+        if let Some(press) = self.at(buttons) {
+            if self.reports.capacity() - self.reports.len() >= 2 {
+                let release = AbsoluteWheelMouseReport {
+                    buttons: 0,
+                    ..press
+                };
+                self.reports.push_back((press, false)).ok();
+                self.reports.push_back((release, false)).ok();
+            }
+        }
+        self.stop();
+    }
+
+    pub fn stop(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Next report to send.
+    pub fn report(&self) -> Option<AbsoluteWheelMouseReport> {
+        self.reports.front().map(|(report, _)| *report)
+    }
+
+    pub fn report_sent(&mut self) {
+        self.reports.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn key(code: KC, index: usize) -> Key {
+        Key {
+            index,
+            code,
+            ticks: 0,
+        }
+    }
+
+    /// One chew loop per ms while the directions are held, gives (ms, x, y) of each move.
+    fn hold(mouse: &mut Mouse, directions: &[KC], from: u32, duration: u32) -> Vec<(u32, i8, i8)> {
+        let mut moves = Vec::new();
+        for ms in 0..duration {
+            let ticks = from.wrapping_add(ms);
+            let mut report = WheelMouseReport::default();
+
+            for direction in directions {
+                mouse.movement(*direction, ticks);
+            }
+            mouse.moves(&mut report, ticks);
+
+            if (report.x, report.y) != (0, 0) {
+                moves.push((ms, report.x, report.y));
+            }
+        }
+        moves
+    }
+
+    /// Same for the wheels, gives (ms, vertical, horizontal) of each scroll.
+    fn roll(mouse: &mut Mouse, wheel: KC, from: u32, duration: u32) -> Vec<(u32, i8, i8)> {
+        let mut scrolls = Vec::new();
+        for ms in 0..duration {
+            let ticks = from.wrapping_add(ms);
+            let mut report = WheelMouseReport::default();
+
+            mouse.scroll(wheel, ticks);
+            mouse.scrolls(&mut report, ticks);
+
+            if (report.vertical_wheel, report.horizontal_wheel) != (0, 0) {
+                scrolls.push((ms, report.vertical_wheel, report.horizontal_wheel));
+            }
+        }
+        scrolls
+    }
+
+    #[test]
+    fn accelerate_follows_the_curve() {
+        assert_eq!(accelerate(0, 8, 320, 1000), 8);
+        assert_eq!(accelerate(1000, 8, 320, 1000), 320);
+        assert_eq!(accelerate(5000, 8, 320, 1000), 320);
+        assert_eq!(accelerate(10, 8, 320, 0), 320);
+
+        // Half of the time gives 1/2^curve of the max
+        assert_eq!(accelerate(500, 0, 256, 1000), 256 >> MOUSE_ACCEL_CURVE);
+
+        let speeds: Vec<i32> = (0..=1200)
+            .step_by(10)
+            .map(|ms| accelerate(ms, 8, 320, 1000))
+            .collect();
+        assert!(speeds.windows(2).all(|s| s[0] <= s[1]));
+    }
+
+    #[test]
+    fn acceleration_with_simulated_ticks() {
+        if !MOUSE_ACCEL {
+            return;
+        }
+
+        let mut mouse = Mouse::new();
+        let duration = MOUSE_ACCEL_DELAY + MOUSE_ACCEL_TIME_TO_MAX + 500;
+        let moves = hold(&mut mouse, &[KC::MouseRight], 0, duration);
+
+        // First move at once, the next ones after the delay & then at each interval
+        for (ms, x, y) in moves.iter() {
+            assert!(*ms == 0 || *ms >= MOUSE_ACCEL_DELAY);
+            assert_eq!(
+                (*ms).saturating_sub(MOUSE_ACCEL_DELAY) % MOUSE_ACCEL_INTERVAL,
+                0
+            );
+            assert!(*x > 0);
+            assert_eq!(*y, 0);
+        }
+
+        // Faster and faster until the max
+        let distance = |from: u32, to: u32| -> i32 {
+            moves
+                .iter()
+                .filter(|(ms, _, _)| *ms >= from && *ms < to)
+                .map(|(_, x, _)| *x as i32)
+                .sum()
+        };
+        let half = MOUSE_ACCEL_TIME_TO_MAX / 2;
+        assert!(distance(0, half) < distance(half, 2 * half));
+
+        for (_, x, _) in moves
+            .iter()
+            .filter(|(ms, _, _)| *ms > MOUSE_ACCEL_DELAY + MOUSE_ACCEL_TIME_TO_MAX)
+        {
+            assert!((*x as i32 * SUBPIXELS - MOUSE_ACCEL_MAX as i32).abs() < SUBPIXELS);
+        }
+    }
+
+    #[test]
+    fn speed_key_gives_a_constant_speed() {
+        let mut mouse = Mouse::new();
+        mouse.speed(&key(KC::MouseSpeed3, 5));
+
+        let (speed, tempo) = MOUSE_SPEED_3;
+        let moves = hold(&mut mouse, &[KC::MouseDown], 0, 10 * tempo);
+
+        assert_eq!(moves.len(), 10);
+        for (i, (ms, x, y)) in moves.into_iter().enumerate() {
+            assert_eq!((ms, x, y), (i as u32 * tempo, 0, speed));
+        }
+    }
+
+    #[test]
+    fn diagonals_are_normalised() {
+        let mut mouse = Mouse::new();
+        mouse.speed(&key(KC::MouseSpeed4, 5));
+
+        let (speed, tempo) = MOUSE_SPEED_4;
+        let moves = hold(&mut mouse, &[KC::MouseRight, KC::MouseUp], 0, 10 * tempo);
+
+        let x: i32 = moves.iter().map(|(_, x, _)| *x as i32).sum();
+        let y: i32 = moves.iter().map(|(_, _, y)| *y as i32).sum();
+        assert_eq!(x, -y);
+        assert!((x - 10 * speed as i32 * DIAGONAL / 256).abs() <= 1);
+    }
+
+    #[test]
+    fn stops_once_released() {
+        if MOUSE_INERTIA {
+            return;
+        }
+
+        let mut mouse = Mouse::new();
+        assert!(!hold(&mut mouse, &[KC::MouseRight], 0, 500).is_empty());
+        assert!(hold(&mut mouse, &[], 500, 500).is_empty());
+
+        assert_eq!(mouse.move_start, None);
+        assert_eq!(mouse.remainder, (0, 0));
+    }
+
+    #[test]
+    fn moves_go_on_when_the_ticks_wrap() {
+        let mut mouse = Mouse::new();
+        mouse.speed(&key(KC::MouseSpeed3, 5));
+
+        let (speed, tempo) = MOUSE_SPEED_3;
+        let moves = hold(&mut mouse, &[KC::MouseRight], u32::MAX - 45, 10 * tempo);
+
+        assert_eq!(moves.len(), 10);
+        for (i, (ms, x, _)) in moves.into_iter().enumerate() {
+            assert_eq!((ms, x), (i as u32 * tempo, speed));
+        }
+    }
+
+    #[test]
+    fn scroll_with_simulated_ticks() {
+        let (speed, tempo) = SCROLL_SPEED_1;
+
+        // Whole detents only
+        let mut mouse = Mouse::new();
+        mouse.speed(&key(KC::MouseSpeed1, 5));
+        let scrolls = roll(&mut mouse, KC::MouseWheelDown, 0, 4 * tempo);
+        assert_eq!(
+            scrolls,
+            (0..4).map(|i| (i * tempo, -speed, 0)).collect::<Vec<_>>()
+        );
+
+        // With the resolution multiplier
+        let mut mouse = Mouse::new();
+        mouse.smooth = true;
+        mouse.speed(&key(KC::MouseSpeed1, 5));
+        let scrolls = roll(&mut mouse, KC::MouseWheelLeft, u32::MAX - 10, 4 * tempo);
+        assert_eq!(
+            scrolls,
+            (0..4)
+                .map(|i| (i * tempo, 0, speed * SCROLL_MULTIPLIER as i8))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    // Grid --
+
+    const CENTER: u16 = ABSOLUTE_MAX / 2;
+    const CELL: u16 = ABSOLUTE_MAX / 3;
+
+    /// Reports sent by the main loop, (buttons, x, y).
+    fn sent(grid: &mut Grid) -> Vec<(u8, u16, u16)> {
+        let mut reports = Vec::new();
+        while let Some(report) = grid.report() {
+            reports.push((report.buttons, report.x, report.y));
+            grid.report_sent();
+        }
+        reports
+    }
+
+    #[test]
+    fn start_select_back_and_click() {
+        let mut grid = Grid::new();
+        assert!(!grid.is_active());
+
+        grid.start();
+        assert_eq!(sent(&mut grid), [(0, CENTER, CENTER)]);
+
+        // Top left, then its bottom right
+        grid.select(0);
+        assert_eq!(sent(&mut grid), [(0, CELL / 2, CELL / 2)]);
+        grid.select(8);
+        let corner = CELL / 3 * 2 + CELL / 3 / 2;
+        assert_eq!(sent(&mut grid), [(0, corner, corner)]);
+
+        grid.back();
+        assert_eq!(sent(&mut grid), [(0, CELL / 2, CELL / 2)]);
+
+        // Middle right
+        grid.select(5);
+        let (x, y) = (CELL / 3 * 2 + CELL / 3 / 2, CELL / 3 + CELL / 3 / 2);
+        assert_eq!(sent(&mut grid), [(0, x, y)]);
+
+        grid.click(0x2);
+        assert_eq!(sent(&mut grid), [(0x2, x, y), (0, x, y)]);
+        assert!(!grid.is_active());
+    }
+
+    #[test]
+    fn back_from_the_screen_leaves() {
+        let mut grid = Grid::new();
+        grid.start();
+        grid.back();
+
+        assert!(!grid.is_active());
+        assert_eq!(sent(&mut grid), [(0, CENTER, CENTER)]);
+    }
+
+    #[test]
+    fn zoom_stops_once_the_history_is_full() {
+        let mut grid = Grid::new();
+        grid.start();
+        for _ in 0..20 {
+            grid.select(4);
+        }
+
+        let region = grid.current().unwrap();
+        assert!(region.width > 0 && region.height > 0);
+
+        // Cells are rounded down
+        let reports = sent(&mut grid);
+        assert_eq!(reports.len(), 1);
+        assert!(CENTER - reports[0].1 < 16 && CENTER - reports[0].2 < 16);
+
+        // Back goes through every parent up to the screen
+        let mut width = region.width;
+        while grid.regions.len() > 1 {
+            grid.back();
+            let parent = grid.current().unwrap();
+            assert!(parent.width >= width * 3 && parent.width < width * 3 + 3);
+            width = parent.width;
+        }
+        assert_eq!(grid.current(), Some(Region::screen()));
+    }
+
+    #[test]
+    fn waiting_moves_are_replaced() {
+        let mut grid = Grid::new();
+        grid.start();
+        grid.select(0);
+        grid.select(0);
+        grid.back();
+
+        assert_eq!(sent(&mut grid), [(0, CELL / 2, CELL / 2)]);
+    }
+
+    #[test]
+    fn clicks_are_never_split() {
+        let mut grid = Grid::new();
+
+        // The host doesn't take the reports meanwhile
+        for cell in 0..20 {
+            grid.start();
+            grid.select(cell % 9);
+            grid.click(0x1);
+        }
+
+        let reports = sent(&mut grid);
+        let presses = reports.iter().filter(|r| r.0 != 0).count();
+        assert!(presses > 0);
+
+        // Each press is followed by its release at the same place
+        for (i, report) in reports.iter().enumerate().filter(|(_, r)| r.0 != 0) {
+            assert_eq!(reports.get(i + 1), Some(&(0, report.1, report.2)));
+        }
+        assert_eq!(reports.last().map(|r| r.0), Some(0));
     }
 }
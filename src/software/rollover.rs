@@ -0,0 +1,269 @@
+use heapless::Vec;
+use usbd_human_interface_device::page::Keyboard;
+
+use super::{
+    keys::{BuffCase, Buffer},
+    modifiers::Modifiers,
+};
+use crate::{hardware::matrix::Matrix, options::BUFFER_CASE_LENGTH};
+
+struct HeldKey {
+    index: usize,
+    codes: Vec<Keyboard, BUFFER_CASE_LENGTH>, // Forced mods included (e.g. shift for '!')
+    excluded: Vec<Keyboard, 5>,               // Active mods removed by this key (no mods)
+}
+
+/// Simple keys (one report) stay in the report as long as they are held down, so several
+/// of them are reported at once (true rollover) and the host can repeat them.
+/// Each change (press, release, mods) sends the whole set again.
+/// Other keys (macros, dead keys...) are still sent as sequences.
+/// With a limit (6KRO boot keyboard), the last held keys wait for a free place.
+pub struct HeldKeys {
+    keys: Vec<HeldKey, 16>,
+    mods: u8,
+    limit: usize,
+}
+
+impl HeldKeys {
+    pub fn new() -> Self {
+        HeldKeys {
+            keys: Vec::new(),
+            mods: 0,
+            limit: usize::MAX,
+        }
+    }
+
+    /// Maximum number of keys (modifiers excluded) in a report.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// To call once a key has been printed from the 'from' buffer entry.
+    /// A simple key still held down is kept and replaced by the report of all the held keys.
+    /// Returns true if the key is kept.
+    pub fn add(
+        &mut self,
+        mut buffer: Buffer,
+        from: usize,
+        index: usize,
+        mods: &Modifiers,
+        held_down: bool,
+    ) -> (Buffer, bool) {
+        let simple = held_down && buffer.keys.len() == from + 1;
+
+        if simple {
+            if let Some(case) = buffer.keys.pop_back() {
+                let active = mods.active();
+
+                self.keys.retain(|k| k.index != index);
+                self.keys
+                    .push(HeldKey {
+                        index,
+                        codes: case
+                            .key_code
+                            .iter()
+                            .filter(|c| !active.contains(c))
+                            .copied()
+                            .collect(),
+                        excluded: active
+                            .iter()
+                            .filter(|m| !case.key_code.contains(m))
+                            .copied()
+                            .collect(),
+                    })
+                    .ok();
+            }
+        }
+
+        // Keys still held down come back after a sequence
+        if !self.keys.is_empty() {
+            buffer = self.report(buffer, mods);
+        }
+
+        (buffer, simple)
+    }
+
+    /// Remove the released keys, returns true if there were some.
+    pub fn release(&mut self, matrix: &Matrix) -> bool {
+        let before = self.keys.len();
+        self.keys.retain(|k| matrix.is_active(k.index));
+        self.keys.len() != before
+    }
+
+    pub fn mods_changed(&self, mods: &Modifiers) -> bool {
+        !self.keys.is_empty() && self.mods != mods.to_bits()
+    }
+
+    /// Add the report of the active mods and all the held keys.
+    pub fn report(&mut self, mut buffer: Buffer, mods: &Modifiers) -> Buffer {
+        self.mods = mods.to_bits();
+
+        let mut key_code: Vec<Keyboard, BUFFER_CASE_LENGTH> = mods
+            .active()
+            .iter()
+            .filter(|m| !self.keys.iter().any(|k| k.excluded.contains(m)))
+            .copied()
+            .collect();
+
+        // The overflow is queued, these keys are added once the first ones are released
+        let mut nb_keys = 0;
+        for key in self.keys.iter() {
+            let codes = key.codes.iter().filter(|c| !is_modifier(c));
+            if nb_keys + codes.clone().count() > self.limit {
+                break;
+            }
+
+            nb_keys += codes.count();
+            for code in key.codes.iter() {
+                if !key_code.contains(code) {
+                    key_code.push(*code).ok();
+                }
+            }
+        }
+
+        if key_code.is_empty() {
+            key_code.push(Keyboard::NoEventIndicated).ok();
+        }
+
+        buffer.keys.push_back(BuffCase { key_code, tempo: 0 }).ok();
+        buffer
+    }
+}
+
+fn is_modifier(code: &Keyboard) -> bool {
+    (Keyboard::LeftControl..=Keyboard::RightGUI).contains(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::software::keys::KC;
+    use std::vec::Vec as StdVec;
+
+    fn matrix(active: &[u8]) -> Matrix {
+        let mut matrix = Matrix::new();
+        matrix.update(Vec::from_slice(active).unwrap());
+        matrix
+    }
+
+    /// Print the key like chew does, it is held if its matrix index is still active.
+    fn print(
+        held: &mut HeldKeys,
+        buffer: Buffer,
+        code: KC,
+        index: usize,
+        matrix: &Matrix,
+    ) -> Buffer {
+        let mods = Modifiers::new();
+        let from = buffer.keys.len();
+        let buffer = code.usb_code(buffer, &mods);
+        held.add(buffer, from, index, &mods, matrix.is_active(index))
+            .0
+    }
+
+    fn reports(buffer: &Buffer) -> StdVec<StdVec<Keyboard>> {
+        buffer
+            .keys
+            .iter()
+            .map(|case| case.key_code.iter().copied().collect())
+            .collect()
+    }
+
+    #[test]
+    fn held_key_stays_while_another_is_tapped() {
+        let mut held = HeldKeys::new();
+        let mut buffer = Buffer::new();
+
+        // A is held down, B is tapped meanwhile (already released)
+        let down = matrix(&[0]);
+        buffer = print(&mut held, buffer, KC::A, 0, &down);
+        buffer = print(&mut held, buffer, KC::B, 1, &down);
+
+        assert_eq!(
+            reports(&buffer),
+            [vec![Keyboard::A], vec![Keyboard::B], vec![Keyboard::A]]
+        );
+
+        // Then A is released
+        assert!(held.release(&matrix(&[])));
+        assert!(held.is_empty());
+        buffer = held.report(Buffer::new(), &Modifiers::new());
+        assert_eq!(reports(&buffer), [vec![Keyboard::NoEventIndicated]]);
+    }
+
+    #[test]
+    fn held_keys_are_reported_together() {
+        let mut held = HeldKeys::new();
+        let mut buffer = Buffer::new();
+
+        let down = matrix(&[0, 1]);
+        buffer = print(&mut held, buffer, KC::A, 0, &down);
+        buffer = print(&mut held, buffer, KC::B, 1, &down);
+        assert_eq!(
+            reports(&buffer).last(),
+            Some(&vec![Keyboard::A, Keyboard::B])
+        );
+
+        // Only the released one goes away
+        assert!(held.release(&matrix(&[1])));
+        assert!(!held.release(&matrix(&[1])));
+        buffer = held.report(Buffer::new(), &Modifiers::new());
+        assert_eq!(reports(&buffer), [vec![Keyboard::B]]);
+    }
+
+    const SEVEN: [(KC, Keyboard); 7] = [
+        (KC::A, Keyboard::A),
+        (KC::B, Keyboard::B),
+        (KC::C, Keyboard::C),
+        (KC::D, Keyboard::D),
+        (KC::E, Keyboard::E),
+        (KC::F, Keyboard::F),
+        (KC::G, Keyboard::G),
+    ];
+
+    /// Seven keys held down with a 6KRO limit.
+    fn seven_held() -> (HeldKeys, Buffer) {
+        let mut held = HeldKeys::new();
+        held.set_limit(6);
+
+        let mut buffer = Buffer::new();
+        let down = matrix(&[0, 1, 2, 3, 4, 5, 6]);
+        for (index, (code, _)) in SEVEN.iter().enumerate() {
+            buffer = print(&mut held, buffer, *code, index, &down);
+        }
+        (held, buffer)
+    }
+
+    fn codes(indexes: &[usize]) -> StdVec<Keyboard> {
+        indexes.iter().map(|i| SEVEN[*i].1).collect()
+    }
+
+    #[test]
+    fn seventh_key_is_queued_until_a_place_is_free() {
+        let (mut held, buffer) = seven_held();
+        assert_eq!(reports(&buffer).last(), Some(&codes(&[0, 1, 2, 3, 4, 5])));
+
+        assert!(held.release(&matrix(&[1, 2, 3, 4, 5, 6])));
+        let buffer = held.report(Buffer::new(), &Modifiers::new());
+        assert_eq!(reports(&buffer), [codes(&[1, 2, 3, 4, 5, 6])]);
+    }
+
+    #[test]
+    fn queued_key_released_is_dropped() {
+        let (mut held, _) = seven_held();
+
+        // G is released before having been reported
+        assert!(held.release(&matrix(&[0, 1, 2, 3, 4, 5])));
+        let buffer = held.report(Buffer::new(), &Modifiers::new());
+        assert_eq!(reports(&buffer), [codes(&[0, 1, 2, 3, 4, 5])]);
+
+        // A free place doesn't bring it back
+        assert!(held.release(&matrix(&[1, 2, 3, 4, 5])));
+        let buffer = held.report(Buffer::new(), &Modifiers::new());
+        assert_eq!(reports(&buffer), [codes(&[1, 2, 3, 4, 5])]);
+    }
+}
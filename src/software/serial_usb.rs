@@ -1,19 +1,64 @@
 use core::fmt::{Display, Write};
-use heapless::String;
+use heapless::{Deque, String};
 use usbd_serial::SerialPort;
 use waveshare_rp2040_zero::hal::usb::UsbBus;
 
-use crate::options::SERIAL_ON;
+use crate::options::{SERIAL_BUFFER_LENGTH, SERIAL_CHUNK, SERIAL_ON};
 
-pub fn serial_write(serial: &mut SerialPort<'_, UsbBus>, txt: &str) {
+/// Bytes waiting to be written on the serial port.
+/// The port shares the USB with the keyboard, so the output is sent by small chunks
+/// when no key is waiting (see TIMER_SERIAL_LOOP). A message is lost if the buffer is full.
+pub struct SerialOutput {
+    pending: Deque<u8, SERIAL_BUFFER_LENGTH>,
+}
+
+impl SerialOutput {
+    pub fn new() -> Self {
+        SerialOutput {
+            pending: Deque::new(),
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        if self.pending.capacity() - self.pending.len() >= bytes.len() {
+            for byte in bytes.iter() {
+                self.pending.push_back(*byte).ok();
+            }
+        }
+    }
+
+    /// Write one chunk (SERIAL_CHUNK max), the rest waits for the next call.
+    pub fn flush(&mut self, port: &mut SerialPort<'_, UsbBus>) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let (chunk, _) = self.pending.as_slices();
+        let chunk = &chunk[..chunk.len().min(SERIAL_CHUNK)];
+
+        if let Ok(written) = port.write(chunk) {
+            for _ in 0..written {
+                self.pending.pop_front();
+            }
+        }
+    }
+}
+
+pub fn serial_write(serial: &mut SerialOutput, txt: &str) {
     if SERIAL_ON {
-        serial.write(txt.as_bytes()).ok();
+        serial.push(txt.as_bytes());
     }
 }
 
+/// Bytes written whatever SERIAL_ON is: the answers of the shell, and the steno chords read by
+/// Plover (in their own SerialOutput so they're not mixed with the text).
+pub fn serial_write_raw(output: &mut SerialOutput, bytes: &[u8]) {
+    output.push(bytes);
+}
+
 #[allow(dead_code)]
 pub fn serial_write_value<T: Display>(
-    serial: &mut SerialPort<'_, UsbBus>,
+    serial: &mut SerialOutput,
     before: &str,
     value: T,
     after: &str,
@@ -26,7 +71,7 @@ pub fn serial_write_value<T: Display>(
 
 #[allow(dead_code)]
 pub fn serial_write_values<T: Display>(
-    serial: &mut SerialPort<'_, UsbBus>,
+    serial: &mut SerialOutput,
     before: &str,
     values: &[T],
     after: &str,
@@ -45,12 +90,7 @@ pub fn serial_write_values<T: Display>(
 }
 
 #[allow(dead_code)]
-pub fn serial_write_time(
-    serial: &mut SerialPort<'_, UsbBus>,
-    before: &str,
-    ticks: u32,
-    after: &str,
-) {
+pub fn serial_write_time(serial: &mut SerialOutput, before: &str, ticks: u32, after: &str) {
     let mut l: String<70> = String::new();
 
     let ms = ticks / 1000;
@@ -0,0 +1,259 @@
+use super::keys::Setting;
+use super::storage::{Flash, KvStore};
+use crate::options::{
+    BUZZER_STARTUP_ACTIVATION, COMBO_TIME, COMBO_TIME_MAX, COMBO_TIME_MIN, HOLD_TIME,
+    HOLD_TIME_MAX, HOLD_TIME_MIN, SETTINGS_SAVE_DELAY, SETTING_COMBO_STEP, SETTING_SPEED_MAX,
+    SETTING_SPEED_MIN, SETTING_SPEED_STEP, SETTING_TIME_STEP, TEMPO_DEAD_KEY, TEMPO_DEAD_KEY_MAX,
+    TIMER_USB_LOOP, TIMER_USB_LOOP_MAX, TIMER_USB_LOOP_MIN,
+};
+
+// Increase the version if the meaning of the stored values changes
+const STORAGE_VERSION: u8 = 1;
+const STORAGE_KIND: u8 = 2;
+
+// Keys of the stored values (never reuse a removed one)
+const KEY_HOLD_TIME: u8 = 0;
+const KEY_COMBO_TIME: u8 = 1;
+const KEY_DEAD_KEY_TEMPO: u8 = 2;
+const KEY_MOUSE_SPEED: u8 = 3;
+const KEY_SCROLL_SPEED: u8 = 4;
+const KEY_USB_LOOP: u8 = 5;
+const KEY_BUZZER: u8 = 6;
+const KEY_DEFAULT_LAYOUT: u8 = 7;
+
+/// Options which can be tuned at runtime (KC::SettingUp/SettingDown, leader key, KC::LaySet,
+/// buzzer toggle or the serial shell). They start from the values of options.rs, then the
+/// changes are kept in flash and come back at the next start.
+/// Mouse and scroll speeds are percentages applied to all the MOUSE/SCROLL_SPEED_* values.
+pub struct Settings<F: Flash> {
+    pub hold_time: u32,
+    pub combo_time: u32,
+    pub dead_key_tempo: u32,
+    pub mouse_speed: u32,
+    pub scroll_speed: u32,
+    pub usb_loop: u32,
+    pub buzzer: bool,
+    pub default_layout: usize,
+    store: KvStore<F, 16>,
+    changed_at: Option<u32>,
+}
+
+impl<F: Flash> Settings<F> {
+    pub fn new(flash: F) -> Self {
+        let store = KvStore::new(flash, STORAGE_VERSION, STORAGE_KIND);
+        let stored = |key: u8, default: u32, min: u32, max: u32| match store.get(key) {
+            Some(value) => value.clamp(min, max),
+            None => default,
+        };
+
+        Settings {
+            hold_time: stored(KEY_HOLD_TIME, HOLD_TIME, HOLD_TIME_MIN, HOLD_TIME_MAX),
+            combo_time: stored(KEY_COMBO_TIME, COMBO_TIME, COMBO_TIME_MIN, COMBO_TIME_MAX),
+            dead_key_tempo: stored(KEY_DEAD_KEY_TEMPO, TEMPO_DEAD_KEY, 0, TEMPO_DEAD_KEY_MAX),
+            mouse_speed: stored(KEY_MOUSE_SPEED, 100, SETTING_SPEED_MIN, SETTING_SPEED_MAX),
+            scroll_speed: stored(KEY_SCROLL_SPEED, 100, SETTING_SPEED_MIN, SETTING_SPEED_MAX),
+            usb_loop: stored(
+                KEY_USB_LOOP,
+                TIMER_USB_LOOP,
+                TIMER_USB_LOOP_MIN,
+                TIMER_USB_LOOP_MAX,
+            ),
+            buzzer: stored(KEY_BUZZER, BUZZER_STARTUP_ACTIVATION as u32, 0, 1) != 0,
+            default_layout: stored(KEY_DEFAULT_LAYOUT, 0, 0, u32::MAX) as usize,
+            store,
+            changed_at: None,
+        }
+    }
+
+    /// Changes are written once the keyboard has been idle for SETTINGS_SAVE_DELAY
+    /// (the keyboard freezes while the flash is written, and a burst of steps is one write).
+    pub fn save(&mut self, idle: bool, ticks: u32) {
+        let values = [
+            (KEY_HOLD_TIME, self.hold_time),
+            (KEY_COMBO_TIME, self.combo_time),
+            (KEY_DEAD_KEY_TEMPO, self.dead_key_tempo),
+            (KEY_MOUSE_SPEED, self.mouse_speed),
+            (KEY_SCROLL_SPEED, self.scroll_speed),
+            (KEY_USB_LOOP, self.usb_loop),
+            (KEY_BUZZER, self.buzzer as u32),
+            (KEY_DEFAULT_LAYOUT, self.default_layout as u32),
+        ];
+
+        let mut changed = false;
+        for (key, value) in values {
+            changed |= self.store.set(key, value);
+        }
+        if changed {
+            self.changed_at = Some(ticks);
+        }
+
+        if let Some(at) = self.changed_at {
+            if idle && ticks.wrapping_sub(at) >= SETTINGS_SAVE_DELAY {
+                // A failed write is tried again after the next change
+                self.store.save().ok();
+                self.changed_at = None;
+            }
+        }
+    }
+
+    /// One step up or down, returns false if the limit is already reached.
+    pub fn adjust(&mut self, setting: Setting, up: bool) -> bool {
+        let (value, step, min, max) = match setting {
+            Setting::HoldTime => (
+                &mut self.hold_time,
+                SETTING_TIME_STEP,
+                HOLD_TIME_MIN,
+                HOLD_TIME_MAX,
+            ),
+            Setting::ComboTime => (
+                &mut self.combo_time,
+                SETTING_COMBO_STEP,
+                COMBO_TIME_MIN,
+                COMBO_TIME_MAX,
+            ),
+            Setting::DeadKeyTempo => (
+                &mut self.dead_key_tempo,
+                SETTING_TIME_STEP,
+                0,
+                TEMPO_DEAD_KEY_MAX,
+            ),
+            Setting::MouseSpeed => (
+                &mut self.mouse_speed,
+                SETTING_SPEED_STEP,
+                SETTING_SPEED_MIN,
+                SETTING_SPEED_MAX,
+            ),
+            Setting::ScrollSpeed => (
+                &mut self.scroll_speed,
+                SETTING_SPEED_STEP,
+                SETTING_SPEED_MIN,
+                SETTING_SPEED_MAX,
+            ),
+            Setting::UsbLoop => (
+                &mut self.usb_loop,
+                1,
+                TIMER_USB_LOOP_MIN,
+                TIMER_USB_LOOP_MAX,
+            ),
+        };
+
+        let new = match up {
+            true => value.saturating_add(step).min(max),
+            false => value.saturating_sub(step).max(min),
+        };
+
+        let changed = new != *value;
+        *value = new;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layouts::LEADER_KEY_COMBINATIONS,
+        options::FLASH_SETTINGS_SIZE,
+        software::{keys::KC, storage::RamFlash},
+    };
+
+    const ALL: [Setting; 6] = [
+        Setting::HoldTime,
+        Setting::ComboTime,
+        Setting::DeadKeyTempo,
+        Setting::MouseSpeed,
+        Setting::ScrollSpeed,
+        Setting::UsbLoop,
+    ];
+
+    fn settings() -> Settings<RamFlash> {
+        Settings::new(RamFlash::new(FLASH_SETTINGS_SIZE))
+    }
+
+    fn value(settings: &Settings<RamFlash>, setting: Setting) -> u32 {
+        match setting {
+            Setting::HoldTime => settings.hold_time,
+            Setting::ComboTime => settings.combo_time,
+            Setting::DeadKeyTempo => settings.dead_key_tempo,
+            Setting::MouseSpeed => settings.mouse_speed,
+            Setting::ScrollSpeed => settings.scroll_speed,
+            Setting::UsbLoop => settings.usb_loop,
+        }
+    }
+
+    #[test]
+    fn one_step_up_and_down() {
+        let mut settings = settings();
+
+        assert!(settings.adjust(Setting::HoldTime, true));
+        assert_eq!(settings.hold_time, HOLD_TIME + SETTING_TIME_STEP);
+        assert!(settings.adjust(Setting::HoldTime, false));
+        assert!(settings.adjust(Setting::HoldTime, false));
+        assert_eq!(settings.hold_time, HOLD_TIME - SETTING_TIME_STEP);
+
+        assert!(settings.adjust(Setting::ComboTime, false));
+        assert_eq!(settings.combo_time, COMBO_TIME - SETTING_COMBO_STEP);
+        assert!(settings.adjust(Setting::MouseSpeed, true));
+        assert_eq!(settings.mouse_speed, 100 + SETTING_SPEED_STEP);
+        assert!(settings.adjust(Setting::UsbLoop, true));
+        assert_eq!(settings.usb_loop, TIMER_USB_LOOP + 1);
+
+        // The others didn't move
+        assert_eq!(settings.dead_key_tempo, TEMPO_DEAD_KEY);
+        assert_eq!(settings.scroll_speed, 100);
+    }
+
+    #[test]
+    fn clamped_at_min_and_max() {
+        let limits = [
+            (Setting::HoldTime, HOLD_TIME_MIN, HOLD_TIME_MAX),
+            (Setting::ComboTime, COMBO_TIME_MIN, COMBO_TIME_MAX),
+            (Setting::DeadKeyTempo, 0, TEMPO_DEAD_KEY_MAX),
+            (Setting::MouseSpeed, SETTING_SPEED_MIN, SETTING_SPEED_MAX),
+            (Setting::ScrollSpeed, SETTING_SPEED_MIN, SETTING_SPEED_MAX),
+            (Setting::UsbLoop, TIMER_USB_LOOP_MIN, TIMER_USB_LOOP_MAX),
+        ];
+
+        for (setting, min, max) in limits {
+            let mut settings = settings();
+            while settings.adjust(setting, true) {}
+            assert_eq!(value(&settings, setting), max, "{:?}", setting);
+
+            while settings.adjust(setting, false) {}
+            assert_eq!(value(&settings, setting), min, "{:?}", setting);
+        }
+    }
+
+    #[test]
+    fn limit_reached() {
+        let mut settings = settings();
+        settings.hold_time = HOLD_TIME_MAX;
+        assert!(!settings.adjust(Setting::HoldTime, true));
+        assert_eq!(settings.hold_time, HOLD_TIME_MAX);
+        assert!(settings.adjust(Setting::HoldTime, false));
+
+        // A step over the limit stops on it, the next one is refused
+        settings.hold_time = HOLD_TIME_MIN + 1;
+        assert!(settings.adjust(Setting::HoldTime, false));
+        assert_eq!(settings.hold_time, HOLD_TIME_MIN);
+        assert!(!settings.adjust(Setting::HoldTime, false));
+    }
+
+    #[test]
+    fn leader_sequences() {
+        // Each setting goes up with a sequence ending by U and down with the same one ending by D
+        for setting in ALL {
+            let find = |code: KC| {
+                LEADER_KEY_COMBINATIONS
+                    .iter()
+                    .find(|(_, k)| *k == code)
+                    .map(|(keys, _)| *keys)
+            };
+
+            let up = find(KC::SettingUp(setting)).unwrap();
+            let down = find(KC::SettingDown(setting)).unwrap();
+            assert_eq!(up[..2], down[..2], "{:?}", setting);
+            assert_eq!((up[2], down[2]), (KC::U, KC::D), "{:?}", setting);
+        }
+    }
+}
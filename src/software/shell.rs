@@ -0,0 +1,315 @@
+use heapless::{String, Vec};
+
+use crate::options::{HOLD_TIME_MAX, HOLD_TIME_MIN, KEYMAP_CHUNK, SHELL_LINE_LENGTH};
+
+/// Commands typed on the serial port (with or without the feature serial), one per line:
+///     help
+///     status              active layer, mods, leader & dynamic macros states
+///     layers              layers count, active and default ones
+///     get hold_time
+///     set hold_time 180   (ms, from HOLD_TIME_MIN to HOLD_TIME_MAX)
+///     buzzer on|off
+///     macros list         registered dynamic macros
+///     keymap begin 1234   upload of a keymap (bytes), sent by the host tool (tools/keymap)
+///     keymap data 0a1b..  part of the upload in hex (KEYMAP_CHUNK bytes max)
+///     keymap end 89abcdef crc32 of the whole upload in hex, the keymap is then saved
+///     keymap default      back to layouts.rs
+///     reset               restart the keyboard
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Status,
+    Layers,
+    GetHoldTime,
+    SetHoldTime(u32),
+    Buzzer(bool),
+    MacrosList,
+    KeymapBegin(usize),
+    KeymapData(Vec<u8, KEYMAP_CHUNK>),
+    KeymapEnd(u32),
+    KeymapDefault,
+    Reset,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShellError {
+    Unknown,
+    MissingValue,
+    WrongValue,
+    TooLong,
+}
+
+impl ShellError {
+    pub fn to_serial(&self) -> String<40> {
+        let mut output = String::new();
+        output
+            .push_str(match self {
+                ShellError::Unknown => "-- Error Unknown command --\r\n",
+                ShellError::MissingValue => "-- Error Missing value --\r\n",
+                ShellError::WrongValue => "-- Error Wrong value --\r\n",
+                ShellError::TooLong => "-- Error Line too long --\r\n",
+            })
+            .ok();
+        output
+    }
+}
+
+/// Parse one line, words are separated by spaces and the case is ignored.
+pub fn parse(line: &str) -> Result<Command, ShellError> {
+    let mut lower: String<SHELL_LINE_LENGTH> = String::new();
+    for c in line.chars() {
+        lower
+            .push(c.to_ascii_lowercase())
+            .map_err(|_| ShellError::TooLong)?;
+    }
+
+    let mut words: Vec<&str, 4> = Vec::new();
+    for word in lower.split_ascii_whitespace() {
+        words.push(word).map_err(|_| ShellError::Unknown)?;
+    }
+
+    match words.as_slice() {
+        ["help"] => Ok(Command::Help),
+        ["status"] => Ok(Command::Status),
+        ["layers"] => Ok(Command::Layers),
+        ["reset"] => Ok(Command::Reset),
+        ["macros", "list"] => Ok(Command::MacrosList),
+
+        ["get", "hold_time"] => Ok(Command::GetHoldTime),
+        ["set", "hold_time"] => Err(ShellError::MissingValue),
+        ["set", "hold_time", value] => match value.parse::<u32>() {
+            Ok(ms) if (HOLD_TIME_MIN..=HOLD_TIME_MAX).contains(&ms) => Ok(Command::SetHoldTime(ms)),
+            _ => Err(ShellError::WrongValue),
+        },
+
+        ["buzzer"] => Err(ShellError::MissingValue),
+        ["buzzer", "on"] => Ok(Command::Buzzer(true)),
+        ["buzzer", "off"] => Ok(Command::Buzzer(false)),
+        ["buzzer", _] => Err(ShellError::WrongValue),
+
+        ["keymap", "default"] => Ok(Command::KeymapDefault),
+        ["keymap", "begin" | "data" | "end"] => Err(ShellError::MissingValue),
+        ["keymap", "begin", value] => match value.parse::<usize>() {
+            Ok(length) => Ok(Command::KeymapBegin(length)),
+            _ => Err(ShellError::WrongValue),
+        },
+        ["keymap", "data", value] => match from_hex(value) {
+            Some(bytes) => Ok(Command::KeymapData(bytes)),
+            None => Err(ShellError::WrongValue),
+        },
+        ["keymap", "end", value] => match u32::from_str_radix(value, 16) {
+            Ok(crc) => Ok(Command::KeymapEnd(crc)),
+            _ => Err(ShellError::WrongValue),
+        },
+
+        _ => Err(ShellError::Unknown),
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8, KEYMAP_CHUNK>> {
+    // from_str_radix also takes a sign
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut bytes = Vec::new();
+    for pair in hex.as_bytes().chunks(2) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        bytes.push(u8::from_str_radix(pair, 16).ok()?).ok()?;
+    }
+    Some(bytes)
+}
+
+/// Collect the typed characters until the end of the line.
+pub struct Shell {
+    line: String<SHELL_LINE_LENGTH>,
+    overflow: bool,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Shell {
+            line: String::new(),
+            overflow: false,
+        }
+    }
+
+    /// Add one received byte, the command is given once the line is complete.
+    pub fn input(&mut self, byte: u8) -> Option<Result<Command, ShellError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let result = match (self.overflow, self.line.trim().is_empty()) {
+                    (true, _) => Some(Err(ShellError::TooLong)),
+                    (false, true) => None,
+                    (false, false) => Some(parse(&self.line)),
+                };
+
+                self.line.clear();
+                self.overflow = false;
+                result
+            }
+            // Backspace & delete
+            0x08 | 0x7F => {
+                self.line.pop();
+                None
+            }
+            b' '..=b'~' => {
+                if self.line.push(byte as char).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What to write back so the typed line is visible in the terminal.
+pub fn echo(byte: &u8) -> &[u8] {
+    match byte {
+        b'\r' | b'\n' => b"\r\n",
+        0x08 | 0x7F => b"\x08 \x08",
+        _ => core::slice::from_ref(byte),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    /// Type the line on the serial port.
+    fn typed(shell: &mut Shell, line: &str) -> Option<Result<Command, ShellError>> {
+        let mut result = None;
+        for byte in line.bytes().chain(*b"\r") {
+            result = shell.input(byte);
+        }
+        result
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("status"), Ok(Command::Status));
+        assert_eq!(parse("layers"), Ok(Command::Layers));
+        assert_eq!(parse("reset"), Ok(Command::Reset));
+        assert_eq!(parse("macros list"), Ok(Command::MacrosList));
+        assert_eq!(parse("get hold_time"), Ok(Command::GetHoldTime));
+        assert_eq!(parse("set hold_time 180"), Ok(Command::SetHoldTime(180)));
+        assert_eq!(parse("buzzer on"), Ok(Command::Buzzer(true)));
+        assert_eq!(parse("buzzer off"), Ok(Command::Buzzer(false)));
+        assert_eq!(parse("keymap default"), Ok(Command::KeymapDefault));
+        assert_eq!(parse("keymap begin 1234"), Ok(Command::KeymapBegin(1234)));
+        assert_eq!(
+            parse("keymap data 0a1BfF"),
+            Ok(Command::KeymapData(
+                Vec::from_slice(&[0x0A, 0x1B, 0xFF]).unwrap()
+            ))
+        );
+        assert_eq!(
+            parse("keymap end 89abcdef"),
+            Ok(Command::KeymapEnd(0x89AB_CDEF))
+        );
+    }
+
+    #[test]
+    fn case_and_spaces_are_ignored() {
+        assert_eq!(
+            parse("  SET   Hold_Time  200 "),
+            Ok(Command::SetHoldTime(200))
+        );
+        assert_eq!(parse("Buzzer ON"), Ok(Command::Buzzer(true)));
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(parse("hello"), Err(ShellError::Unknown));
+        assert_eq!(parse("get combo_time"), Err(ShellError::Unknown));
+        assert_eq!(parse("help me"), Err(ShellError::Unknown));
+        assert_eq!(parse("a b c d e"), Err(ShellError::Unknown));
+    }
+
+    #[test]
+    fn missing_values() {
+        assert_eq!(parse("set hold_time"), Err(ShellError::MissingValue));
+        assert_eq!(parse("buzzer"), Err(ShellError::MissingValue));
+        assert_eq!(parse("keymap begin"), Err(ShellError::MissingValue));
+        assert_eq!(parse("keymap data"), Err(ShellError::MissingValue));
+        assert_eq!(parse("keymap end"), Err(ShellError::MissingValue));
+    }
+
+    #[test]
+    fn wrong_values() {
+        // Hold time out of its range
+        let below = std::format!("set hold_time {}", HOLD_TIME_MIN - 1);
+        let above = std::format!("set hold_time {}", HOLD_TIME_MAX + 1);
+        assert_eq!(parse(&below), Err(ShellError::WrongValue));
+        assert_eq!(parse(&above), Err(ShellError::WrongValue));
+        assert_eq!(parse("set hold_time -5"), Err(ShellError::WrongValue));
+        assert_eq!(parse("set hold_time fast"), Err(ShellError::WrongValue));
+
+        let min = std::format!("set hold_time {}", HOLD_TIME_MIN);
+        let max = std::format!("set hold_time {}", HOLD_TIME_MAX);
+        assert_eq!(parse(&min), Ok(Command::SetHoldTime(HOLD_TIME_MIN)));
+        assert_eq!(parse(&max), Ok(Command::SetHoldTime(HOLD_TIME_MAX)));
+
+        assert_eq!(parse("buzzer maybe"), Err(ShellError::WrongValue));
+        assert_eq!(parse("keymap begin many"), Err(ShellError::WrongValue));
+        assert_eq!(parse("keymap end 12345678z"), Err(ShellError::WrongValue));
+    }
+
+    #[test]
+    fn wrong_keymap_data() {
+        // Odd length
+        assert_eq!(parse("keymap data 0a1"), Err(ShellError::WrongValue));
+        // Not hex
+        assert_eq!(parse("keymap data 0g"), Err(ShellError::WrongValue));
+        assert_eq!(parse("keymap data +1"), Err(ShellError::WrongValue));
+
+        // More than a chunk
+        let full: String = "ab".repeat(KEYMAP_CHUNK);
+        let over: String = "ab".repeat(KEYMAP_CHUNK + 1);
+        assert!(parse(&std::format!("keymap data {}", full)).is_ok());
+        assert_eq!(
+            parse(&std::format!("keymap data {}", over)),
+            Err(ShellError::WrongValue)
+        );
+    }
+
+    #[test]
+    fn lines_are_typed() {
+        let mut shell = Shell::new();
+        assert_eq!(typed(&mut shell, "layers"), Some(Ok(Command::Layers)));
+
+        // Empty lines are skipped, \n also ends a line
+        assert_eq!(typed(&mut shell, "   "), None);
+        assert_eq!(shell.input(b'\n'), None);
+
+        // Backspace & delete, other control characters are ignored
+        assert_eq!(typed(&mut shell, "statux\x08s"), Some(Ok(Command::Status)));
+        assert_eq!(typed(&mut shell, "he\x1blpp\x7f"), Some(Ok(Command::Help)));
+
+        assert_eq!(typed(&mut shell, "nope"), Some(Err(ShellError::Unknown)));
+    }
+
+    #[test]
+    fn line_overflow() {
+        let mut shell = Shell::new();
+        let long: String = "a".repeat(SHELL_LINE_LENGTH + 1);
+        assert_eq!(typed(&mut shell, &long), Some(Err(ShellError::TooLong)));
+
+        // The next line is read as usual
+        assert_eq!(typed(&mut shell, "help"), Some(Ok(Command::Help)));
+
+        // A line of the max length is fine
+        let exact: String = std::format!("{:<1$}", "help", SHELL_LINE_LENGTH);
+        assert_eq!(typed(&mut shell, &exact), Some(Ok(Command::Help)));
+    }
+
+    #[test]
+    fn echo_of_the_typed_bytes() {
+        assert_eq!(echo(&b'a'), b"a");
+        assert_eq!(echo(&b'\r'), b"\r\n");
+        assert_eq!(echo(&0x7F), b"\x08 \x08");
+    }
+}
@@ -8,15 +8,33 @@ pub enum Status {
     SwitchOff,
 }
 
+impl Status {
+    pub fn is_on(&self) -> bool {
+        matches!(self, Status::On | Status::SwitchOn)
+    }
+}
+
 pub struct Statuses {
     pub layout_fr: Status,
     pub layout_fn: Status,
+    pub layout_pad: Status,
+    pub layout_midi: Status,
+    pub layout_steno: Status,
     pub leader_key: Status,
     pub caplock: Status,
+    pub num_lock: Status,
+    pub scroll_lock: Status,
     pub dynmac_rec_waitkey: Status,
     pub dynmac_rec_inprogess: Status,
     pub dynmac_go_waitkey: Status,
+    pub dynmac_append_waitkey: Status,
+    pub dynmac_delete_waitkey: Status,
+    pub dynmac_playing: Status,
+    pub mouse_lock: Status,
+    pub mouse_grid: Status,
     pub buzzer_activation: Status,
+    pub setting: Status,
+    pub setting_limit: Status,
 }
 
 impl Statuses {
@@ -24,18 +42,32 @@ impl Statuses {
         Statuses {
             layout_fr: Status::Off,
             layout_fn: Status::Off,
+            layout_pad: Status::Off,
+            layout_midi: Status::Off,
+            layout_steno: Status::Off,
             leader_key: Status::Off,
             caplock: Status::Off,
+            num_lock: Status::Off,
+            scroll_lock: Status::Off,
 
             dynmac_rec_waitkey: Status::Off,
             dynmac_rec_inprogess: Status::Off,
             dynmac_go_waitkey: Status::Off,
+            dynmac_append_waitkey: Status::Off,
+            dynmac_delete_waitkey: Status::Off,
+            dynmac_playing: Status::Off,
+
+            mouse_lock: Status::Off,
+            mouse_grid: Status::Off,
 
             buzzer_activation: if BUZZER_STARTUP_ACTIVATION {
                 Status::On
             } else {
                 Status::Off
             },
+
+            setting: Status::Off,
+            setting_limit: Status::Off,
         }
     }
 
@@ -43,14 +75,26 @@ impl Statuses {
         match who {
             "FR" => self.layout_fr = next(&self.layout_fr, value),
             "FN" => self.layout_fn = next(&self.layout_fn, value),
+            "PAD" => self.layout_pad = next(&self.layout_pad, value),
+            "MIDI" => self.layout_midi = next(&self.layout_midi, value),
+            "STENO" => self.layout_steno = next(&self.layout_steno, value),
             "LEADER" => self.leader_key = next(&self.leader_key, value),
             "CAPLOCK" => self.caplock = next(&self.caplock, value),
+            "NUMLOCK" => self.num_lock = next(&self.num_lock, value),
+            "SCROLLLOCK" => self.scroll_lock = next(&self.scroll_lock, value),
 
             "DN_REC_WAIT" => self.dynmac_rec_waitkey = next(&self.dynmac_rec_waitkey, value),
             "DN_REC_PROG" => self.dynmac_rec_inprogess = next(&self.dynmac_rec_inprogess, value),
             "DN_GO_WAIT" => self.dynmac_go_waitkey = next(&self.dynmac_go_waitkey, value),
+            "DN_APP_WAIT" => self.dynmac_append_waitkey = next(&self.dynmac_append_waitkey, value),
+            "DN_DEL_WAIT" => self.dynmac_delete_waitkey = next(&self.dynmac_delete_waitkey, value),
+            "DN_PLAY" => self.dynmac_playing = next(&self.dynmac_playing, value),
+            "MOUSE_LOCK" => self.mouse_lock = next(&self.mouse_lock, value),
+            "MOUSE_GRID" => self.mouse_grid = next(&self.mouse_grid, value),
 
             "BUZZER" => self.buzzer_activation = next(&self.buzzer_activation, value),
+            "SETTING" => self.setting = next(&self.setting, value),
+            "SETTING_LIMIT" => self.setting_limit = next(&self.setting_limit, value),
             _ => {}
         }
     }
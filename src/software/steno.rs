@@ -0,0 +1,140 @@
+use heapless::Vec;
+
+use super::keys::Steno;
+
+/// Byte & bit of each steno key in a GeminiPR packet (in the order of the Steno enum).
+const GEMINI: [(usize, u8); 23] = [
+    (1, 0x40), // S-
+    (1, 0x10), // T-
+    (1, 0x08), // K-
+    (1, 0x04), // P-
+    (1, 0x02), // W-
+    (1, 0x01), // H-
+    (2, 0x40), // R-
+    (2, 0x20), // A
+    (2, 0x10), // O
+    (2, 0x08), // *
+    (3, 0x08), // E
+    (3, 0x04), // U
+    (3, 0x02), // -F
+    (3, 0x01), // -R
+    (4, 0x40), // -P
+    (4, 0x20), // -B
+    (4, 0x10), // -L
+    (4, 0x08), // -G
+    (4, 0x04), // -T
+    (4, 0x02), // -S
+    (4, 0x01), // -D
+    (5, 0x01), // -Z
+    (0, 0x20), // #
+];
+
+/// Steno keys are collected as long as a key is held down.
+/// Once the matrix is released, the whole chord is given back to be sent.
+pub struct StenoChord {
+    chord: u32,
+}
+
+impl StenoChord {
+    pub fn new() -> Self {
+        StenoChord { chord: 0 }
+    }
+
+    pub fn add(&mut self, key: Steno) {
+        self.chord |= 1 << key as u32;
+    }
+
+    /// The complete chord once all the keys are released.
+    pub fn take(&mut self, released: bool) -> Option<u32> {
+        match released && self.chord != 0 {
+            true => Some(core::mem::take(&mut self.chord)),
+            false => None,
+        }
+    }
+}
+
+/// GeminiPR, 6 bytes with the first bit set.
+pub fn gemini(chord: u32) -> [u8; 6] {
+    let mut packet = [0x80, 0, 0, 0, 0, 0];
+    for (bit, (byte, mask)) in GEMINI.iter().enumerate() {
+        if chord & (1 << bit) != 0 {
+            packet[*byte] |= mask;
+        }
+    }
+    packet
+}
+
+/// TX Bolt, one byte per group of 6 keys (group number in the two high bits).
+/// Empty groups are skipped and the final 0 ends the stroke.
+pub fn tx_bolt(chord: u32) -> Vec<u8, 5> {
+    let mut packet: Vec<u8, 5> = (0..4)
+        .map(|group| (group << 6, (chord >> (group * 6)) as u8 & 0x3F))
+        .filter(|(_, keys)| *keys != 0)
+        .map(|(group, keys)| group as u8 | keys)
+        .collect();
+
+    packet.push(0).ok();
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(keys: &[Steno]) -> u32 {
+        let mut chord = StenoChord::new();
+        for key in keys {
+            chord.add(*key);
+        }
+        chord.take(true).unwrap()
+    }
+
+    #[test]
+    fn chord_is_given_once_released() {
+        let mut chord = StenoChord::new();
+        assert_eq!(chord.take(true), None);
+
+        chord.add(Steno::SL);
+        assert_eq!(chord.take(false), None);
+        chord.add(Steno::A);
+        assert_eq!(chord.take(true), Some(1 << 0 | 1 << 7));
+        assert_eq!(chord.take(true), None);
+    }
+
+    #[test]
+    fn gemini_packets() {
+        let stkpw = chord(&[Steno::SL, Steno::TL, Steno::KL, Steno::PL, Steno::WL]);
+        assert_eq!(gemini(stkpw), [0x80, 0x5E, 0x00, 0x00, 0x00, 0x00]);
+
+        let number = chord(&[
+            Steno::Num,
+            Steno::HL,
+            Steno::O,
+            Steno::U,
+            Steno::LR,
+            Steno::ZR,
+        ]);
+        assert_eq!(gemini(number), [0xA0, 0x01, 0x10, 0x04, 0x10, 0x01]);
+
+        // The first bit is only set on the first byte
+        let all = (0..23).fold(0, |chord, bit| chord | 1 << bit);
+        let packet = gemini(all);
+        assert_eq!(packet[0] & 0x80, 0x80);
+        assert!(packet[1..].iter().all(|byte| byte & 0x80 == 0));
+    }
+
+    #[test]
+    fn tx_bolt_packets() {
+        // One group
+        assert_eq!(tx_bolt(chord(&[Steno::SL])), [0x01, 0x00]);
+        assert_eq!(tx_bolt(chord(&[Steno::Num])), [0xD0, 0x00]);
+
+        // K- A -T, the empty group is skipped
+        let kat = chord(&[Steno::KL, Steno::A, Steno::TR]);
+        assert_eq!(tx_bolt(kat), [0x04, 0x42, 0xC1, 0x00]);
+
+        // All the groups
+        let all = (0..23).fold(0, |chord, bit| chord | 1 << bit);
+        assert_eq!(tx_bolt(all), [0x3F, 0x7F, 0xBF, 0xDF, 0x00]);
+    }
+}
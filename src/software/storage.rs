@@ -0,0 +1,658 @@
+use heapless::Vec;
+
+pub const SECTOR_SIZE: usize = 4096;
+pub const PAGE_SIZE: usize = 256;
+
+/// Raw access to a reserved flash region.
+/// Offsets start at the beginning of the region, which is a multiple of SECTOR_SIZE.
+/// Erased bytes are 0xFF and programming can only clear bits, so a sector has to be erased
+/// before being programmed again.
+pub trait Flash {
+    fn capacity(&self) -> usize;
+    fn read(&self, offset: usize, buffer: &mut [u8]);
+    /// Offset is aligned on a sector.
+    fn erase_sector(&mut self, offset: usize);
+    /// Offset is aligned on a page.
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]);
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StorageError {
+    TooLarge,
+}
+
+/// Flash emulated in ram, used by the host tests to check the stores without the board.
+#[cfg(test)]
+pub struct RamFlash {
+    pub bytes: std::vec::Vec<u8>,
+    pub erase_count: std::vec::Vec<u32>, // One per sector
+}
+
+#[cfg(test)]
+impl RamFlash {
+    pub fn new(size: usize) -> Self {
+        RamFlash {
+            bytes: std::vec![0xFF; size],
+            erase_count: std::vec![0; size / SECTOR_SIZE],
+        }
+    }
+}
+
+#[cfg(test)]
+impl Flash for RamFlash {
+    fn capacity(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.bytes[offset..offset + buffer.len()]);
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        self.bytes[offset..offset + SECTOR_SIZE].fill(0xFF);
+        self.erase_count[offset / SECTOR_SIZE] += 1;
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]) {
+        self.bytes[offset..offset + PAGE_SIZE]
+            .iter_mut()
+            .zip(data.iter())
+            .for_each(|(b, d)| *b &= d);
+    }
+}
+
+// --------------------------------------------------------------------------------------
+// --------------------------------------------------------------------------- RECORDS --
+
+const MAGIC: u16 = 0xC4E7;
+const HEADER_LENGTH: usize = 16;
+
+/// Header
+///
+///   Magic    Version  Kind     Length      Sequence              Crc
+/// 0xC4E7  -  0000  -  0000  -  0000 0000 - 0000 0000 0000 0000 - 0000 0000 0000 0000 ...
+///
+/// The crc covers the header (without the crc) and the payload.
+#[derive(Clone, Copy)]
+struct Header {
+    version: u8,
+    kind: u8,
+    length: usize,
+    sequence: u32,
+    crc: u32,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LENGTH] {
+        let mut bytes = [0xFF; HEADER_LENGTH];
+        bytes[0..2].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[2] = self.version;
+        bytes[3] = self.kind;
+        bytes[4..6].copy_from_slice(&(self.length as u16).to_le_bytes());
+        bytes[6..10].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[10..14].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; HEADER_LENGTH]) -> Option<Header> {
+        if u16::from_le_bytes([bytes[0], bytes[1]]) != MAGIC {
+            return None;
+        }
+
+        Some(Header {
+            version: bytes[2],
+            kind: bytes[3],
+            length: u16::from_le_bytes([bytes[4], bytes[5]]) as usize,
+            sequence: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]),
+            crc: u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]),
+        })
+    }
+
+    fn compute_crc<I: Iterator<Item = u8>>(&self, payload: I) -> u32 {
+        let bytes = self.to_bytes();
+        crc32(bytes[0..10].iter().copied().chain(payload))
+    }
+}
+
+/// Log of records written one after the other in a flash region used as a ring.
+/// Only the newest valid record matters, the previous ones stay until their sector is needed.
+///
+/// Wear: each save uses the following pages so the sectors are erased in turn.
+/// Corruption: a record with a bad crc (e.g. unplugged while writing) is ignored and the
+/// previous one is loaded. Records with another version or kind are ignored as well.
+pub struct RecordStore<F: Flash> {
+    flash: F,
+    version: u8,
+    kind: u8,
+    last: Option<(usize, Header)>,
+    sequence: u32,
+    next: usize,
+}
+
+impl<F: Flash> RecordStore<F> {
+    pub fn new(flash: F, version: u8, kind: u8) -> Self {
+        let mut store = RecordStore {
+            flash,
+            version,
+            kind,
+            last: None,
+            sequence: 0,
+            next: 0,
+        };
+        store.scan();
+        store
+    }
+
+    /// Find the newest valid record & the next writing position.
+    fn scan(&mut self) {
+        let mut offset = 0;
+        let mut found = false;
+
+        while offset < self.flash.capacity() {
+            let mut bytes = [0; HEADER_LENGTH];
+            self.read(offset, &mut bytes);
+
+            match Header::from_bytes(&bytes) {
+                Some(header)
+                    if HEADER_LENGTH + header.length <= self.max_length()
+                        && header.compute_crc(self.payload(offset, header.length))
+                            == header.crc =>
+                {
+                    let end = page_ceil(offset + HEADER_LENGTH + header.length);
+
+                    // Keep writing after the newest one, whatever its version is
+                    if !found || header.sequence > self.sequence {
+                        self.sequence = header.sequence;
+                        self.next = end % self.flash.capacity();
+                        found = true;
+                    }
+                    if header.version == self.version
+                        && header.kind == self.kind
+                        && self.last.is_none_or(|(_, h)| header.sequence > h.sequence)
+                    {
+                        self.last = Some((offset, header));
+                    }
+
+                    offset = end;
+                }
+                _ => offset += PAGE_SIZE,
+            }
+        }
+    }
+
+    /// Biggest record (header included) which can be written without erasing the last one.
+    fn max_length(&self) -> usize {
+        (self.flash.capacity() - SECTOR_SIZE) / 2
+    }
+
+    /// Read with wrapping around the end of the region.
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        let offset = offset % self.flash.capacity();
+        let first = buffer.len().min(self.flash.capacity() - offset);
+
+        self.flash.read(offset, &mut buffer[..first]);
+        if first < buffer.len() {
+            self.flash.read(0, &mut buffer[first..]);
+        }
+    }
+
+    fn payload(&self, offset: usize, length: usize) -> Payload<'_, F> {
+        Payload {
+            store: self,
+            offset: offset + HEADER_LENGTH,
+            end: offset + HEADER_LENGTH + length,
+            chunk: [0; 32],
+            chunk_offset: usize::MAX,
+        }
+    }
+
+    /// Bytes of the newest valid record.
+    pub fn load(&self) -> Option<Payload<'_, F>> {
+        self.last
+            .map(|(offset, header)| self.payload(offset, header.length))
+    }
+
+    /// Write a new record after the newest one.
+    /// Payload is iterated twice, once for the crc and once to program the pages.
+    pub fn save<I: Iterator<Item = u8> + Clone>(&mut self, payload: I) -> Result<(), StorageError> {
+        let length = payload.clone().count();
+        if HEADER_LENGTH + length > self.max_length() || length > u16::MAX as usize {
+            return Err(StorageError::TooLarge);
+        }
+
+        let mut header = Header {
+            version: self.version,
+            kind: self.kind,
+            length,
+            sequence: self.sequence.wrapping_add(1),
+            crc: 0,
+        };
+        header.crc = header.compute_crc(payload.clone());
+
+        // The end of the current sector has to be blank (a write can have been interrupted)
+        let mut start = self.next;
+        let sector_end = start - start % SECTOR_SIZE + SECTOR_SIZE;
+        if !start.is_multiple_of(SECTOR_SIZE) && !self.is_blank(start, sector_end) {
+            start = sector_end % self.flash.capacity();
+        }
+
+        // Program page by page & erase each new sector just before using it
+        let mut bytes = header.to_bytes().into_iter().chain(payload).peekable();
+        let mut offset = start;
+        while bytes.peek().is_some() {
+            if offset.is_multiple_of(SECTOR_SIZE) {
+                self.flash.erase_sector(offset);
+            }
+
+            let mut page = [0xFF; PAGE_SIZE];
+            page.iter_mut().zip(&mut bytes).for_each(|(p, b)| *p = b);
+            self.flash.program_page(offset, &page);
+
+            offset = (offset + PAGE_SIZE) % self.flash.capacity();
+        }
+
+        self.last = Some((start, header));
+        self.sequence = header.sequence;
+        self.next = offset;
+        Ok(())
+    }
+
+    /// The flash back, to open it again as after a restart.
+    #[cfg(test)]
+    pub fn into_flash(self) -> F {
+        self.flash
+    }
+
+    fn is_blank(&self, from: usize, to: usize) -> bool {
+        let mut chunk = [0; 32];
+        (from..to).step_by(chunk.len()).all(|offset| {
+            self.read(offset, &mut chunk);
+            chunk.iter().all(|b| *b == 0xFF)
+        })
+    }
+}
+
+/// Iterator over the payload bytes, read by small chunks to keep the ram usage low.
+pub struct Payload<'a, F: Flash> {
+    store: &'a RecordStore<F>,
+    offset: usize,
+    end: usize,
+    chunk: [u8; 32],
+    chunk_offset: usize,
+}
+
+impl<F: Flash> Iterator for Payload<'_, F> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.offset >= self.end {
+            return None;
+        }
+
+        let chunk_offset = self.offset - self.offset % self.chunk.len();
+        if chunk_offset != self.chunk_offset {
+            self.store.read(chunk_offset, &mut self.chunk);
+            self.chunk_offset = chunk_offset;
+        }
+
+        let value = self.chunk[self.offset - chunk_offset];
+        self.offset += 1;
+        Some(value)
+    }
+}
+
+// --------------------------------------------------------------------------------------
+// ------------------------------------------------------------------------- KEY-VALUE --
+
+const ENTRY_LENGTH: usize = 5;
+
+/// Small key-value store (u8 keys, u32 values) kept in ram, all the entries are saved in one
+/// record so the wear levelling and the crc come from the RecordStore.
+///
+/// Format
+///
+///  Key         Value (5 bytes per entry)
+///  0000 0000 - 0000 0000 0000 0000 0000 0000 0000 0000 ...
+pub struct KvStore<F: Flash, const N: usize> {
+    store: RecordStore<F>,
+    entries: Vec<(u8, u32), N>,
+    dirty: bool,
+}
+
+impl<F: Flash, const N: usize> KvStore<F, N> {
+    pub fn new(flash: F, version: u8, kind: u8) -> Self {
+        let store = RecordStore::new(flash, version, kind);
+        let mut entries = Vec::new();
+
+        if let Some(mut bytes) = store.load() {
+            let mut entry = [0; ENTRY_LENGTH];
+            while entry.iter_mut().all(|b| match bytes.next() {
+                Some(value) => {
+                    *b = value;
+                    true
+                }
+                None => false,
+            }) {
+                let value = u32::from_le_bytes([entry[1], entry[2], entry[3], entry[4]]);
+                entries.push((entry[0], value)).ok();
+            }
+        }
+
+        KvStore {
+            store,
+            entries,
+            dirty: false,
+        }
+    }
+
+    pub fn get(&self, key: u8) -> Option<u32> {
+        self.entries
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    /// Returns true if the value has changed (it's saved with the next save()).
+    pub fn set(&mut self, key: u8, value: u32) -> bool {
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) if *v == value => return false,
+            Some((_, v)) => *v = value,
+            None => {
+                if self.entries.push((key, value)).is_err() {
+                    return false;
+                }
+            }
+        }
+
+        self.dirty = true;
+        true
+    }
+
+    /// Write a new record if a value has changed.
+    pub fn save(&mut self) -> Result<(), StorageError> {
+        if self.dirty {
+            self.store.save(
+                self.entries
+                    .iter()
+                    .flat_map(|(key, value)| core::iter::once(*key).chain(value.to_le_bytes())),
+            )?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+fn page_ceil(offset: usize) -> usize {
+    offset.div_ceil(PAGE_SIZE) * PAGE_SIZE
+}
+
+/// Crc-32 (IEEE), computed bit by bit to avoid a table.
+pub fn crc32<I: Iterator<Item = u8>>(bytes: I) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{FLASH_DYNMAC_SIZE, FLASH_SETTINGS_SIZE};
+    use std::vec::Vec;
+
+    fn record(seed: u8, length: usize) -> Vec<u8> {
+        (0..length).map(|i| seed.wrapping_add(i as u8)).collect()
+    }
+
+    /// Same flash, as after a restart.
+    fn reopen(store: RecordStore<RamFlash>, version: u8, kind: u8) -> RecordStore<RamFlash> {
+        RecordStore::new(store.flash, version, kind)
+    }
+
+    fn reopen_kv(kv: KvStore<RamFlash, 4>) -> KvStore<RamFlash, 4> {
+        KvStore::new(kv.store.flash, 1, 2)
+    }
+
+    fn loaded(store: &RecordStore<RamFlash>) -> Option<Vec<u8>> {
+        store.load().map(|payload| payload.collect())
+    }
+
+    #[test]
+    fn empty_flash() {
+        let store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        assert_eq!(loaded(&store), None);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        store.save(record(1, 300).into_iter()).unwrap();
+        store.save(record(2, 10).into_iter()).unwrap();
+        assert_eq!(loaded(&store), Some(record(2, 10)));
+
+        let store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(2, 10)));
+    }
+
+    #[test]
+    fn too_large() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        assert_eq!(
+            store.save(record(1, 2 * SECTOR_SIZE).into_iter()),
+            Err(StorageError::TooLarge)
+        );
+        assert_eq!(loaded(&store), None);
+    }
+
+    #[test]
+    fn bad_crc_falls_back_to_the_previous_record() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        store.save(record(1, 100).into_iter()).unwrap();
+        store.save(record(2, 100).into_iter()).unwrap();
+
+        // A bit of the second payload is cleared
+        let (offset, _) = store.last.unwrap();
+        store.flash.bytes[offset + HEADER_LENGTH + 50] &= 0x0F;
+
+        let mut store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(1, 100)));
+
+        // The next save goes after the broken one
+        store.save(record(3, 100).into_iter()).unwrap();
+        let store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(3, 100)));
+    }
+
+    #[test]
+    fn torn_record_falls_back_to_the_previous_record() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        store.save(record(1, 100).into_iter()).unwrap();
+        store.save(record(2, 1000).into_iter()).unwrap();
+
+        // Unplugged after the first page of the second record
+        let (offset, _) = store.last.unwrap();
+        store.flash.bytes[offset + PAGE_SIZE..offset + 4 * PAGE_SIZE].fill(0xFF);
+
+        let mut store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(1, 100)));
+
+        store.save(record(3, 1000).into_iter()).unwrap();
+        let store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(3, 1000)));
+    }
+
+    #[test]
+    fn torn_sector_keeps_the_end_of_the_previous_one() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        store.save(record(1, 100).into_iter()).unwrap();
+
+        // A header was written at the next position, the rest of the sector isn't blank anymore
+        let next = store.next;
+        store.flash.bytes[next..next + 8].fill(0);
+
+        let mut store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(1, 100)));
+
+        store.save(record(2, 100).into_iter()).unwrap();
+        assert_eq!(store.last.unwrap().0, SECTOR_SIZE);
+
+        let store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(2, 100)));
+    }
+
+    #[test]
+    fn wrap_around() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+
+        // 1500 bytes per record (6 pages), the 11th one starts at the end of the region
+        let mut wrapped = false;
+        for seed in 0..40 {
+            store.save(record(seed, 1500).into_iter()).unwrap();
+
+            let (offset, header) = store.last.unwrap();
+            wrapped |= offset + HEADER_LENGTH + header.length > 4 * SECTOR_SIZE;
+
+            store = reopen(store, 1, 1);
+            assert_eq!(loaded(&store), Some(record(seed, 1500)));
+            assert_eq!(store.sequence, seed as u32 + 1);
+        }
+
+        assert!(wrapped);
+        assert!(store.flash.erase_count.iter().all(|count| *count >= 3));
+    }
+
+    #[test]
+    fn other_kind_or_version_is_ignored() {
+        let mut store = RecordStore::new(RamFlash::new(4 * SECTOR_SIZE), 1, 1);
+        store.save(record(1, 100).into_iter()).unwrap();
+
+        let store = reopen(store, 1, 2);
+        assert_eq!(loaded(&store), None);
+
+        // A new version starts from scratch and doesn't overwrite the old record
+        let mut store = reopen(store, 2, 1);
+        assert_eq!(loaded(&store), None);
+        store.save(record(2, 100).into_iter()).unwrap();
+
+        let store = reopen(store, 1, 1);
+        assert_eq!(loaded(&store), Some(record(1, 100)));
+        let store = reopen(store, 2, 1);
+        assert_eq!(loaded(&store), Some(record(2, 100)));
+    }
+
+    #[test]
+    fn erase_count_covers_the_region() {
+        let mut flash = RamFlash::new(FLASH_DYNMAC_SIZE);
+        assert_eq!(flash.erase_count.len(), FLASH_DYNMAC_SIZE / SECTOR_SIZE);
+
+        flash.erase_sector(FLASH_DYNMAC_SIZE - SECTOR_SIZE);
+        assert_eq!(flash.erase_count.last(), Some(&1));
+    }
+
+    #[test]
+    fn kv_save_and_load() {
+        let mut kv: KvStore<RamFlash, 4> = KvStore::new(RamFlash::new(FLASH_SETTINGS_SIZE), 1, 2);
+        assert_eq!(kv.get(1), None);
+
+        assert!(kv.set(1, 180));
+        assert!(kv.set(7, u32::MAX));
+        assert!(!kv.set(1, 180));
+        kv.save().unwrap();
+
+        let mut kv = reopen_kv(kv);
+        assert_eq!(kv.get(1), Some(180));
+        assert_eq!(kv.get(7), Some(u32::MAX));
+        assert_eq!(kv.get(2), None);
+
+        assert!(kv.set(1, 200));
+        kv.save().unwrap();
+        let kv = reopen_kv(kv);
+        assert_eq!(kv.get(1), Some(200));
+        assert_eq!(kv.get(7), Some(u32::MAX));
+    }
+
+    #[test]
+    fn kv_saves_only_the_changes() {
+        let mut kv: KvStore<RamFlash, 4> = KvStore::new(RamFlash::new(FLASH_SETTINGS_SIZE), 1, 2);
+        kv.save().unwrap();
+        assert!(kv.store.last.is_none());
+
+        kv.set(1, 180);
+        kv.save().unwrap();
+        let sequence = kv.store.sequence;
+
+        kv.set(1, 180);
+        kv.save().unwrap();
+        assert_eq!(kv.store.sequence, sequence);
+    }
+
+    #[test]
+    fn kv_full() {
+        let mut kv: KvStore<RamFlash, 4> = KvStore::new(RamFlash::new(FLASH_SETTINGS_SIZE), 1, 2);
+        for key in 0..4 {
+            assert!(kv.set(key, key as u32));
+        }
+        assert!(!kv.set(4, 4));
+        assert!(kv.set(3, 30));
+        kv.save().unwrap();
+
+        let kv = reopen_kv(kv);
+        assert_eq!(kv.get(3), Some(30));
+        assert_eq!(kv.get(4), None);
+    }
+
+    #[test]
+    fn kv_wear_levelling() {
+        let mut kv: KvStore<RamFlash, 4> = KvStore::new(RamFlash::new(FLASH_SETTINGS_SIZE), 1, 2);
+
+        // One page per save, each sector is erased in turn
+        let pages = FLASH_SETTINGS_SIZE / PAGE_SIZE;
+        for value in 0..10 * pages as u32 {
+            kv.set(1, value);
+            kv.set(2, value / 2);
+            kv.save().unwrap();
+
+            if value % 50 == 0 {
+                kv = reopen_kv(kv);
+                assert_eq!(kv.get(1), Some(value));
+            }
+        }
+
+        let counts = &kv.store.flash.erase_count;
+        assert!(counts.iter().all(|count| (10..=11).contains(count)));
+
+        let kv = reopen_kv(kv);
+        assert_eq!(kv.get(1), Some(10 * pages as u32 - 1));
+        assert_eq!(kv.get(2), Some((10 * pages as u32 - 1) / 2));
+    }
+
+    #[test]
+    fn kv_corrupt_last_record_is_ignored() {
+        let mut kv: KvStore<RamFlash, 4> = KvStore::new(RamFlash::new(FLASH_SETTINGS_SIZE), 1, 2);
+        kv.set(1, 180);
+        kv.set(2, 5);
+        kv.save().unwrap();
+        kv.set(1, 300);
+        kv.save().unwrap();
+
+        // The value of the last record is damaged
+        let (offset, _) = kv.store.last.unwrap();
+        kv.store.flash.bytes[offset + HEADER_LENGTH + 1] ^= 0x01;
+
+        let mut kv = reopen_kv(kv);
+        assert_eq!(kv.get(1), Some(180));
+        assert_eq!(kv.get(2), Some(5));
+
+        // And the next save is loaded as usual
+        kv.set(2, 6);
+        kv.save().unwrap();
+        let kv = reopen_kv(kv);
+        assert_eq!(kv.get(1), Some(180));
+        assert_eq!(kv.get(2), Some(6));
+    }
+}
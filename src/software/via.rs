@@ -0,0 +1,754 @@
+use heapless::Vec;
+use usbd_human_interface_device::page::Keyboard;
+
+use super::{
+    keys::{BuffCase, Buffer, Media, Pad, Setting, Steno, KC},
+    macros::str_to_usb_code,
+    storage::{Flash, RecordStore},
+};
+use crate::options::{
+    BUFFER_CASE_LENGTH, NB_KEYS, SETTINGS_SAVE_DELAY, VIA_BOOTLOADER, VIA_COLS, VIA_MACRO_BUFFER,
+    VIA_MACRO_COUNT, VIA_ROWS,
+};
+
+pub const VIA_REPORT_LENGTH: usize = 32;
+const PROTOCOL_VERSION: u16 = 0x000C;
+
+// Increase the version each time the format below changes (old records are then ignored).
+const STORAGE_VERSION: u8 = 1;
+const STORAGE_KIND: u8 = 4;
+
+// Commands (first byte of a packet), the answer is the packet with its values filled in
+const GET_PROTOCOL_VERSION: u8 = 0x01;
+const GET_KEYBOARD_VALUE: u8 = 0x02;
+const SET_KEYBOARD_VALUE: u8 = 0x03;
+const GET_KEYCODE: u8 = 0x04;
+const SET_KEYCODE: u8 = 0x05;
+const KEYMAP_RESET: u8 = 0x06;
+const EEPROM_RESET: u8 = 0x0A;
+const BOOTLOADER_JUMP: u8 = 0x0B;
+const MACRO_GET_COUNT: u8 = 0x0C;
+const MACRO_GET_BUFFER_SIZE: u8 = 0x0D;
+const MACRO_GET_BUFFER: u8 = 0x0E;
+const MACRO_SET_BUFFER: u8 = 0x0F;
+const MACRO_RESET: u8 = 0x10;
+const GET_LAYER_COUNT: u8 = 0x11;
+const KEYMAP_GET_BUFFER: u8 = 0x12;
+const KEYMAP_SET_BUFFER: u8 = 0x13;
+const UNHANDLED: u8 = 0xFF;
+
+// Keyboard values
+const UPTIME: u8 = 0x01;
+const LAYOUT_OPTIONS: u8 = 0x02;
+const FIRMWARE_VERSION: u8 = 0x04;
+const DEVICE_INDICATION: u8 = 0x05;
+
+// Get/set buffer packets: command, offset (2 bytes), size, then the data
+const BUFFER_DATA: usize = VIA_REPORT_LENGTH - 4;
+
+// Macro actions, each one follows a MACRO_PREFIX in the text
+const MACRO_PREFIX: u8 = 0x01;
+const MACRO_TAP: u8 = 0x01;
+const MACRO_DOWN: u8 = 0x02;
+const MACRO_UP: u8 = 0x03;
+const MACRO_DELAY: u8 = 0x04; // Followed by the ms in ascii & '|'
+
+/// Key index at each position of the VIA matrix (the json definitions are in tools/via).
+const NO_KEY: u8 = 0xFF;
+#[rustfmt::skip]
+const MATRIX: [[u8; VIA_COLS]; VIA_ROWS] = [
+    [     0,      1,  2,  3,      4,      5,  6,  7,      8,      9],
+    [    10,     11, 12, 13,     14,     15, 16, 17,     18,     19],
+    [    20,     21, 22, 23, NO_KEY, NO_KEY, 24, 25,     26,     27],
+    [NO_KEY, NO_KEY, 28, 29,     30,     31, 32, 33, NO_KEY, NO_KEY],
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ViaEvent {
+    None,
+    KeymapChanged,
+    KeymapReset,
+    Bootloader,
+}
+
+/// VIA protocol (version 12) over the raw HID interface (see hid_via.rs).
+/// Keys are read & written in the runtime keymap with their VIA keycode (see to_via & from_via),
+/// the macros are kept here and saved in flash once the keyboard is idle.
+///
+/// The flash is a parameter so the packets can be checked without the board (see RamFlash).
+pub struct Via<F: Flash> {
+    macros: [u8; VIA_MACRO_BUFFER],
+    store: RecordStore<F>,
+    changed_at: Option<u32>,
+}
+
+impl<F: Flash> Via<F> {
+    pub fn new(flash: F) -> Self {
+        let store = RecordStore::new(flash, STORAGE_VERSION, STORAGE_KIND);
+
+        let mut macros = [0; VIA_MACRO_BUFFER];
+        if let Some(payload) = store.load() {
+            for (byte, stored) in macros.iter_mut().zip(payload) {
+                *byte = stored;
+            }
+        }
+
+        Via {
+            macros,
+            store,
+            changed_at: None,
+        }
+    }
+
+    /// Answer a packet in place, the event tells what the keymap owner has to do.
+    pub fn command(
+        &mut self,
+        packet: &mut [u8; VIA_REPORT_LENGTH],
+        layouts: &mut [[KC; NB_KEYS]],
+        ticks: u32,
+    ) -> ViaEvent {
+        let nb_layouts = layouts.len();
+        let offset = u16::from_be_bytes([packet[1], packet[2]]) as usize;
+        let size = (packet[3] as usize).min(BUFFER_DATA);
+
+        match packet[0] {
+            GET_PROTOCOL_VERSION => packet[1..3].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes()),
+            GET_KEYBOARD_VALUE => match packet[1] {
+                UPTIME => packet[2..6].copy_from_slice(&ticks.to_be_bytes()),
+                LAYOUT_OPTIONS | FIRMWARE_VERSION => packet[2..6].fill(0),
+                _ => packet[0] = UNHANDLED,
+            },
+            SET_KEYBOARD_VALUE => match packet[1] {
+                LAYOUT_OPTIONS | DEVICE_INDICATION => {}
+                _ => packet[0] = UNHANDLED,
+            },
+
+            // Keymap --
+            GET_KEYCODE => {
+                let code = key_index(packet[2], packet[3])
+                    .and_then(|index| layouts.get(packet[1] as usize).map(|keys| keys[index]))
+                    .map_or(KC_NO, to_via);
+                packet[4..6].copy_from_slice(&code.to_be_bytes());
+            }
+            SET_KEYCODE => {
+                let code = u16::from_be_bytes([packet[4], packet[5]]);
+                if let Some(index) = key_index(packet[2], packet[3]) {
+                    if let Some(keys) = layouts.get_mut(packet[1] as usize) {
+                        keys[index] = from_via(code, nb_layouts);
+                        return ViaEvent::KeymapChanged;
+                    }
+                }
+            }
+            KEYMAP_RESET => return ViaEvent::KeymapReset,
+            GET_LAYER_COUNT => packet[1] = nb_layouts as u8,
+
+            // The whole keymap as big endian keycodes, layout by layout & row by row
+            KEYMAP_GET_BUFFER => {
+                for (position, code) in (offset / 2..).zip(packet[4..4 + size].chunks_exact_mut(2))
+                {
+                    let key = buffer_key(layouts, position).map_or(KC_NO, |k| to_via(*k));
+                    code.copy_from_slice(&key.to_be_bytes());
+                }
+            }
+            KEYMAP_SET_BUFFER => {
+                for (position, code) in (offset / 2..).zip(packet[4..4 + size].chunks_exact(2)) {
+                    if let Some(key) = buffer_key_mut(layouts, position) {
+                        *key = from_via(u16::from_be_bytes([code[0], code[1]]), nb_layouts);
+                    }
+                }
+                return ViaEvent::KeymapChanged;
+            }
+
+            // Macros --
+            MACRO_GET_COUNT => packet[1] = VIA_MACRO_COUNT as u8,
+            MACRO_GET_BUFFER_SIZE => {
+                packet[1..3].copy_from_slice(&(VIA_MACRO_BUFFER as u16).to_be_bytes())
+            }
+            MACRO_GET_BUFFER => {
+                for (byte, stored) in packet[4..4 + size].iter_mut().zip(
+                    self.macros
+                        .iter()
+                        .skip(offset)
+                        .chain(core::iter::repeat(&0)),
+                ) {
+                    *byte = *stored;
+                }
+            }
+            MACRO_SET_BUFFER => {
+                for (stored, byte) in self
+                    .macros
+                    .iter_mut()
+                    .skip(offset)
+                    .zip(&packet[4..4 + size])
+                {
+                    *stored = *byte;
+                }
+                self.changed_at = Some(ticks);
+            }
+            MACRO_RESET => {
+                self.macros.fill(0);
+                self.changed_at = Some(ticks);
+            }
+
+            EEPROM_RESET => {
+                self.macros.fill(0);
+                self.changed_at = Some(ticks);
+                return ViaEvent::KeymapReset;
+            }
+            BOOTLOADER_JUMP if VIA_BOOTLOADER => return ViaEvent::Bootloader,
+
+            // Custom values (lighting...), the bootloader without its feature & everything else
+            _ => packet[0] = UNHANDLED,
+        }
+
+        ViaEvent::None
+    }
+
+    /// Macros are written once the keyboard has been idle for SETTINGS_SAVE_DELAY.
+    pub fn save(&mut self, idle: bool, ticks: u32) {
+        if let Some(at) = self.changed_at {
+            if idle && ticks.wrapping_sub(at) >= SETTINGS_SAVE_DELAY {
+                let length = self
+                    .macros
+                    .iter()
+                    .rposition(|b| *b != 0)
+                    .map_or(0, |p| p + 1);
+
+                // A failed write is tried again after the next change
+                self.store.save(self.macros[..length].iter().copied()).ok();
+                self.changed_at = None;
+            }
+        }
+    }
+
+    /// Add a macro to the buffer, the macros are separated by a 0.
+    /// The text is typed like the macros of keys.rs, the actions (tap, down, up) use the basic
+    /// keycodes and a delay is a wait after the previous step.
+    pub fn play(&self, number: u8, mut buffer: Buffer) -> Buffer {
+        let Some(text) = self.macros.split(|b| *b == 0).nth(number as usize) else {
+            return buffer;
+        };
+
+        let mut held: Vec<Keyboard, BUFFER_CASE_LENGTH> = Vec::new();
+        let mut i = 0;
+
+        while i < text.len() {
+            if text[i] != MACRO_PREFIX {
+                let end = text[i..]
+                    .iter()
+                    .position(|b| *b == MACRO_PREFIX)
+                    .map_or(text.len(), |p| i + p);
+
+                if let Ok(part) = core::str::from_utf8(&text[i..end]) {
+                    buffer = str_to_usb_code(part, 0, buffer);
+                }
+                i = end;
+                continue;
+            }
+
+            match (text.get(i + 1).copied(), text.get(i + 2).copied()) {
+                (Some(action @ (MACRO_TAP | MACRO_DOWN | MACRO_UP)), Some(code)) => {
+                    let key = Keyboard::from(code);
+                    match action {
+                        MACRO_TAP => {
+                            let mut pressed = held.clone();
+                            pressed.push(key).ok();
+                            buffer = report(buffer, &pressed, 0);
+                        }
+                        MACRO_DOWN => {
+                            held.push(key).ok();
+                        }
+                        _ => held.retain(|k| *k != key),
+                    }
+                    buffer = report(buffer, &held, 0);
+                    i += 3;
+                }
+                (Some(MACRO_DELAY), _) => {
+                    let end = text[i..]
+                        .iter()
+                        .position(|b| *b == b'|')
+                        .map_or(text.len(), |p| i + p);
+
+                    let ms = text[i + 2..end]
+                        .iter()
+                        .filter(|b| b.is_ascii_digit())
+                        .fold(0_u32, |ms, b| {
+                            ms.saturating_mul(10).saturating_add((b - b'0') as u32)
+                        });
+
+                    match buffer.keys.back_mut() {
+                        Some(case) => case.tempo = case.tempo.max(ms),
+                        None => buffer = report(buffer, &[], ms),
+                    }
+                    i = end + 1;
+                }
+                _ => i += 2,
+            }
+        }
+
+        if !held.is_empty() {
+            buffer = report(buffer, &[], 0);
+        }
+        buffer
+    }
+}
+
+/// One report with these keys (none is a release).
+fn report(mut buffer: Buffer, keys: &[Keyboard], tempo: u32) -> Buffer {
+    let key_code = match keys.is_empty() {
+        true => Vec::from_slice(&[Keyboard::NoEventIndicated]).unwrap_or_default(),
+        false => Vec::from_slice(keys).unwrap_or_default(),
+    };
+    buffer.keys.push_back(BuffCase { key_code, tempo }).ok();
+    buffer
+}
+
+fn key_index(row: u8, col: u8) -> Option<usize> {
+    MATRIX
+        .get(row as usize)
+        .and_then(|cols| cols.get(col as usize))
+        .filter(|index| **index != NO_KEY)
+        .map(|index| *index as usize)
+}
+
+/// Position in the get/set buffer: layout, row & col.
+fn buffer_index(position: usize) -> (usize, Option<usize>) {
+    let layout = position / (VIA_ROWS * VIA_COLS);
+    let position = position % (VIA_ROWS * VIA_COLS);
+    (
+        layout,
+        key_index((position / VIA_COLS) as u8, (position % VIA_COLS) as u8),
+    )
+}
+
+fn buffer_key(layouts: &[[KC; NB_KEYS]], position: usize) -> Option<&KC> {
+    let (layout, index) = buffer_index(position);
+    layouts.get(layout)?.get(index?)
+}
+
+fn buffer_key_mut(layouts: &mut [[KC; NB_KEYS]], position: usize) -> Option<&mut KC> {
+    let (layout, index) = buffer_index(position);
+    layouts.get_mut(layout)?.get_mut(index?)
+}
+
+// ------------------------------------------------------------------------- KEYCODES --
+
+const KC_NO: u16 = 0x0000;
+const MOD_TAP: u16 = 0x2000; // Mods (5 bits) << 8 | basic keycode
+const MOMENTARY: u16 = 0x5220; // | layer (5 bits)
+const DEF_LAYER: u16 = 0x5240;
+const ONE_SHOT_LAYER: u16 = 0x5280;
+const MACRO: u16 = 0x7700; // | macro number
+const CUSTOM: u16 = 0x7E00; // + index in CUSTOM_KEYCODES
+
+/// Mods of the mod-tap keycodes (HomeRow).
+const MOD_TAP_MODS: [(&KC, u16); 5] = [
+    (&KC::Ctl, 0x01),
+    (&KC::Sft, 0x02),
+    (&KC::Alt, 0x04),
+    (&KC::Gui, 0x08),
+    (&KC::Altgr, 0x14),
+];
+
+/// Keys with a VIA keycode, the same as the ones of QMK.
+/// Del & BackSpace are swapped as in keys.rs.
+#[rustfmt::skip]
+const KEYCODES: [(KC, u16); 165] = [
+    (KC::A, 0x04), (KC::B, 0x05), (KC::C, 0x06), (KC::D, 0x07), (KC::E, 0x08), (KC::F, 0x09),
+    (KC::G, 0x0A), (KC::H, 0x0B), (KC::I, 0x0C), (KC::J, 0x0D), (KC::K, 0x0E), (KC::L, 0x0F),
+    (KC::M, 0x10), (KC::N, 0x11), (KC::O, 0x12), (KC::P, 0x13), (KC::Q, 0x14), (KC::R, 0x15),
+    (KC::S, 0x16), (KC::T, 0x17), (KC::U, 0x18), (KC::V, 0x19), (KC::W, 0x1A), (KC::X, 0x1B),
+    (KC::Y, 0x1C), (KC::Z, 0x1D),
+
+    (KC::Num1, 0x1E), (KC::Num2, 0x1F), (KC::Num3, 0x20), (KC::Num4, 0x21), (KC::Num5, 0x22),
+    (KC::Num6, 0x23), (KC::Num7, 0x24), (KC::Num8, 0x25), (KC::Num9, 0x26), (KC::Num0, 0x27),
+
+    (KC::Enter, 0x28), (KC::Esc, 0x29), (KC::Del, 0x2A), (KC::Tab, 0x2B), (KC::Space, 0x2C),
+    (KC::Minus, 0x2D), (KC::Equal, 0x2E), (KC::LeftBracket, 0x2F), (KC::RightBracket, 0x30),
+    (KC::Backslash, 0x31), (KC::NonusHash, 0x32), (KC::SemiColon, 0x33), (KC::Quote, 0x34),
+    (KC::Grave, 0x35), (KC::Comma, 0x36), (KC::Dot, 0x37), (KC::Slash, 0x38), (KC::CapLock, 0x39),
+
+    (KC::F1, 0x3A), (KC::F2, 0x3B), (KC::F3, 0x3C), (KC::F4, 0x3D), (KC::F5, 0x3E), (KC::F6, 0x3F),
+    (KC::F7, 0x40), (KC::F8, 0x41), (KC::F9, 0x42), (KC::F10, 0x43), (KC::F11, 0x44), (KC::F12, 0x45),
+
+    (KC::Home, 0x4A), (KC::PageUp, 0x4B), (KC::BackSpace, 0x4C), (KC::End, 0x4D), (KC::PageDown, 0x4E),
+    (KC::Right, 0x4F), (KC::Left, 0x50), (KC::Down, 0x51), (KC::Up, 0x52), (KC::NonusBackslash, 0x64),
+
+    (KC::F13, 0x68), (KC::F14, 0x69), (KC::F15, 0x6A), (KC::F16, 0x6B), (KC::F17, 0x6C), (KC::F18, 0x6D),
+    (KC::F19, 0x6E), (KC::F20, 0x6F), (KC::F21, 0x70), (KC::F22, 0x71), (KC::F23, 0x72), (KC::F24, 0x73),
+
+    (KC::SystemPower, 0xA5), (KC::SystemSleep, 0xA6), (KC::SystemWake, 0xA7),
+    (KC::Media(Media::Mute), 0xA8), (KC::Media(Media::VolumeUp), 0xA9), (KC::Media(Media::VolumeDown), 0xAA),
+    (KC::Media(Media::Next), 0xAB), (KC::Media(Media::Previous), 0xAC), (KC::Media(Media::Stop), 0xAD),
+    (KC::Media(Media::PlayPause), 0xAE), (KC::Media(Media::Calculator), 0xB2), (KC::Media(Media::Search), 0xB4),
+    (KC::Media(Media::Home), 0xB5), (KC::Media(Media::BrightnessUp), 0xBD), (KC::Media(Media::BrightnessDown), 0xBE),
+
+    (KC::MouseUp, 0xCD), (KC::MouseDown, 0xCE), (KC::MouseLeft, 0xCF), (KC::MouseRight, 0xD0),
+    (KC::MouseBtLeft, 0xD1), (KC::MouseBtRight, 0xD2), (KC::MouseBtMiddle, 0xD3),
+    (KC::MouseWheelUp, 0xD9), (KC::MouseWheelDown, 0xDA), (KC::MouseWheelLeft, 0xDB), (KC::MouseWheelRight, 0xDC),
+
+    (KC::Ctl, 0xE0), (KC::Sft, 0xE1), (KC::Alt, 0xE2), (KC::Gui, 0xE3), (KC::Altgr, 0xE6),
+
+    // Shifted
+    (KC::STab, 0x022B), (KC::Tilde, 0x0235), (KC::Exclaim, 0x021E), (KC::At, 0x021F), (KC::Hash, 0x0220),
+    (KC::Dollar, 0x0221), (KC::Percentage, 0x0222), (KC::Circumflex, 0x0223), (KC::Ampersand, 0x0224),
+    (KC::Asterix, 0x0225), (KC::LeftParent, 0x0226), (KC::RightParent, 0x0227), (KC::Underscore, 0x022D),
+    (KC::Plus, 0x022E), (KC::LeftCurly, 0x022F), (KC::RightCurly, 0x0230), (KC::Pipe, 0x0231),
+    (KC::Colon, 0x0233), (KC::DoubleQuote, 0x0234), (KC::LowerThan, 0x0236), (KC::GreaterThan, 0x0237),
+    (KC::Question, 0x0238),
+
+    // Right alt
+    (KC::CCedilla, 0x1436), (KC::EAcute, 0x1408), (KC::AE, 0x141D), (KC::OE, 0x140E),
+    (KC::GuillemetL, 0x142F), (KC::GuillemetD, 0x1430), (KC::Diameter, 0x140F), (KC::Euro, 0x1422),
+    (KC::Copyright, 0x1406), (KC::Registered, 0x1419), (KC::ExpOne, 0x141E), (KC::ExpTwo, 0x141F),
+    (KC::ExpThree, 0x1420), (KC::Pilcrow, 0x1433), (KC::Multi, 0x142E), (KC::Beta, 0x1416), (KC::Yen, 0x142D),
+
+    // Right alt & shift
+    (KC::Degre, 0x1633), (KC::Pound, 0x1621), (KC::Div, 0x162E),
+    (KC::Quarter, 0x1623), (KC::Half, 0x1624), (KC::ThreeQuarter, 0x1625),
+
+    (KC::LeaderKey, 0x7C58),
+];
+
+/// Chew keys without a VIA keycode, listed in this order in the customKeycodes of the json
+/// definitions (tools/via).
+#[rustfmt::skip]
+const CUSTOM_KEYCODES: [KC; 105] = [
+    KC::DeadCircumflex, KC::DeadDiaeris, KC::DeadGrave,
+    KC::ACircum, KC::AGrave, KC::ADiaer, KC::ECircum, KC::EGrave, KC::EDiaer,
+    KC::ICircum, KC::IGrave, KC::IDiaer, KC::OCircum, KC::OGrave, KC::ODiaer,
+    KC::UCircum, KC::UGrave, KC::UDiaer, KC::YCircum, KC::YGrave, KC::YDiaer,
+    KC::Qu, KC::Tion,
+
+    KC::MouseBtLock(&KC::MouseBtLeft), KC::MouseBtLock(&KC::MouseBtMiddle), KC::MouseBtLock(&KC::MouseBtRight),
+    KC::MouseSpeed1, KC::MouseSpeed2, KC::MouseSpeed3, KC::MouseSpeed4, KC::MouseGrid,
+    KC::Media(Media::Browser), KC::Midi,
+
+    KC::DynMacRecord, KC::DynMacGo, KC::DynMacGoFast, KC::DynMacAppend, KC::DynMacDelete, KC::DynMacList,
+    KC::MacroGit, KC::MacroMail, KC::MacroMailEnd, KC::MacroMailShort, KC::MacroHTTPS, KC::MacroDotfiles,
+    KC::MacroNixOS,
+
+    KC::ToggleBuzzer, KC::MacroTempoUp, KC::MacroTempoDown, KC::ToggleNkro,
+    KC::SettingUp(Setting::HoldTime), KC::SettingDown(Setting::HoldTime),
+    KC::SettingUp(Setting::ComboTime), KC::SettingDown(Setting::ComboTime),
+    KC::SettingUp(Setting::DeadKeyTempo), KC::SettingDown(Setting::DeadKeyTempo),
+    KC::SettingUp(Setting::MouseSpeed), KC::SettingDown(Setting::MouseSpeed),
+    KC::SettingUp(Setting::ScrollSpeed), KC::SettingDown(Setting::ScrollSpeed),
+    KC::SettingUp(Setting::UsbLoop), KC::SettingDown(Setting::UsbLoop),
+
+    KC::Pad(Pad::Bt1), KC::Pad(Pad::Bt2), KC::Pad(Pad::Bt3), KC::Pad(Pad::Bt4),
+    KC::Pad(Pad::Bt5), KC::Pad(Pad::Bt6), KC::Pad(Pad::Bt7), KC::Pad(Pad::Bt8),
+    KC::Pad(Pad::Bt9), KC::Pad(Pad::Bt10), KC::Pad(Pad::Bt11), KC::Pad(Pad::Bt12),
+    KC::Pad(Pad::Bt13), KC::Pad(Pad::Bt14), KC::Pad(Pad::Bt15), KC::Pad(Pad::Bt16),
+    KC::Pad(Pad::Up), KC::Pad(Pad::Down), KC::Pad(Pad::Left), KC::Pad(Pad::Right),
+
+    KC::Steno(Steno::SL), KC::Steno(Steno::TL), KC::Steno(Steno::KL), KC::Steno(Steno::PL),
+    KC::Steno(Steno::WL), KC::Steno(Steno::HL), KC::Steno(Steno::RL), KC::Steno(Steno::A),
+    KC::Steno(Steno::O), KC::Steno(Steno::Star), KC::Steno(Steno::E), KC::Steno(Steno::U),
+    KC::Steno(Steno::FR), KC::Steno(Steno::RR), KC::Steno(Steno::PR), KC::Steno(Steno::BR),
+    KC::Steno(Steno::LR), KC::Steno(Steno::GR), KC::Steno(Steno::TR), KC::Steno(Steno::SR),
+    KC::Steno(Steno::DR), KC::Steno(Steno::ZR), KC::Steno(Steno::Num),
+];
+
+/// Basic keycode (one byte) of a key, as held by a mod-tap.
+fn basic(key: KC) -> Option<u16> {
+    KEYCODES
+        .iter()
+        .find(|(k, code)| *k == key && *code <= 0xFF)
+        .map(|(_, code)| *code)
+}
+
+/// VIA keycode of a key, KC_NO if VIA can't show it (e.g. a HomeRow with a Chew key).
+pub fn to_via(key: KC) -> u16 {
+    match key {
+        KC::Layout(n) => MOMENTARY | n as u16,
+        KC::LaySet(n) => DEF_LAYER | n as u16,
+        KC::LayDead(n) => ONE_SHOT_LAYER | n as u16,
+        KC::ViaMacro(n) => MACRO | n as u16,
+        KC::HomeRow(modifier, key) => {
+            match (
+                MOD_TAP_MODS.iter().find(|(m, _)| *m == modifier),
+                basic(*key),
+            ) {
+                (Some((_, mods)), Some(code)) => MOD_TAP | mods << 8 | code,
+                _ => KC_NO,
+            }
+        }
+        _ => KEYCODES
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, code)| *code)
+            .or_else(|| {
+                CUSTOM_KEYCODES
+                    .iter()
+                    .position(|k| *k == key)
+                    .map(|index| CUSTOM + index as u16)
+            })
+            .unwrap_or(KC_NO),
+    }
+}
+
+/// Key of a VIA keycode, KC::None if the Chew can't do it.
+pub fn from_via(code: u16, nb_layouts: usize) -> KC {
+    let layer = (code & 0x1F) as usize;
+    let layer_exists = layer < nb_layouts;
+
+    match code {
+        c if c & 0xFFE0 == MOMENTARY && layer_exists => KC::Layout(layer),
+        c if c & 0xFFE0 == DEF_LAYER && layer_exists => KC::LaySet(layer),
+        c if c & 0xFFE0 == ONE_SHOT_LAYER && layer_exists => KC::LayDead(layer),
+        c if (MACRO..MACRO + VIA_MACRO_COUNT as u16).contains(&c) => {
+            KC::ViaMacro((c - MACRO) as u8)
+        }
+        c if c & 0xE000 == MOD_TAP => {
+            let modifier = MOD_TAP_MODS
+                .iter()
+                .find(|(_, mods)| *mods == (c >> 8) & 0x1F)
+                .map(|(m, _)| *m);
+            let key = KEYCODES
+                .iter()
+                .find(|(_, k)| *k == c & 0xFF)
+                .map(|(k, _)| KC::from_u16_static(k.to_u16()));
+
+            match (modifier, key) {
+                (Some(modifier), Some(key)) if *key != KC::None => KC::HomeRow(modifier, key),
+                _ => KC::None,
+            }
+        }
+        c if c >= CUSTOM => CUSTOM_KEYCODES
+            .get((c - CUSTOM) as usize)
+            .copied()
+            .unwrap_or(KC::None),
+        c => KEYCODES
+            .iter()
+            .find(|(_, k)| *k == c)
+            .map(|(k, _)| *k)
+            .unwrap_or(KC::None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{options::FLASH_VIA_SIZE, software::storage::RamFlash};
+
+    fn via() -> Via<RamFlash> {
+        Via::new(RamFlash::new(FLASH_VIA_SIZE))
+    }
+
+    fn packet(bytes: &[u8]) -> [u8; VIA_REPORT_LENGTH] {
+        let mut packet = [0; VIA_REPORT_LENGTH];
+        packet[..bytes.len()].copy_from_slice(bytes);
+        packet
+    }
+
+    /// Get/set buffer packet: command, offset, size & data.
+    fn buffer(command: u8, offset: u16, size: u8, data: &[u8]) -> [u8; VIA_REPORT_LENGTH] {
+        let [high, low] = offset.to_be_bytes();
+        let mut packet = packet(&[command, high, low, size]);
+        packet[4..4 + data.len()].copy_from_slice(data);
+        packet
+    }
+
+    #[test]
+    fn get_and_set_keycode() {
+        let mut via = via();
+        let mut layouts = [[KC::None; NB_KEYS]; 2];
+
+        // Layout 1, row 3, col 2 is the key 28 (thumbs)
+        let mut set = packet(&[SET_KEYCODE, 1, 3, 2, 0x00, 0x04]);
+        assert_eq!(
+            via.command(&mut set, &mut layouts, 0),
+            ViaEvent::KeymapChanged
+        );
+        assert_eq!(layouts[1][28], KC::A);
+
+        let mut get = packet(&[GET_KEYCODE, 1, 3, 2]);
+        assert_eq!(via.command(&mut get, &mut layouts, 0), ViaEvent::None);
+        assert_eq!(get[4..6], [0x00, 0x04]);
+
+        // Mod-tap & layer keys
+        let mut set = packet(&[SET_KEYCODE, 0, 0, 0, 0x22, 0x16]);
+        via.command(&mut set, &mut layouts, 0);
+        assert_eq!(layouts[0][0], KC::HomeRow(&KC::Sft, &KC::S));
+        let mut set = packet(&[SET_KEYCODE, 0, 0, 1, 0x52, 0x21]);
+        via.command(&mut set, &mut layouts, 0);
+        assert_eq!(layouts[0][1], KC::Layout(1));
+
+        // No key in this place of the matrix, or no such layout
+        let before = layouts;
+        for (layout, row, col) in [(0, 2, 4), (0, 3, 0), (0, 4, 0), (0, 0, 10), (2, 0, 0)] {
+            let mut set = packet(&[SET_KEYCODE, layout, row, col, 0x00, 0x04]);
+            assert_eq!(via.command(&mut set, &mut layouts, 0), ViaEvent::None);
+
+            let mut get = packet(&[GET_KEYCODE, layout, row, col]);
+            via.command(&mut get, &mut layouts, 0);
+            assert_eq!(get[4..6], [0, 0]);
+        }
+        assert_eq!(layouts, before);
+    }
+
+    #[test]
+    fn keymap_buffer() {
+        let mut via = via();
+        let mut layouts = [[KC::None; NB_KEYS]; 2];
+        layouts[0][0] = KC::A;
+        layouts[0][1] = KC::B;
+        layouts[1][33] = KC::Z;
+
+        let mut get = buffer(KEYMAP_GET_BUFFER, 0, 4, &[]);
+        via.command(&mut get, &mut layouts, 0);
+        assert_eq!(get[4..8], [0x00, 0x04, 0x00, 0x05]);
+
+        // Layout 1 row 3 col 7 is the last key, the rest of the packet is past the end
+        let mut get = buffer(KEYMAP_GET_BUFFER, 77 * 2, 28, &[0xAA; 28]);
+        via.command(&mut get, &mut layouts, 0);
+        assert_eq!(get[4..6], [0x00, 0x1D]);
+        assert!(get[6..].iter().all(|b| *b == 0));
+
+        for offset in [160, 0xFFFF] {
+            let mut get = buffer(KEYMAP_GET_BUFFER, offset, 0xFF, &[0xAA; 28]);
+            via.command(&mut get, &mut layouts, 0);
+            assert!(get[4..].iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn keymap_set_buffer() {
+        let mut via = via();
+        let mut layouts = [[KC::None; NB_KEYS]; 2];
+
+        // Row 2 of layout 0 holds two empty places (cols 4 & 5)
+        let codes: std::vec::Vec<u8> = (0x04..0x0E).flat_map(|code| [0x00, code]).collect();
+        let mut set = buffer(KEYMAP_SET_BUFFER, 20 * 2, 20, &codes);
+        assert_eq!(
+            via.command(&mut set, &mut layouts, 0),
+            ViaEvent::KeymapChanged
+        );
+        assert_eq!(
+            layouts[0][20..28],
+            [KC::A, KC::B, KC::C, KC::D, KC::G, KC::H, KC::I, KC::J]
+        );
+
+        // Past the end nothing is written
+        let before = layouts;
+        let mut set = buffer(KEYMAP_SET_BUFFER, 78 * 2, 0xFF, &[0x04; 28]);
+        via.command(&mut set, &mut layouts, 0);
+        let mut set = buffer(KEYMAP_SET_BUFFER, 0xFFFF, 28, &[0x04; 28]);
+        via.command(&mut set, &mut layouts, 0);
+        assert_eq!(layouts, before);
+
+        // A layer which doesn't exist is no key
+        let mut set = buffer(KEYMAP_SET_BUFFER, 0, 2, &[0x52, 0x22]);
+        via.command(&mut set, &mut layouts, 0);
+        assert_eq!(layouts[0][0], KC::None);
+    }
+
+    #[test]
+    fn macro_buffer() {
+        let mut via = via();
+        let mut layouts = [[KC::None; NB_KEYS]; 1];
+
+        let mut set = buffer(MACRO_SET_BUFFER, 10, 5, b"hello");
+        assert_eq!(via.command(&mut set, &mut layouts, 100), ViaEvent::None);
+
+        let mut get = buffer(MACRO_GET_BUFFER, 8, 9, &[]);
+        via.command(&mut get, &mut layouts, 100);
+        assert_eq!(&get[4..13], b"\0\0hello\0\0");
+
+        // Only the bytes before the end are written & read
+        let end = VIA_MACRO_BUFFER as u16 - 2;
+        let mut set = buffer(MACRO_SET_BUFFER, end, 4, b"abcd");
+        via.command(&mut set, &mut layouts, 100);
+        let mut get = buffer(MACRO_GET_BUFFER, end, 28, &[0xAA; 28]);
+        via.command(&mut get, &mut layouts, 100);
+        assert_eq!(&get[4..6], b"ab");
+        assert!(get[6..].iter().all(|b| *b == 0));
+
+        let mut get = buffer(MACRO_GET_BUFFER, 0xFFFF, 0xFF, &[0xAA; 28]);
+        via.command(&mut get, &mut layouts, 100);
+        assert!(get[4..].iter().all(|b| *b == 0));
+
+        // Saved once the keyboard is idle
+        via.save(true, 100 + SETTINGS_SAVE_DELAY);
+        let via = Via::new(via.store.into_flash());
+        assert_eq!(&via.macros[10..15], b"hello");
+        assert_eq!(&via.macros[VIA_MACRO_BUFFER - 2..], b"ab");
+    }
+
+    #[test]
+    fn unhandled() {
+        let mut via = via();
+        let mut layouts = [[KC::None; NB_KEYS]; 1];
+
+        for bytes in [
+            &[0x42, 1, 2, 3][..],
+            &[GET_KEYBOARD_VALUE, 0x42],
+            &[SET_KEYBOARD_VALUE, 0x42],
+        ] {
+            let mut packet = packet(bytes);
+            assert_eq!(via.command(&mut packet, &mut layouts, 0), ViaEvent::None);
+            assert_eq!(packet[0], UNHANDLED);
+            assert_eq!(packet[1..bytes.len()], bytes[1..]);
+        }
+    }
+
+    #[test]
+    fn bootloader_needs_its_feature() {
+        let mut via = via();
+        let mut layouts = [[KC::None; NB_KEYS]; 1];
+
+        let mut packet = packet(&[BOOTLOADER_JUMP]);
+        match VIA_BOOTLOADER {
+            true => assert_eq!(
+                via.command(&mut packet, &mut layouts, 0),
+                ViaEvent::Bootloader
+            ),
+            false => {
+                assert_eq!(via.command(&mut packet, &mut layouts, 0), ViaEvent::None);
+                assert_eq!(packet[0], UNHANDLED);
+            }
+        }
+    }
+
+    #[test]
+    fn keycodes_round_trip() {
+        let keys = KEYCODES
+            .iter()
+            .copied()
+            .chain(CUSTOM_KEYCODES.iter().map(|k| (*k, to_via(*k))));
+        for (key, code) in keys {
+            assert_ne!(code, KC_NO, "{:?}", key);
+            assert_eq!(to_via(key), code, "{:?}", key);
+            assert_eq!(from_via(code, 1), key, "{:#06X}", code);
+        }
+
+        // Keys with data
+        let nb_layouts = 3;
+        for n in 0..nb_layouts {
+            for key in [KC::Layout(n), KC::LaySet(n), KC::LayDead(n)] {
+                assert_eq!(from_via(to_via(key), nb_layouts), key);
+            }
+        }
+        for n in 0..VIA_MACRO_COUNT as u8 {
+            assert_eq!(from_via(to_via(KC::ViaMacro(n)), 1), KC::ViaMacro(n));
+        }
+        let home_row = KC::HomeRow(&KC::Altgr, &KC::Esc);
+        assert_eq!(to_via(home_row), 0x3429);
+        assert_eq!(from_via(0x3429, 1), home_row);
+    }
+
+    #[test]
+    fn keycodes_the_chew_cant_do() {
+        assert_eq!(to_via(KC::HomeRow(&KC::Ctl, &KC::Qu)), KC_NO);
+        assert_eq!(to_via(KC::Done), KC_NO);
+
+        // Missing layout & macro, unknown mod or key
+        assert_eq!(from_via(to_via(KC::Layout(2)), 2), KC::None);
+        assert_eq!(from_via(MACRO | VIA_MACRO_COUNT as u16, 1), KC::None);
+        assert_eq!(from_via(MOD_TAP | 0x10 << 8 | 0x04, 1), KC::None);
+        assert_eq!(from_via(MOD_TAP | 0x01 << 8 | 0xFF, 1), KC::None);
+        assert_eq!(from_via(CUSTOM + CUSTOM_KEYCODES.len() as u16, 1), KC::None);
+        assert_eq!(from_via(0x0047, 1), KC::None);
+    }
+}
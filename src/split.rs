@@ -1,5 +1,5 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 mod hardware;
 mod layouts;
@@ -14,11 +14,24 @@ use hardware::{
     led::{Led, LedColor},
     uart::{Uart, UartError, HR_KEYS, HR_STATUS},
 };
-use options::{SERIAL_ON, TIMER_SPLIT_LOOP, TIMER_UART_LOOP, TIMER_USB_LOOP};
+use options::{
+    BOOT_6KRO_KEYS, MIDI_BUZZER_ECHO, SERIAL_ON, TIMER_SERIAL_LOOP, TIMER_SPLIT_LOOP,
+    TIMER_UART_LOOP, TIMER_USB_LOOP,
+};
 use software::{
     chew::Chew,
+    hid_consumer::{Consumer, ConsumerConfig, ConsumerReport},
+    hid_gamepad::{Gamepad, GamepadConfig, GamepadReport},
+    hid_keyboard::{ChewKeyboard, ChewKeyboardConfig, KEYBOARD_6KRO},
+    hid_mouse::{SmoothMouse, SmoothMouseConfig},
+    hid_system::{SystemControl, SystemControlConfig},
+    hid_via::{ViaRaw, ViaRawConfig},
     keys::{BuffCase, Buffer},
-    serial_usb::{serial_write, serial_write_time, serial_write_values},
+    midi_usb::MidiClass,
+    serial_usb::{
+        serial_write, serial_write_raw, serial_write_time, serial_write_values, SerialOutput,
+    },
+    shell::{echo, Command, Shell},
     status::{Status, Statuses},
 };
 use usbd_serial::SerialPort;
@@ -30,11 +43,12 @@ use waveshare_rp2040_zero::{
 
 use bsp::hal::{
     clocks::{init_clocks_and_plls, Clock},
-    entry, pac,
+    pac,
     pio::PIOExt,
+    rom_data,
     timer::Timer,
     usb,
-    watchdog::Watchdog,
+    watchdog::{ScratchRegister, Watchdog},
     Sio,
 };
 use cortex_m::prelude::*;
@@ -47,8 +61,9 @@ use ws2812_pio::Ws2812;
 #[allow(clippy::wildcard_imports)]
 use usb_device::class_prelude::*;
 use usb_device::prelude::*;
-use usbd_human_interface_device::device::keyboard::NKROBootKeyboard;
-use usbd_human_interface_device::device::mouse::{WheelMouse, WheelMouseReport};
+use usbd_human_interface_device::device::mouse::{
+    AbsoluteWheelMouse, AbsoluteWheelMouseConfig, WheelMouseReport,
+};
 use usbd_human_interface_device::prelude::*;
 
 // Status --
@@ -60,10 +75,26 @@ const STATUS_CAPLOCK: u8 = 4;
 const STATUS_DYNMAC_REC: u8 = 5;
 const STATUS_DYNMAC_GO_WAIT: u8 = 6;
 const STATUS_DYNMAC_REC_WAIT: u8 = 7;
+const STATUS_DYNMAC_APP_WAIT: u8 = 9;
+const STATUS_DYNMAC_DEL_WAIT: u8 = 10;
+const STATUS_DYNMAC_PLAY: u8 = 11;
+
+const STATUS_MOUSE_LOCK: u8 = 12;
+const STATUS_MOUSE_GRID: u8 = 13;
+
+const STATUS_LAYOUT_PAD: u8 = 14;
+const STATUS_LAYOUT_MIDI: u8 = 15;
+const STATUS_LAYOUT_STENO: u8 = 16;
+
+const STATUS_NUM_LOCK: u8 = 17;
+const STATUS_SCROLL_LOCK: u8 = 18;
+
+const STATUS_SETTING: u8 = 19;
+const STATUS_SETTING_LIMIT: u8 = 20;
 
 const STATUS_TOGGLE_BUZZER: u8 = 8;
 
-#[entry]
+#[cfg_attr(not(test), bsp::hal::entry)]
 fn main() -> ! {
     let mut pac = pac::Peripherals::take().unwrap();
 
@@ -94,32 +125,6 @@ fn main() -> ! {
 
     let (mut pio, sm0, sm1, _, _) = pac.PIO0.split(&mut pac.RESETS);
 
-    // USB --
-    let usb_bus = UsbBusAllocator::new(usb::UsbBus::new(
-        pac.USBCTRL_REGS,
-        pac.USBCTRL_DPRAM,
-        clocks.usb_clock,
-        true,
-        &mut pac.RESETS,
-    ));
-
-    let mut serial = SerialPort::new(&usb_bus);
-    let mut rusty_chew = UsbHidClassBuilder::new()
-        .add_device(
-            usbd_human_interface_device::device::keyboard::NKROBootKeyboardConfig::default(),
-        )
-        .add_device(usbd_human_interface_device::device::mouse::WheelMouseConfig::default())
-        .build(&usb_bus);
-
-    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x1337, 0x1985))
-        .strings(&[StringDescriptors::default()
-            .manufacturer("florent@linguenheld.fr")
-            .product("Rusty Chew Split")
-            .serial_number("00")])
-        .unwrap()
-        .supports_remote_wakeup(true)
-        .build();
-
     // Side detection (releases the pin for the buzzer)--
     let mut pin10 = pins.gp10.into_pull_down_input();
     let is_left = pin10.is_high().unwrap();
@@ -188,6 +193,45 @@ fn main() -> ! {
         gpios.add(pins.gp7.into_pull_up_input().into_dyn_pin(), 33);
     }
 
+    // Keyboard protocol --
+    // 6KRO boot keyboard if a BOOT_6KRO_KEYS is held or after a KC::ToggleNkro restart
+    let boot_keyboard = watchdog.read_scratch(ScratchRegister::Scratch0) == KEYBOARD_6KRO
+        || gpios
+            .get_active_indexes()
+            .iter()
+            .any(|index| BOOT_6KRO_KEYS.contains(&(*index as usize)));
+
+    // USB --
+    let usb_bus = UsbBusAllocator::new(usb::UsbBus::new(
+        pac.USBCTRL_REGS,
+        pac.USBCTRL_DPRAM,
+        clocks.usb_clock,
+        true,
+        &mut pac.RESETS,
+    ));
+
+    let mut serial_port = SerialPort::new(&usb_bus);
+    let mut rusty_chew = UsbHidClassBuilder::new()
+        .add_device(ChewKeyboardConfig::new(boot_keyboard))
+        .add_device(SmoothMouseConfig::default())
+        .add_device(AbsoluteWheelMouseConfig::default())
+        .add_device(ConsumerConfig::default())
+        .add_device(SystemControlConfig::default())
+        .add_device(GamepadConfig::default())
+        .add_device(ViaRawConfig::default())
+        .build(&usb_bus);
+
+    let mut midi = MidiClass::new(&usb_bus);
+
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x1337, 0x1985))
+        .strings(&[StringDescriptors::default()
+            .manufacturer("florent@linguenheld.fr")
+            .product("Rusty Chew Split")
+            .serial_number("00")])
+        .unwrap()
+        .supports_remote_wakeup(true)
+        .build();
+
     // Buzzer (Check doc to see the assignment pin/pwm/channel)
     let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
 
@@ -229,15 +273,23 @@ fn main() -> ! {
     let mut uart_count_down = timer.count_down();
     uart_count_down.start(TIMER_UART_LOOP.millis());
 
+    let mut usb_loop = TIMER_USB_LOOP;
     let mut usb_count_down = timer.count_down();
-    usb_count_down.start(TIMER_USB_LOOP.millis());
+    usb_count_down.start(usb_loop.millis());
 
     let mut split_count_down = timer.count_down();
     split_count_down.start(TIMER_SPLIT_LOOP.millis());
 
+    let mut serial_count_down = timer.count_down();
+    serial_count_down.start(TIMER_SERIAL_LOOP.millis());
+
     // --
     let mut ticks: u32 = 0;
+    let mut serial = SerialOutput::new();
+    let mut steno = SerialOutput::new(); // Chords for Plover, the port is theirs meanwhile
+    let mut shell = Shell::new();
     let mut chew = Chew::new(ticks);
+    chew.set_boot_keyboard(boot_keyboard);
     let mut statuses = Statuses::new();
 
     let mut key_buffer = Buffer::new();
@@ -247,11 +299,16 @@ fn main() -> ! {
     let mut mouse_report = WheelMouseReport::default();
     let mut last_mouse_buttons = 0;
 
-    loop {
-        if SERIAL_ON && !usb_dev.poll(&mut [&mut serial]) {
-            continue;
-        }
+    let mut consumer_report;
+    let mut last_consumer_report = ConsumerReport::default();
+
+    let mut system_report;
+    let mut last_system_report = 0;
+
+    let mut pad_report;
+    let mut last_pad_report = GamepadReport::default();
 
+    loop {
         if uart_count_down.wait().is_ok() {
             // serial_write_time(&mut serial, "Uart loop -- ", ticks, " --\r\n");
 
@@ -261,7 +318,9 @@ fn main() -> ! {
                 match uart.receive() {
                     Ok(mail) => {
                         if mail.header == HR_KEYS {
-                            serial_write_values(&mut serial, "Indexes: ", &mail.values, "\r\n");
+                            if !statuses.layout_steno.is_on() {
+                                serial_write_values(&mut serial, "Indexes: ", &mail.values, "\r\n");
+                            }
 
                             // Get active indexes & combine them with the other side
                             chew.update_matrix(
@@ -274,9 +333,49 @@ fn main() -> ! {
                                 ticks,
                             );
 
-                            (key_buffer, mouse_report, statuses) =
+                            (key_buffer, mouse_report, consumer_report, statuses) =
                                 chew.run(key_buffer, mouse_report, statuses, ticks);
 
+                            // USB loop changed by the settings --
+                            if chew.settings().usb_loop != usb_loop {
+                                usb_loop = chew.settings().usb_loop;
+                                usb_count_down.start(usb_loop.millis());
+                            }
+
+                            // Restart with the other keyboard protocol --
+                            if chew.keyboard_switch() {
+                                let protocol = if boot_keyboard { 0 } else { KEYBOARD_6KRO };
+                                watchdog.write_scratch(ScratchRegister::Scratch0, protocol);
+                                watchdog.start(1_000.micros());
+                                loop {
+                                    cortex_m::asm::nop();
+                                }
+                            }
+
+                            if let Some(line) = chew.dynmac_list_line() {
+                                serial_write_raw(&mut serial, line.as_bytes());
+                            }
+                            if let Some(packet) = chew.steno_packet() {
+                                serial_write_raw(&mut steno, &packet);
+                            }
+
+                            // Suspended host --
+                            // Nothing is sent, a freshly pressed key wakes it up (if the host allows it)
+                            // but it isn't printed
+                            system_report = chew.system_report();
+                            pad_report = chew.pad_report();
+                            if usb_dev.state() == UsbDeviceState::Suspend {
+                                if chew.wakeup() && usb_dev.remote_wakeup_enabled() {
+                                    usb_dev.bus().remote_wakeup();
+                                }
+
+                                key_buffer.keys.clear();
+                                mouse_report = WheelMouseReport::default();
+                                consumer_report = last_consumer_report;
+                                system_report = last_system_report;
+                                pad_report = last_pad_report;
+                            }
+
                             // Mouse report directly done here --------------------------
                             // Keyboard has its own timer to allow combinations
                             if mouse_report.buttons != last_mouse_buttons
@@ -285,7 +384,7 @@ fn main() -> ! {
                                 || mouse_report.vertical_wheel != 0
                                 || mouse_report.horizontal_wheel != 0
                             {
-                                let mouse = rusty_chew.device::<WheelMouse<'_, _>, _>();
+                                let mouse = rusty_chew.device::<SmoothMouse<'_, _>, _>();
                                 match mouse.write_report(&mouse_report) {
                                     Err(UsbHidError::WouldBlock) => {
                                         led.on(LedColor::Red);
@@ -300,6 +399,76 @@ fn main() -> ! {
                                 };
                             }
 
+                            // Consumer report only when it changes --
+                            if consumer_report != last_consumer_report {
+                                let consumer = rusty_chew.device::<Consumer<'_, _>, _>();
+                                match consumer.write_report(&consumer_report) {
+                                    Err(UsbHidError::WouldBlock) => {}
+                                    Ok(_) => last_consumer_report = consumer_report,
+                                    Err(e) => {
+                                        core::panic!("Failed to write consumer report: {:?}", e)
+                                    }
+                                };
+                            }
+
+                            // System control --
+                            if system_report != last_system_report {
+                                let system = rusty_chew.device::<SystemControl<'_, _>, _>();
+                                match system.write_report(system_report) {
+                                    Err(UsbHidError::WouldBlock) => {}
+                                    Ok(_) => last_system_report = system_report,
+                                    Err(e) => {
+                                        core::panic!(
+                                            "Failed to write system control report: {:?}",
+                                            e
+                                        )
+                                    }
+                                };
+                            }
+
+                            // Gamepad --
+                            if pad_report != last_pad_report {
+                                let pad = rusty_chew.device::<Gamepad<'_, _>, _>();
+                                match pad.write_report(&pad_report) {
+                                    Err(UsbHidError::WouldBlock) => {}
+                                    Ok(_) => last_pad_report = pad_report,
+                                    Err(e) => {
+                                        core::panic!("Failed to write gamepad report: {:?}", e)
+                                    }
+                                };
+                            }
+
+                            // MIDI, lost if the host doesn't read it --
+                            let midi_events = chew.midi_events();
+                            if !midi_events.is_empty() {
+                                if MIDI_BUZZER_ECHO {
+                                    for event in midi_events.iter().filter(|e| e.on) {
+                                        sing(
+                                            &mut buz_left,
+                                            &mut buz_right,
+                                            Song::Midi(event.note),
+                                            false,
+                                        );
+                                    }
+                                }
+                                midi.send(&midi_events).ok();
+                            }
+
+                            // Absolute pointer (grid) --
+                            if let Some(report) = chew.absolute_report() {
+                                let pointer = rusty_chew.device::<AbsoluteWheelMouse<'_, _>, _>();
+                                match pointer.write_report(&report) {
+                                    Err(UsbHidError::WouldBlock) => {}
+                                    Ok(_) => chew.absolute_report_sent(),
+                                    Err(e) => {
+                                        core::panic!(
+                                            "Failed to write absolute pointer report: {:?}",
+                                            e
+                                        )
+                                    }
+                                };
+                            }
+
                             // Update LED & share its state to the slave --
                             let led_status = if statuses.layout_fr == Status::On {
                                 led.on(LedColor::Aqua);
@@ -307,6 +476,15 @@ fn main() -> ! {
                             } else if statuses.layout_fn == Status::On {
                                 led.on(LedColor::Fushia);
                                 STATUS_LAYOUT_FN
+                            } else if statuses.layout_pad == Status::On {
+                                led.on(LedColor::Green);
+                                STATUS_LAYOUT_PAD
+                            } else if statuses.layout_midi == Status::On {
+                                led.on(LedColor::Gray);
+                                STATUS_LAYOUT_MIDI
+                            } else if statuses.layout_steno == Status::On {
+                                led.on(LedColor::Yellow);
+                                STATUS_LAYOUT_STENO
                             } else if statuses.leader_key == Status::On {
                                 led.on(LedColor::Blue);
                                 STATUS_LEADER_KEY
@@ -322,6 +500,27 @@ fn main() -> ! {
                             } else if statuses.dynmac_rec_waitkey == Status::On {
                                 led.blink(LedColor::Purple, 800, ticks);
                                 STATUS_DYNMAC_REC_WAIT
+                            } else if statuses.dynmac_append_waitkey == Status::On {
+                                led.blink(LedColor::Maroon, 800, ticks);
+                                STATUS_DYNMAC_APP_WAIT
+                            } else if statuses.dynmac_delete_waitkey == Status::On {
+                                led.blink(LedColor::Yellow, 800, ticks);
+                                STATUS_DYNMAC_DEL_WAIT
+                            } else if statuses.dynmac_playing == Status::On {
+                                led.blink(LedColor::Lime, 400, ticks);
+                                STATUS_DYNMAC_PLAY
+                            } else if statuses.mouse_grid == Status::On {
+                                led.on(LedColor::Navy);
+                                STATUS_MOUSE_GRID
+                            } else if statuses.mouse_lock == Status::On {
+                                led.on(LedColor::Teal);
+                                STATUS_MOUSE_LOCK
+                            } else if statuses.num_lock == Status::On {
+                                led.on(LedColor::Lime);
+                                STATUS_NUM_LOCK
+                            } else if statuses.scroll_lock == Status::On {
+                                led.on(LedColor::Purple);
+                                STATUS_SCROLL_LOCK
                             } else {
                                 led.off();
                                 0
@@ -340,6 +539,24 @@ fn main() -> ! {
                             } else if statuses.layout_fn == Status::SwitchOff {
                                 sing(&mut buz_left, &mut buz_right, Song::NotifC, true);
                                 STATUS_LAYOUT_FN + 128
+                            } else if statuses.layout_pad == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoFA, false);
+                                STATUS_LAYOUT_PAD
+                            } else if statuses.layout_pad == Status::SwitchOff {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoFA, true);
+                                STATUS_LAYOUT_PAD + 128
+                            } else if statuses.layout_midi == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoGB, false);
+                                STATUS_LAYOUT_MIDI
+                            } else if statuses.layout_midi == Status::SwitchOff {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoGB, true);
+                                STATUS_LAYOUT_MIDI + 128
+                            } else if statuses.layout_steno == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoAC, false);
+                                STATUS_LAYOUT_STENO
+                            } else if statuses.layout_steno == Status::SwitchOff {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoAC, true);
+                                STATUS_LAYOUT_STENO + 128
                             } else if statuses.leader_key == Status::SwitchOn {
                                 sing(&mut buz_left, &mut buz_right, Song::NotifA, false);
                                 STATUS_LEADER_KEY
@@ -358,6 +575,21 @@ fn main() -> ! {
                             } else if statuses.dynmac_rec_waitkey == Status::SwitchOn {
                                 sing(&mut buz_left, &mut buz_right, Song::AlertE, false);
                                 STATUS_DYNMAC_REC_WAIT
+                            } else if statuses.dynmac_append_waitkey == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::AlertF, false);
+                                STATUS_DYNMAC_APP_WAIT
+                            } else if statuses.dynmac_delete_waitkey == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::ErrorE, false);
+                                STATUS_DYNMAC_DEL_WAIT
+                            } else if statuses.mouse_grid == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::NotifG, false);
+                                STATUS_MOUSE_GRID
+                            } else if statuses.mouse_lock == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoCE, false);
+                                STATUS_MOUSE_LOCK
+                            } else if statuses.mouse_lock == Status::SwitchOff {
+                                sing(&mut buz_left, &mut buz_right, Song::TwoCE, true);
+                                STATUS_MOUSE_LOCK + 128
                             } else if statuses.buzzer_activation == Status::SwitchOn {
                                 active_buzzer(&mut buz_left, &mut buz_right, true);
                                 sing(&mut buz_left, &mut buz_right, Song::StartupA, false);
@@ -366,6 +598,12 @@ fn main() -> ! {
                                 sing(&mut buz_left, &mut buz_right, Song::StartupA, true);
                                 active_buzzer(&mut buz_left, &mut buz_right, false);
                                 STATUS_TOGGLE_BUZZER + 128
+                            } else if statuses.setting == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::SuccessA, false);
+                                STATUS_SETTING
+                            } else if statuses.setting_limit == Status::SwitchOn {
+                                sing(&mut buz_left, &mut buz_right, Song::ErrorF, false);
+                                STATUS_SETTING_LIMIT
                             } else {
                                 0
                             };
@@ -403,12 +641,22 @@ fn main() -> ! {
                             match mail.values[0] {
                                 STATUS_LAYOUT_FR => led.on(LedColor::Aqua),
                                 STATUS_LAYOUT_FN => led.on(LedColor::Fushia),
+                                STATUS_LAYOUT_PAD => led.on(LedColor::Green),
+                                STATUS_LAYOUT_MIDI => led.on(LedColor::Gray),
+                                STATUS_LAYOUT_STENO => led.on(LedColor::Yellow),
                                 STATUS_LEADER_KEY => led.on(LedColor::Blue),
                                 STATUS_CAPLOCK => led.on(LedColor::Orange),
 
                                 STATUS_DYNMAC_GO_WAIT => led.blink(LedColor::Olive, 800, ticks),
                                 STATUS_DYNMAC_REC => led.blink(LedColor::Red, 600, ticks),
                                 STATUS_DYNMAC_REC_WAIT => led.blink(LedColor::Purple, 800, ticks),
+                                STATUS_DYNMAC_APP_WAIT => led.blink(LedColor::Maroon, 800, ticks),
+                                STATUS_DYNMAC_DEL_WAIT => led.blink(LedColor::Yellow, 800, ticks),
+                                STATUS_DYNMAC_PLAY => led.blink(LedColor::Lime, 400, ticks),
+                                STATUS_MOUSE_LOCK => led.on(LedColor::Teal),
+                                STATUS_MOUSE_GRID => led.on(LedColor::Navy),
+                                STATUS_NUM_LOCK => led.on(LedColor::Lime),
+                                STATUS_SCROLL_LOCK => led.on(LedColor::Purple),
 
                                 _ => led.off(),
                             }
@@ -422,6 +670,18 @@ fn main() -> ! {
                                     sing(&mut buz_left, &mut buz_right, Song::NotifC, false);
                                 } else if mail.values[1] == STATUS_LAYOUT_FN + 128 {
                                     sing(&mut buz_left, &mut buz_right, Song::NotifC, true);
+                                } else if mail.values[1] == STATUS_LAYOUT_PAD {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoFA, false);
+                                } else if mail.values[1] == STATUS_LAYOUT_PAD + 128 {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoFA, true);
+                                } else if mail.values[1] == STATUS_LAYOUT_MIDI {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoGB, false);
+                                } else if mail.values[1] == STATUS_LAYOUT_MIDI + 128 {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoGB, true);
+                                } else if mail.values[1] == STATUS_LAYOUT_STENO {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoAC, false);
+                                } else if mail.values[1] == STATUS_LAYOUT_STENO + 128 {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoAC, true);
                                 } else if mail.values[1] == STATUS_LEADER_KEY {
                                     sing(&mut buz_left, &mut buz_right, Song::NotifA, false);
                                 } else if mail.values[1] == STATUS_CAPLOCK {
@@ -434,12 +694,26 @@ fn main() -> ! {
                                     sing(&mut buz_left, &mut buz_right, Song::AlertD, false);
                                 } else if mail.values[1] == STATUS_DYNMAC_REC_WAIT {
                                     sing(&mut buz_left, &mut buz_right, Song::AlertE, false);
+                                } else if mail.values[1] == STATUS_DYNMAC_APP_WAIT {
+                                    sing(&mut buz_left, &mut buz_right, Song::AlertF, false);
+                                } else if mail.values[1] == STATUS_DYNMAC_DEL_WAIT {
+                                    sing(&mut buz_left, &mut buz_right, Song::ErrorE, false);
+                                } else if mail.values[1] == STATUS_MOUSE_GRID {
+                                    sing(&mut buz_left, &mut buz_right, Song::NotifG, false);
+                                } else if mail.values[1] == STATUS_MOUSE_LOCK {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoCE, false);
+                                } else if mail.values[1] == STATUS_MOUSE_LOCK + 128 {
+                                    sing(&mut buz_left, &mut buz_right, Song::TwoCE, true);
                                 } else if mail.values[1] == STATUS_TOGGLE_BUZZER {
                                     active_buzzer(&mut buz_left, &mut buz_right, true);
                                     sing(&mut buz_left, &mut buz_right, Song::StartupA, false);
                                 } else if mail.values[1] == STATUS_TOGGLE_BUZZER + 128 {
                                     sing(&mut buz_left, &mut buz_right, Song::StartupA, true);
                                     active_buzzer(&mut buz_left, &mut buz_right, false);
+                                } else if mail.values[1] == STATUS_SETTING {
+                                    sing(&mut buz_left, &mut buz_right, Song::SuccessA, false);
+                                } else if mail.values[1] == STATUS_SETTING_LIMIT {
+                                    sing(&mut buz_left, &mut buz_right, Song::ErrorF, false);
                                 }
                             }
                         }
@@ -464,26 +738,26 @@ fn main() -> ! {
             }
         }
 
+        // Serial, only when no key is waiting (the text waits while the steno layout is on) --
+        let steno_on = statuses.layout_steno.is_on();
+        if is_master {
+            steno.flush(&mut serial_port);
+        }
+        if !steno_on && serial_count_down.wait().is_ok() && key_buffer.keys.is_empty() {
+            serial.flush(&mut serial_port);
+        }
+
         // ------------------------------------------------------------------------------
         // -------------------------------------------------------------------- MASTER --
         if is_master {
             // USB --
-            if !SERIAL_ON && usb_count_down.wait().is_ok() && key_buffer_tempo <= ticks {
+            if usb_count_down.wait().is_ok() && key_buffer_tempo <= ticks {
                 if let Some(popped_key) = key_buffer.keys.pop_front() {
                     if popped_key != last_printed_key {
-                        let keyboard = rusty_chew.device::<NKROBootKeyboard<'_, _>, _>();
+                        let keyboard = rusty_chew.device::<ChewKeyboard<'_, _>, _>();
                         match keyboard.write_report(popped_key.key_code.clone()) {
                             Err(UsbHidError::WouldBlock) => {
-                                // Wake up --
-                                if usb_dev.state() == UsbDeviceState::Suspend {
-                                    usb_dev.bus().remote_wakeup();
-                                    key_buffer.keys.clear();
-                                } else {
-                                    key_buffer.keys.push_front(popped_key).ok();
-                                }
-                            }
-                            Err(UsbHidError::Duplicate) => {
-                                led.on(LedColor::Blue);
+                                key_buffer.keys.push_front(popped_key).ok();
                             }
                             Ok(_) => {
                                 key_buffer_tempo = ticks.wrapping_add(popped_key.tempo);
@@ -507,16 +781,55 @@ fn main() -> ! {
                 };
             }
 
-            if !SERIAL_ON && usb_dev.poll(&mut [&mut rusty_chew]) {
-                match rusty_chew
-                    .device::<NKROBootKeyboard<'_, _>, _>()
-                    .read_report()
-                {
+            if usb_dev.poll(&mut [&mut rusty_chew, &mut midi, &mut serial_port]) {
+                match rusty_chew.device::<ChewKeyboard<'_, _>, _>().read_report() {
                     Err(UsbError::WouldBlock) => {}
                     Err(e) => {
                         core::panic!("Failed to read keyboard report: {:?}", e)
                     }
-                    Ok(_leds) => {}
+                    Ok(leds) => chew.host_leds(leds),
+                }
+
+                // Smooth scrolling if the host has set the wheel resolution multiplier
+                chew.smooth_scroll(
+                    rusty_chew
+                        .device::<SmoothMouse<'_, _>, _>()
+                        .read_multiplier(),
+                );
+
+                // VIA configuration, each packet is answered at once
+                if let Ok(mut packet) = rusty_chew.device::<ViaRaw<'_, _>, _>().read_report() {
+                    if chew.via(&mut packet, ticks) {
+                        rom_data::reset_to_usb_boot(0, 0);
+                    }
+                    rusty_chew
+                        .device::<ViaRaw<'_, _>, _>()
+                        .write_report(&packet)
+                        .ok();
+                }
+            }
+
+            // Serial shell, with or without the feature serial --
+            let mut received = [0; 16];
+            if let Ok(length) = serial_port.read(&mut received) {
+                for byte in received[..length].iter() {
+                    if !steno_on {
+                        serial_write_raw(&mut serial, echo(byte));
+                    }
+
+                    match shell.input(*byte) {
+                        Some(Ok(Command::Reset)) => {
+                            watchdog.start(1_000.micros());
+                            loop {
+                                cortex_m::asm::nop();
+                            }
+                        }
+                        Some(Ok(command)) => {
+                            serial_write_raw(&mut serial, chew.command(command).as_bytes())
+                        }
+                        Some(Err(e)) => serial_write_raw(&mut serial, e.to_serial().as_bytes()),
+                        None => {}
+                    }
                 }
             }
         } else {
@@ -527,6 +840,10 @@ fn main() -> ! {
                 ticks = ticks.wrapping_add(1);
             }
 
+            if SERIAL_ON {
+                usb_dev.poll(&mut [&mut serial_port]);
+            }
+
             // Slave is in charge of starting a new "chew loop"
             // It sends its active indexes and the master proceeds to the logic only when
             // it's able to combine the two matrix sides.
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use crate::text::{Error, Key, Keymap};
+
+// The keycodes & limits come from the firmware sources, so they can't drift apart
+const KEYS_RS: &str = include_str!("../../../src/software/keys.rs");
+const OPTIONS_RS: &str = include_str!("../../../src/options.rs");
+
+/// What a KC variant holds (e.g. HomeRow holds 2 keys, Layout a number).
+#[derive(Clone, Debug)]
+enum Data {
+    Key,
+    Number,
+    Byte,          // e.g. a VIA macro
+    Value(String), // Variant of another enum (Media, Pad, Steno, Setting)
+}
+
+#[derive(Clone, Debug)]
+struct Variant {
+    value: u16,
+    data: Vec<Data>,
+}
+
+/// Variants of the enums of keys.rs (KC, Media, Pad, Steno & Setting).
+pub struct Codes {
+    enums: HashMap<String, HashMap<String, Variant>>,
+    pub nb_keys: usize,
+    max_layouts: usize,
+    max_combos: usize,
+    max_leader: usize,
+    max_size: usize,
+    pub chunk: usize,
+}
+
+impl Codes {
+    pub fn new() -> Result<Self, Error> {
+        let mut enums = HashMap::new();
+        for name in ["KC", "Media", "Pad", "Steno", "Setting"] {
+            enums.insert(name.to_string(), variants(KEYS_RS, name)?);
+        }
+
+        Ok(Codes {
+            enums,
+            nb_keys: option(OPTIONS_RS, "NB_KEYS")?,
+            max_layouts: option(OPTIONS_RS, "KEYMAP_MAX_LAYOUTS")?,
+            max_combos: option(OPTIONS_RS, "KEYMAP_MAX_COMBOS")?,
+            max_leader: option(OPTIONS_RS, "KEYMAP_MAX_LEADER")?,
+            max_size: option(OPTIONS_RS, "KEYMAP_MAX_SIZE")?,
+            chunk: option(OPTIONS_RS, "KEYMAP_CHUNK")?,
+        })
+    }
+
+    /// Binary keymap read by the firmware (see decode in src/software/keymap.rs).
+    pub fn encode(&self, keymap: &Keymap) -> Result<Vec<u8>, Error> {
+        for (what, count, max) in [
+            ("layouts", keymap.layouts.len(), self.max_layouts),
+            ("combos", keymap.combos.len(), self.max_combos),
+            ("leader combinations", keymap.leader.len(), self.max_leader),
+        ] {
+            if count > max {
+                return Err(Error::new(0, format!("{} {} ({} max)", count, what, max)));
+            }
+        }
+
+        let mut bytes = vec![
+            keymap.layouts.len() as u8,
+            self.nb_keys as u8,
+            keymap.combos.len() as u8,
+            keymap.leader.len() as u8,
+        ];
+
+        for key in keymap.layouts.iter().flatten() {
+            self.key(key, keymap.layouts.len(), &mut bytes)?;
+        }
+        for (combo, new_key) in keymap.combos.iter() {
+            for key in combo.iter().chain([new_key]) {
+                self.key(key, keymap.layouts.len(), &mut bytes)?;
+            }
+        }
+        for (combination, new_key) in keymap.leader.iter() {
+            for key in combination.iter() {
+                self.key(key, keymap.layouts.len(), &mut bytes)?;
+            }
+            for _ in combination.len()..3 {
+                bytes.extend(self.kc("None", 0)?.value.to_le_bytes());
+            }
+            self.key(new_key, keymap.layouts.len(), &mut bytes)?;
+        }
+
+        if bytes.len() > self.max_size {
+            return Err(Error::new(
+                0,
+                format!("{} bytes ({} max)", bytes.len(), self.max_size),
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// Rust expression of a key checked by encode, e.g. `KC::HomeRow(&KC::Alt, &KC::A)`
+    /// (used by the build.rs of the firmware to generate the layouts).
+    pub fn rust(&self, key: &Key) -> Result<String, Error> {
+        let variant = self.kc(&key.name, key.line)?;
+        if key.args.is_empty() {
+            return Ok(format!("KC::{}", key.name));
+        }
+
+        let args: Vec<String> = variant
+            .data
+            .iter()
+            .zip(key.args.iter())
+            .map(|(data, arg)| match data {
+                Data::Key => format!("&KC::{}", arg.name),
+                Data::Number | Data::Byte => arg.name.clone(),
+                Data::Value(name) => format!("{}::{}", name, arg.name),
+            })
+            .collect();
+        Ok(format!("KC::{}({})", key.name, args.join(", ")))
+    }
+
+    fn kc(&self, name: &str, line: usize) -> Result<&Variant, Error> {
+        self.enums["KC"]
+            .get(name)
+            .ok_or_else(|| Error::new(line, format!("unknown key '{}'", name)))
+    }
+
+    fn key(&self, key: &Key, nb_layouts: usize, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        let variant = self.kc(&key.name, key.line)?;
+        if key.args.len() != variant.data.len() {
+            return Err(Error::new(
+                key.line,
+                format!("'{}' takes {} value(s)", key.name, variant.data.len()),
+            ));
+        }
+
+        bytes.extend(variant.value.to_le_bytes());
+        for (data, arg) in variant.data.iter().zip(key.args.iter()) {
+            let value = match data {
+                Data::Key => match self.kc(&arg.name, arg.line)? {
+                    v if v.data.is_empty() && arg.args.is_empty() => v.value,
+                    _ => {
+                        return Err(Error::new(
+                            arg.line,
+                            format!("'{}' can't be held by '{}'", arg.name, key.name),
+                        ))
+                    }
+                },
+                Data::Number => match arg.name.parse::<u16>() {
+                    Ok(n) if (n as usize) < nb_layouts => n,
+                    Ok(n) => {
+                        return Err(Error::new(
+                            arg.line,
+                            format!("layout {} doesn't exist ({} layouts)", n, nb_layouts),
+                        ))
+                    }
+                    Err(_) => {
+                        return Err(Error::new(
+                            arg.line,
+                            format!("'{}' is not a number", arg.name),
+                        ))
+                    }
+                },
+                Data::Byte => match arg.name.parse::<u8>() {
+                    Ok(n) => n as u16,
+                    Err(_) => {
+                        return Err(Error::new(
+                            arg.line,
+                            format!("'{}' is not a number (0-255)", arg.name),
+                        ))
+                    }
+                },
+                Data::Value(name) => match self.enums[name].get(&arg.name) {
+                    Some(v) => v.value,
+                    None => {
+                        return Err(Error::new(
+                            arg.line,
+                            format!("unknown {} '{}'", name, arg.name),
+                        ))
+                    }
+                },
+            };
+            bytes.extend(value.to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Crc-32 (IEEE), the same as crc32 in src/software/storage.rs.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// `pub const NAME: usize = 16 * 1024;` (products only)
+fn option(source: &str, name: &str) -> Result<usize, Error> {
+    let prefix = format!("pub const {}:", name);
+    source
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(&prefix))
+        .and_then(|line| line.split_once('='))
+        .and_then(|(_, value)| {
+            value
+                .split(';')
+                .next()?
+                .split('*')
+                .map(|n| n.trim().replace('_', "").parse::<usize>().ok())
+                .product()
+        })
+        .ok_or_else(|| Error::new(0, format!("{} not found in options.rs", name)))
+}
+
+/// Variants of `pub enum name {...}`, the values follow the Rust rules
+/// (explicit discriminant or the previous one + 1).
+fn variants(source: &str, name: &str) -> Result<HashMap<String, Variant>, Error> {
+    let start = format!("pub enum {} {{", name);
+    let not_found = || Error::new(0, format!("enum {} not found in keys.rs", name));
+
+    let body = source.split_once(&start).ok_or_else(not_found)?.1;
+    let body = body.split("\n}").next().ok_or_else(not_found)?;
+    let body: String = body
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut variants = HashMap::new();
+    let mut next = 0;
+
+    for item in split_items(&body) {
+        let (head, value) = match item.split_once('=') {
+            Some((head, value)) => (head.trim(), Some(value.trim())),
+            None => (item.trim(), None),
+        };
+
+        let (variant, data) = match head.split_once('(') {
+            Some((variant, data)) => (variant.trim(), data.trim_end_matches(')').trim()),
+            None => (head, ""),
+        };
+
+        let value = match value {
+            Some(value) => match value.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => value.parse::<u16>(),
+            }
+            .map_err(|_| Error::new(0, format!("{}::{} has a wrong value", name, variant)))?,
+            None => next,
+        };
+        next = value.wrapping_add(1);
+
+        let data = data
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| match d {
+                "&'static KC" => Data::Key,
+                "usize" => Data::Number,
+                "u8" => Data::Byte,
+                other => Data::Value(other.to_string()),
+            })
+            .collect();
+
+        variants.insert(variant.to_string(), Variant { value, data });
+    }
+
+    Ok(variants)
+}
+
+/// Items separated by commas outside the parentheses.
+fn split_items(body: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut depth = 0;
+
+    for c in body.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(std::mem::take(&mut item));
+                continue;
+            }
+            _ => {}
+        }
+        item.push(c);
+    }
+    items.push(item);
+
+    items
+        .into_iter()
+        .map(|i| i.trim().to_string())
+        .filter(|i| !i.is_empty())
+        .collect()
+}
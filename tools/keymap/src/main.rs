@@ -0,0 +1,79 @@
+use std::{env, fs, process::ExitCode};
+
+mod codes;
+mod rust;
+mod serial;
+mod text;
+
+use codes::Codes;
+use serial::Port;
+
+const USAGE: &str = "\
+Compile a text keymap for the Chew and upload it on its serial port
+
+Usage:
+    keymap check <keymap>
+    keymap compile <keymap> <output>    binary keymap, as stored in flash
+    keymap rust <keymap>                tables generated in layouts.rs by build.rs
+    keymap upload <keymap> <port>       e.g. /dev/ttyACM0
+    keymap default <port>               back to layouts.rs
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["check", keymap] => compile(keymap).map(|(bytes, _)| {
+            println!("{}: ok ({} bytes)", keymap, bytes.len());
+        }),
+        ["compile", keymap, output] => compile(keymap).and_then(|(bytes, _)| {
+            fs::write(output, bytes).map_err(|e| format!("{}: {}", output, e))
+        }),
+        ["rust", keymap] => parse(keymap).and_then(|(codes, parsed)| {
+            let tables =
+                rust::tables(&codes, &parsed, keymap).map_err(|e| format!("{}: {}", keymap, e))?;
+            print!("{}", tables);
+            Ok(())
+        }),
+        ["upload", keymap, port] => compile(keymap).and_then(|(bytes, chunk)| {
+            let mut port = Port::open(port)?;
+            serial::upload(&mut port, &bytes, chunk)
+        }),
+        ["default", port] => Port::open(port).and_then(|mut port| {
+            let answer = port.command("keymap default", "keymap: layouts.rs")?;
+            println!("{}", answer);
+            Ok(())
+        }),
+        _ => {
+            eprint!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse(path: &str) -> Result<(Codes, text::Keymap), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let codes = Codes::new().map_err(|e| e.to_string())?;
+
+    let keymap = text::parse(&source, codes.nb_keys).map_err(|e| format!("{}: {}", path, e))?;
+    Ok((codes, keymap))
+}
+
+/// Binary keymap & the number of bytes per upload line.
+fn compile(path: &str) -> Result<(Vec<u8>, usize), String> {
+    let (codes, keymap) = parse(path)?;
+    let bytes = codes
+        .encode(&keymap)
+        .map_err(|e| format!("{}: {}", path, e))?;
+
+    Ok((bytes, codes.chunk))
+}
@@ -0,0 +1,97 @@
+use std::fmt::Write;
+
+use crate::codes::Codes;
+use crate::text::{Error, Keymap};
+
+/// LAYOUTS, COMBOS & LEADER_KEY_COMBINATIONS of src/layouts.rs, written by the build.rs of the
+/// firmware. The keymap is checked as for an upload (keys, values, layouts & limits).
+pub fn tables(codes: &Codes, keymap: &Keymap, source: &str) -> Result<String, Error> {
+    codes.encode(keymap)?;
+
+    let mut out = String::new();
+
+    // Layouts, one line per line of the keymap file
+    writeln!(out, "\n#[rustfmt::skip]").ok();
+    writeln!(
+        out,
+        "pub const LAYOUTS: [[KC; NB_KEYS]; {}] = [",
+        keymap.layouts.len()
+    )
+    .ok();
+    for layout in keymap.layouts.iter() {
+        out.push_str("    [");
+        let mut line = 0;
+        for key in layout.iter() {
+            if key.line != line {
+                out.push_str("\n       ");
+                line = key.line;
+            }
+            write!(out, " {},", codes.rust(key)?).ok();
+        }
+        out.push_str("\n    ],\n");
+    }
+    out.push_str("];\n");
+
+    writeln!(out, "\n#[rustfmt::skip]").ok();
+    writeln!(
+        out,
+        "pub const COMBOS: [([KC; 2], KC); {}] = [",
+        keymap.combos.len()
+    )
+    .ok();
+    for ([a, b], new_key) in keymap.combos.iter() {
+        writeln!(
+            out,
+            "    ([{}, {}], {}),",
+            codes.rust(a)?,
+            codes.rust(b)?,
+            codes.rust(new_key)?
+        )
+        .ok();
+    }
+    out.push_str("];\n");
+
+    writeln!(out, "\n#[rustfmt::skip]").ok();
+    writeln!(
+        out,
+        "pub const LEADER_KEY_COMBINATIONS: [([KC; 3], KC); {}] = [",
+        keymap.leader.len()
+    )
+    .ok();
+    for (combination, new_key) in keymap.leader.iter() {
+        let mut names = combination
+            .iter()
+            .map(|key| codes.rust(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        names.resize(3, "KC::None".to_string());
+
+        writeln!(
+            out,
+            "    ([{}], {}),",
+            names.join(", "),
+            codes.rust(new_key)?
+        )
+        .ok();
+    }
+    out.push_str("];\n");
+
+    // KC & NB_KEYS come from layouts.rs, the other enums are imported when they are used
+    let enums: Vec<&str> = ["Media", "Pad", "Setting", "Steno"]
+        .into_iter()
+        .filter(|name| out.contains(&format!("({}::", name)))
+        .collect();
+
+    let mut header = format!(
+        "// Generated by build.rs from {}, edit it instead.\n",
+        source
+    );
+    if !enums.is_empty() {
+        writeln!(
+            header,
+            "use crate::software::keys::{{{}}};",
+            enums.join(", ")
+        )
+        .ok();
+    }
+    Ok(header + &out)
+}
@@ -0,0 +1,122 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::codes::crc32;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serial port of the keyboard (CDC), the shell answers each command line.
+pub struct Port {
+    file: File,
+    pending: String,
+}
+
+impl Port {
+    pub fn open(path: &str) -> Result<Self, String> {
+        // Raw mode without echo from the host, a read gives up after 0.1 s without data
+        let flag = if cfg!(target_os = "macos") {
+            "-f"
+        } else {
+            "-F"
+        };
+        match Command::new("stty")
+            .args([flag, path, "raw", "-echo", "min", "0", "time", "1"])
+            .status()
+        {
+            Ok(status) if status.success() => {}
+            _ => return Err(format!("{}: can't be set in raw mode with stty", path)),
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut port = Port {
+            file,
+            pending: String::new(),
+        };
+
+        // Drop a line typed before (an empty line has no answer)
+        port.write("")?;
+        port.read_for(Duration::from_millis(200))?;
+        port.pending.clear();
+        Ok(port)
+    }
+
+    /// Send a command line and wait for the answer starting with 'expected' (the echo of the
+    /// line is skipped). An error of the shell is given back as it is.
+    pub fn command(&mut self, line: &str, expected: &str) -> Result<String, String> {
+        self.write(line)?;
+        let start = Instant::now();
+
+        loop {
+            while let Some(end) = self.pending.find('\n') {
+                let answer: String = self.pending.drain(..=end).collect();
+                let answer = answer.trim();
+
+                if answer.starts_with("-- Error") {
+                    return Err(answer.to_string());
+                }
+                if answer.starts_with(expected) {
+                    return Ok(answer.to_string());
+                }
+            }
+
+            if start.elapsed() > TIMEOUT {
+                return Err(format!("no answer to '{}'", line));
+            }
+            self.read_for(Duration::ZERO)?;
+        }
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), String> {
+        self.file
+            .write_all(format!("{}\r", line).as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Add the received bytes to the pending ones (one read at least).
+    fn read_for(&mut self, duration: Duration) -> Result<(), String> {
+        let start = Instant::now();
+        let mut buffer = [0; 256];
+
+        loop {
+            let length = self.file.read(&mut buffer).map_err(|e| e.to_string())?;
+            self.pending
+                .push_str(&String::from_utf8_lossy(&buffer[..length]));
+
+            if start.elapsed() >= duration {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Keymap sent in hex by parts, then checked by the keyboard with its crc before being saved.
+pub fn upload(port: &mut Port, bytes: &[u8], chunk: usize) -> Result<(), String> {
+    port.command(
+        &format!("keymap begin {}", bytes.len()),
+        &format!("keymap: 0/{}", bytes.len()),
+    )?;
+
+    let mut sent = 0;
+    for part in bytes.chunks(chunk) {
+        sent += part.len();
+        let hex: String = part.iter().map(|b| format!("{:02x}", b)).collect();
+
+        port.command(
+            &format!("keymap data {}", hex),
+            &format!("keymap: {}", sent),
+        )?;
+        eprint!("\r{}/{} bytes", sent, bytes.len());
+    }
+    eprintln!();
+
+    let answer = port.command(&format!("keymap end {:08x}", crc32(bytes)), "keymap: saved")?;
+    println!("{}", answer);
+    Ok(())
+}
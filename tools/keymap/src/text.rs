@@ -0,0 +1,298 @@
+use std::fmt;
+
+/// A key as written in the keymap file, e.g. `A`, `Layout(3)` or `HomeRow(Alt, A)`.
+/// The `KC::` prefix & the `&` of Rust are accepted, so keys can be copied from the sources.
+#[derive(Clone, Debug)]
+pub struct Key {
+    pub name: String,
+    pub args: Vec<Key>,
+    pub line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Keymap {
+    pub layouts: Vec<Vec<Key>>,
+    pub combos: Vec<([Key; 2], Key)>,
+    pub leader: Vec<(Vec<Key>, Key)>,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(line: usize, message: impl Into<String>) -> Self {
+        Error {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.message),
+            line => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+/// Keymap file
+///
+///     # Comment
+///     layout
+///         Q  C  O  P  V      J  M  D  Y  W        (keys row by row,
+///         ...                                      nb_keys per layout)
+///     combo   Layout(1) Layout(2) = Layout(5)     (2 keys)
+///     leader  F L = MacroGit                      (1 to 3 keys)
+pub fn parse(source: &str, nb_keys: usize) -> Result<Keymap, Error> {
+    let mut keymap = Keymap::default();
+    let mut layout_line = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        match word {
+            "" => {}
+            "layout" => {
+                check_layout(&keymap, layout_line, nb_keys)?;
+                keymap.layouts.push(keys(rest, number)?);
+                layout_line = number;
+            }
+            "combo" => {
+                let (combo, new_key) = assignment(rest, number)?;
+                match <[Key; 2]>::try_from(combo) {
+                    Ok(combo) => keymap.combos.push((combo, new_key)),
+                    Err(_) => return Err(Error::new(number, "a combo has 2 keys")),
+                }
+            }
+            "leader" => {
+                let (combination, new_key) = assignment(rest, number)?;
+                if !(1..=3).contains(&combination.len()) {
+                    return Err(Error::new(number, "a leader combination has 1 to 3 keys"));
+                }
+                keymap.leader.push((combination, new_key));
+            }
+            _ => match keymap.layouts.last_mut() {
+                Some(layout) => layout.extend(keys(line, number)?),
+                None => return Err(Error::new(number, "keys before the first 'layout'")),
+            },
+        }
+    }
+
+    check_layout(&keymap, layout_line, nb_keys)?;
+    if keymap.layouts.is_empty() {
+        return Err(Error::new(0, "no layout"));
+    }
+    Ok(keymap)
+}
+
+fn check_layout(keymap: &Keymap, line: usize, nb_keys: usize) -> Result<(), Error> {
+    match keymap.layouts.last() {
+        Some(layout) if layout.len() != nb_keys => Err(Error::new(
+            line,
+            format!(
+                "layout {} has {} keys instead of {}",
+                keymap.layouts.len() - 1,
+                layout.len(),
+                nb_keys
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// `keys = key`
+fn assignment(text: &str, line: usize) -> Result<(Vec<Key>, Key), Error> {
+    let Some((keys_text, key_text)) = text.split_once('=') else {
+        return Err(Error::new(line, "'=' is missing"));
+    };
+
+    let mut new_key = keys(key_text, line)?;
+    if new_key.len() != 1 {
+        return Err(Error::new(line, "one key is expected after '='"));
+    }
+    Ok((keys(keys_text, line)?, new_key.remove(0)))
+}
+
+/// Keys separated by spaces (or commas), spaces are allowed inside the parentheses.
+fn keys(text: &str, line: usize) -> Result<Vec<Key>, Error> {
+    split(text, line)?
+        .iter()
+        .map(|word| key(word, line))
+        .collect()
+}
+
+fn split(text: &str, line: usize) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(Error::new(line, "unexpected ')'")),
+            ')' => depth -= 1,
+            _ => {}
+        }
+
+        if depth == 0 && (c.is_whitespace() || c == ',') {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+        } else if !c.is_whitespace() {
+            word.push(c);
+        }
+    }
+
+    if depth != 0 {
+        return Err(Error::new(line, "')' is missing"));
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn key(word: &str, line: usize) -> Result<Key, Error> {
+    let (name, args) = match word.split_once('(') {
+        Some((name, args)) => match args.strip_suffix(')') {
+            Some(args) => (name, keys(args, line)?),
+            None => return Err(Error::new(line, format!("'{}' is not a key", word))),
+        },
+        None => (word, Vec::new()),
+    };
+
+    let name = name.trim_start_matches('&');
+    let name = name.rsplit("::").next().unwrap_or(name);
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::new(line, format!("'{}' is not a key", word)));
+    }
+
+    Ok(Key {
+        name: name.to_string(),
+        args,
+        line,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codes::Codes;
+
+    /// A keymap with one full layout (its keys on line 3), then the given lines.
+    fn source(nb_keys: usize, after: &str) -> String {
+        format!(
+            "# Test\nlayout\n{}\n{}",
+            vec!["A"; nb_keys].join(" "),
+            after
+        )
+    }
+
+    /// The error of the keymap, from the parser or from the keycodes.
+    fn error(source: &str) -> Error {
+        let codes = Codes::new().unwrap();
+        match parse(source, codes.nb_keys) {
+            Ok(keymap) => codes.encode(&keymap).unwrap_err(),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn keymap_is_read() {
+        let nb_keys = Codes::new().unwrap().nb_keys;
+        let keymap = parse(
+            &source(
+                nb_keys,
+                "combo A, B = Layout(0)\nleader KC::F L = &KC::MacroGit # Git",
+            ),
+            nb_keys,
+        )
+        .unwrap();
+
+        assert_eq!(keymap.layouts.len(), 1);
+        assert_eq!(keymap.layouts[0].len(), nb_keys);
+        assert_eq!(keymap.layouts[0][0].line, 3);
+
+        let ([a, b], layout) = &keymap.combos[0];
+        assert_eq!((a.name.as_str(), b.name.as_str()), ("A", "B"));
+        assert_eq!(
+            (layout.name.as_str(), layout.args[0].name.as_str()),
+            ("Layout", "0")
+        );
+        assert_eq!(layout.line, 4);
+
+        let (combination, git) = &keymap.leader[0];
+        assert_eq!(combination.len(), 2);
+        assert_eq!(git.name, "MacroGit");
+    }
+
+    #[test]
+    fn unknown_key() {
+        let nb_keys = Codes::new().unwrap().nb_keys;
+        let e = error(&source(nb_keys, "\n\nleader F = Foo"));
+        assert_eq!(e.to_string(), "line 6: unknown key 'Foo'");
+
+        let e = error(&source(nb_keys, "combo A B = Pad(Bt99)"));
+        assert_eq!(e.to_string(), "line 4: unknown Pad 'Bt99'");
+    }
+
+    #[test]
+    fn wrong_key_count() {
+        let nb_keys = Codes::new().unwrap().nb_keys;
+
+        // The error is on the 'layout' line of the wrong one
+        let short = format!("layout\n{}", vec!["B"; nb_keys - 1].join(" "));
+        let e = error(&source(nb_keys, &short));
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "line 4: layout 1 has {} keys instead of {}",
+                nb_keys - 1,
+                nb_keys
+            )
+        );
+
+        let e = error(&source(nb_keys, "combo A B C = D"));
+        assert_eq!(e.to_string(), "line 4: a combo has 2 keys");
+        let e = error(&source(nb_keys, "leader A B C D = E"));
+        assert_eq!(
+            e.to_string(),
+            "line 4: a leader combination has 1 to 3 keys"
+        );
+        let e = error(&source(nb_keys, "combo A B = C D"));
+        assert_eq!(e.to_string(), "line 4: one key is expected after '='");
+    }
+
+    #[test]
+    fn bad_layer_reference() {
+        let nb_keys = Codes::new().unwrap().nb_keys;
+        let e = error(&source(nb_keys, "combo A B = Layout(1)"));
+        assert_eq!(e.to_string(), "line 4: layout 1 doesn't exist (1 layouts)");
+
+        let e = error(&source(nb_keys, "\nleader A = LaySet(one)"));
+        assert_eq!(e.to_string(), "line 5: 'one' is not a number");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            error("A B").to_string(),
+            "line 1: keys before the first 'layout'"
+        );
+        assert_eq!(error("# Nothing").to_string(), "no layout");
+
+        let nb_keys = Codes::new().unwrap().nb_keys;
+        let e = error(&source(nb_keys, "combo A B = Layout(1"));
+        assert_eq!(e.to_string(), "line 4: ')' is missing");
+        let e = error(&source(nb_keys, "combo A B Layout(1)"));
+        assert_eq!(e.to_string(), "line 4: '=' is missing");
+    }
+}